@@ -0,0 +1,197 @@
+//! The Monkey calls a runtime error happened in.
+//!
+//! Little is recorded while the program runs: only the functions that made tail calls, since
+//! their frames are gone by the time an error happens. As an error makes its way out, each
+//! statement it leaves notes its line and each function call it leaves becomes a `Call`.
+
+use std::cell::RefCell;
+use std::fmt;
+
+use crate::eval_error::EvalError;
+use crate::object::{ErrorValue, Object};
+use crate::sync::Rc;
+
+/// The calls active when an error happened, innermost first, ending with the program.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Backtrace {
+    pub calls: Vec<Call>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Call {
+    /// The name of the `let` the function was defined in, `<anonymous>` if it had none, or
+    /// `<program>`.
+    pub function: String,
+    /// Line of the statement that failed or made the next call, 0 in code without lines like
+    /// the prelude and imported modules.
+    pub line: usize,
+}
+
+impl fmt::Display for Call {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            0 => write!(f, "at {}", self.function),
+            line => write!(f, "at {} (line {line})", self.function),
+        }
+    }
+}
+
+/// One `at ...` per line. Runs of the same call, as deep recursion leaves, are shown once.
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut calls = self.calls.iter().peekable();
+        let mut first = true;
+        while let Some(call) = calls.next() {
+            let mut repeated = 0;
+            while calls.next_if_eq(&call).is_some() {
+                repeated += 1;
+            }
+            if !first {
+                writeln!(f)?;
+            }
+            first = false;
+            write!(f, "{call}")?;
+            if repeated > 0 {
+                write!(f, "\n... repeated {repeated} more times")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Unwinding {
+    calls: Vec<Call>,
+    /// Line of the innermost failed statement in the call being left, 0 until there is one.
+    line: usize,
+}
+
+thread_local! {
+    static UNWINDING: RefCell<Unwinding> = RefCell::new(Unwinding::default());
+}
+
+/// Runs `f` with a fresh backtrace, and returns what an error out of it left behind.
+pub(crate) fn collect<T>(f: impl FnOnce() -> T) -> (T, Backtrace) {
+    let previous = UNWINDING.with(|u| u.replace(Unwinding::default()));
+    let result = f();
+    let mut unwinding = UNWINDING.with(|u| u.replace(previous));
+    if !unwinding.calls.is_empty() || unwinding.line != 0 {
+        unwinding.calls.push(Call {
+            function: "<program>".to_string(),
+            line: unwinding.line,
+        });
+    }
+    let backtrace = Backtrace {
+        calls: unwinding.calls,
+    };
+    (result, backtrace)
+}
+
+/// Notes that `error` is leaving the statement on `line`.
+pub(crate) fn statement_failed(line: usize, error: EvalError) -> EvalError {
+    if let EvalError::Propagated(_) = error {
+        return error;
+    }
+    UNWINDING.with(|u| {
+        let mut unwinding = u.borrow_mut();
+        if unwinding.line == 0 {
+            unwinding.line = line;
+        }
+    });
+    error
+}
+
+/// Starts unwinding a thrown `value`. A caught error that is thrown again keeps the calls it
+/// had already left.
+pub(crate) fn throw(value: Rc<Object>) -> EvalError {
+    if let Object::Error(error) = value.as_ref() {
+        UNWINDING.with(|u| u.borrow_mut().calls = error.trace.clone());
+    }
+    EvalError::Thrown(value)
+}
+
+/// Stops unwinding `error` and turns it into the value its `catch` binds.
+pub(crate) fn catch(error: EvalError) -> Rc<Object> {
+    let trace = UNWINDING.with(|u| u.take()).calls;
+    match error {
+        EvalError::Thrown(value) => match value.as_ref() {
+            Object::Error(error) if !trace.is_empty() => {
+                Object::Error(ErrorValue { trace, ..error.clone() }).into()
+            }
+            _ => value,
+        },
+        error => Object::Error(ErrorValue {
+            kind: error.kind().to_string(),
+            message: error.to_string(),
+            trace,
+        })
+        .into(),
+    }
+}
+
+/// Runs `f`, a `finally` block, without losing the calls of an error that may be unwinding
+/// through it. If `f` fails itself, its own error is the one that goes on.
+pub(crate) fn aside<T, E>(f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    let unwinding = UNWINDING.with(|u| u.take());
+    let result = f();
+    if result.is_ok() {
+        UNWINDING.with(|u| u.replace(unwinding));
+    }
+    result
+}
+
+/// The Monkey functions one call has run: the running one, and before it those that tail
+/// called the next one in turn.
+#[derive(Default)]
+pub(crate) struct TailCalls {
+    running: Option<Rc<Object>>,
+    /// Each function that tail called another, and the line it did it from.
+    callers: Vec<(Rc<Object>, usize)>,
+}
+
+impl TailCalls {
+    pub(crate) fn enter(&mut self, function: &Rc<Object>) {
+        self.running = Some(function.clone());
+    }
+
+    /// Notes that the running function tail called another from `line`. A loop of tail calls is
+    /// only kept once, so this stays as short as the source.
+    pub(crate) fn tail_call(&mut self, line: usize) {
+        let Some(running) = self.running.take() else {
+            return;
+        };
+        let repeated = self
+            .callers
+            .iter()
+            .position(|(caller, l)| Rc::ptr_eq(caller, &running) && *l == line);
+        match repeated {
+            Some(i) => self.callers.truncate(i + 1),
+            None => self.callers.push((running, line)),
+        }
+    }
+
+    /// Notes that `error` is leaving the call.
+    pub(crate) fn failed(&self, error: EvalError) -> EvalError {
+        UNWINDING.with(|u| {
+            let mut unwinding = u.borrow_mut();
+            if let Some(running) = &self.running {
+                let line = std::mem::take(&mut unwinding.line);
+                unwinding.calls.push(Call::new(running, line));
+            }
+            let callers = self.callers.iter().rev();
+            let callers = callers.map(|(caller, line)| Call::new(caller, *line));
+            unwinding.calls.extend(callers);
+        });
+        error
+    }
+}
+
+impl Call {
+    fn new(function: &Object, line: usize) -> Self {
+        let function = match function {
+            Object::Function(.., Some(name)) => name.clone(),
+            _ => "<anonymous>".to_string(),
+        };
+        Call { function, line }
+    }
+}
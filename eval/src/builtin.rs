@@ -1,26 +1,55 @@
-use std::{collections::HashMap, rc::Rc};
+use std::collections::HashMap;
 
-// pub type BuiltinFn = fn(&[Rc<Object>]) -> EvalResponse;
 use crate::{
     eval::EvalResponse,
     eval_error::EvalError,
-    object::{BuiltinFn, Object},
+    object::{BuiltinFn, ErrorValue, NativeFn, Object},
+    sync::Rc,
 };
 
+thread_local! {
+    /// The builtins are made once per thread, so a binding can be told to be one of them by
+    /// identity.
+    static BUILTINS: HashMap<String, Rc<Object>> = make_builtin_fns();
+}
+
 pub fn get_builtin_fns() -> HashMap<String, Rc<Object>> {
+    BUILTINS.with(Clone::clone)
+}
+
+/// Whether `value` is the builtin called `name` rather than something else bound to that name.
+pub(crate) fn is_builtin(name: &str, value: &Rc<Object>) -> bool {
+    BUILTINS.with(|builtins| builtins.get(name).is_some_and(|b| Rc::ptr_eq(b, value)))
+}
+
+fn make_builtin_fns() -> HashMap<String, Rc<Object>> {
     let builtins = vec![
-        build_builtin("len", builtin_len),
-        build_builtin("first", builtin_first),
-        build_builtin("last", builtin_last),
-        build_builtin("rest", builtin_rest),
-        build_builtin("push", builtin_push),
-        build_builtin("put", builtin_put),
+        build_builtin("len", Some(1), builtin_len),
+        build_builtin("first", Some(1), builtin_first),
+        build_builtin("last", Some(1), builtin_last),
+        build_builtin("rest", Some(1), builtin_rest),
+        build_builtin("push", Some(2), builtin_push),
+        build_builtin("put", None, builtin_put),
+        build_builtin("str", Some(1), builtin_str),
+        build_builtin("split", Some(2), builtin_split),
+        build_builtin("type", Some(1), builtin_type),
+        build_builtin("fail", Some(1), builtin_fail),
+        build_builtin("error", Some(1), builtin_error),
+        build_builtin("ok", Some(1), builtin_ok),
+        build_builtin("err", Some(1), builtin_err),
+        build_builtin("is_ok", Some(1), builtin_is_ok),
+        build_builtin("unwrap", Some(1), builtin_unwrap),
+        build_builtin("unwrap_err", Some(1), builtin_unwrap_err),
+        build_builtin("unwrap_or", Some(2), builtin_unwrap_or),
     ];
     builtins.into_iter().collect()
 }
 
-fn build_builtin(key: impl Into<String>, fnn: BuiltinFn) -> (String, Rc<Object>) {
-    (key.into(), Object::Builtin(fnn).into())
+fn build_builtin(key: &str, arity: Option<usize>, fnn: BuiltinFn) -> (String, Rc<Object>) {
+    (
+        key.to_string(),
+        Object::NativeFn(NativeFn::new(key, arity, fnn)).into(),
+    )
 }
 
 fn builtin_put(vals: &[Rc<Object>]) -> EvalResponse {
@@ -29,8 +58,79 @@ fn builtin_put(vals: &[Rc<Object>]) -> EvalResponse {
     }
     Ok(Object::Null.into())
 }
+
+fn builtin_str(vals: &[Rc<Object>]) -> EvalResponse {
+    Ok(Object::String(vals[0].to_string()).into())
+}
+
+fn builtin_split(vals: &[Rc<Object>]) -> EvalResponse {
+    match (vals[0].as_ref(), vals[1].as_ref()) {
+        (Object::String(s), Object::String(sep)) if !sep.is_empty() => Ok(Object::Array(
+            s.split(sep.as_str())
+                .map(|part| Rc::new(part.into()))
+                .collect(),
+        )
+        .into()),
+        _ => Err(generate_param_error(&["String", "non-empty String"], vals)),
+    }
+}
+
+fn builtin_type(vals: &[Rc<Object>]) -> EvalResponse {
+    Ok(Object::from(vals[0].type_name()).into())
+}
+
+fn builtin_fail(vals: &[Rc<Object>]) -> EvalResponse {
+    Err(EvalError::AssertionFailed(vals[0].to_string()))
+}
+
+fn builtin_error(vals: &[Rc<Object>]) -> EvalResponse {
+    match vals[0].as_ref() {
+        Object::String(message) => Ok(Object::Error(ErrorValue::new(message.as_str())).into()),
+        _ => Err(generate_param_error(&["String"], vals)),
+    }
+}
+
+fn builtin_ok(vals: &[Rc<Object>]) -> EvalResponse {
+    Ok(Object::Result(Ok(vals[0].clone())).into())
+}
+
+fn builtin_err(vals: &[Rc<Object>]) -> EvalResponse {
+    Ok(Object::Result(Err(vals[0].clone())).into())
+}
+
+fn get_result(val: &Rc<Object>) -> Result<&Result<Rc<Object>, Rc<Object>>, EvalError> {
+    match val.as_ref() {
+        Object::Result(result) => Ok(result),
+        o => Err(EvalError::InvalidObjectType("Result".into(), o.to_string())),
+    }
+}
+
+fn builtin_is_ok(vals: &[Rc<Object>]) -> EvalResponse {
+    Ok(Object::Bool(get_result(&vals[0])?.is_ok()).into())
+}
+
+fn builtin_unwrap(vals: &[Rc<Object>]) -> EvalResponse {
+    match get_result(&vals[0])? {
+        Ok(value) => Ok(value.clone()),
+        Err(_) => Err(EvalError::UnwrapFailed(vals[0].to_string())),
+    }
+}
+
+fn builtin_unwrap_err(vals: &[Rc<Object>]) -> EvalResponse {
+    match get_result(&vals[0])? {
+        Ok(_) => Err(EvalError::UnwrapFailed(vals[0].to_string())),
+        Err(error) => Ok(error.clone()),
+    }
+}
+
+fn builtin_unwrap_or(vals: &[Rc<Object>]) -> EvalResponse {
+    match get_result(&vals[0])? {
+        Ok(value) => Ok(value.clone()),
+        Err(_) => Ok(vals[1].clone()),
+    }
+}
+
 fn builtin_rest(vals: &[Rc<Object>]) -> EvalResponse {
-    validate_param_count(1, vals.len())?;
     let slice = get_array(vals[0].clone())?
         .get(1..) 
         .ok_or(EvalError::IndexOutOfBounds { index: 0, max: 0 })?
@@ -39,7 +139,6 @@ fn builtin_rest(vals: &[Rc<Object>]) -> EvalResponse {
 }
 
 fn builtin_push(vals: &[Rc<Object>]) -> EvalResponse {
-    validate_param_count(2, vals.len())?;
     let mut slice = get_array(vals[0].clone())?;
     slice.push(vals[1].clone());
     Ok(Object::Array(slice).into())
@@ -47,7 +146,6 @@ fn builtin_push(vals: &[Rc<Object>]) -> EvalResponse {
 
 
 fn builtin_len(vals: &[Rc<Object>]) -> EvalResponse {
-    validate_param_count(1, vals.len())?;
     let len = match vals[0].as_ref() {
         Object::String(s) => s.len(),
         Object::Array(a) => a.len(),
@@ -62,7 +160,6 @@ fn builtin_len(vals: &[Rc<Object>]) -> EvalResponse {
 }
 
 fn builtin_first(vals: &[Rc<Object>]) -> EvalResponse {
-    validate_param_count(1, vals.len())?;
     Ok(get_array(vals[0].clone())?
         .first()
         .ok_or(EvalError::IndexOutOfBounds { index: 0, max: 0 })?
@@ -70,7 +167,6 @@ fn builtin_first(vals: &[Rc<Object>]) -> EvalResponse {
 }
 
 fn builtin_last(vals: &[Rc<Object>]) -> EvalResponse {
-    validate_param_count(1, vals.len())?;
     Ok(get_array(vals[0].clone())?
         .last()
         .ok_or(EvalError::IndexOutOfBounds { index: 0, max: 0 })?
@@ -87,12 +183,6 @@ fn generate_param_error(exp: &[&str], actual: &[Rc<Object>]) -> EvalError {
             .join(","),
     }
 }
-fn validate_param_count(expected: usize, actual: usize) -> Result<(), EvalError> {
-    if expected != actual {
-        return Err(EvalError::InvalidParamCount { expected, actual });
-    }
-    Ok(())
-}
 fn get_array(obj: Rc<Object>) -> Result<Vec<Rc<Object>>, EvalError> {
     if let Object::Array(a) = obj.as_ref() {
         Ok(a.clone())
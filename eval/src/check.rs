@@ -0,0 +1,438 @@
+//! Gradual type checking. Annotations (`let x: int = ...`, `fn(a: int) -> bool { ... }`) are
+//! checked against what the code does with the annotated values, and unannotated code gets
+//! whatever types follow from literals, operators, builtins and the functions it calls.
+//! Everything else is `Type::Any`, which fits anywhere, so in unannotated code only operations
+//! that can never succeed, like `"a" - 1`, are reported.
+//!
+//! A name only has a type if it is bound once in the whole program: a name bound by several
+//! `let`s or params holds whichever value was bound last, so it is `Any`. A name the program
+//! doesn't bind is taken to be a Rust builtin when checking source on its own; when checking
+//! for an environment, only if the environment really holds that builtin, since the host or an
+//! earlier run may have bound the name to anything.
+
+use std::collections::HashMap;
+
+use lexer::token::Token;
+use parser::ast::{
+    BlockStatement, Expression, Identifier, Param, Pattern, Program, Signature, Statement, Type,
+};
+
+use crate::builtin::is_builtin;
+use crate::environment::Env;
+use crate::lint::{self, Diagnostic, Lint};
+use crate::optimize::count_bindings;
+
+/// Parses `input` and type checks it. Parse errors are reported as diagnostics of their own, in
+/// which case the program isn't checked any further.
+pub fn check_source(input: &str) -> Vec<Diagnostic> {
+    match lint::parse(input) {
+        Ok((program, statement_lines)) => check(&program, &statement_lines),
+        Err(diagnostics) => diagnostics,
+    }
+}
+
+/// Type checks `program`. `statement_lines` is the parser's table of statement lines; without
+/// it every diagnostic is reported on line 0.
+pub fn check(program: &Program, statement_lines: &[usize]) -> Vec<Diagnostic> {
+    let mut checker = Checker::new(program, statement_lines);
+    checker.block(program);
+    checker.diagnostics
+}
+
+/// Like `check`, for running `program` in `env`: names the program doesn't bind get the type
+/// of what `env` holds.
+pub fn check_in(program: &Program, statement_lines: &[usize], env: &Env) -> Vec<Diagnostic> {
+    let mut checker = Checker::new(program, statement_lines);
+    checker.env = Some(env);
+    checker.block(program);
+    checker.diagnostics
+}
+
+/// Types of the names bound once in `program`, as far as the checker can tell, for showing in
+/// an editor. Names bound more than once are left out, since they could hold anything.
+pub fn infer(program: &Program) -> HashMap<Identifier, Type> {
+    let mut checker = Checker::new(program, &[]);
+    checker.block(program);
+    checker.types
+}
+
+struct Checker<'a> {
+    lines: &'a [usize],
+    statements: usize,
+    line: usize,
+    bindings: HashMap<Identifier, usize>,
+    /// Types of the names bound once, from their annotation or else their value.
+    types: HashMap<Identifier, Type>,
+    /// Types returned with `return` by each enclosing function, innermost last.
+    returns: Vec<Vec<Type>>,
+    /// Where the globals the program doesn't bind come from, if known.
+    env: Option<&'a Env>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Checker<'a> {
+    fn new(program: &Program, lines: &'a [usize]) -> Self {
+        Checker {
+            lines,
+            statements: 0,
+            line: 0,
+            bindings: count_bindings(program),
+            types: HashMap::new(),
+            returns: Vec::new(),
+            env: None,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    fn report(&mut self, lint: Lint) {
+        self.diagnostics.push(Diagnostic {
+            lint,
+            line: self.line,
+        });
+    }
+
+    fn expect(&mut self, expected: &Type, actual: &Type, context: impl FnOnce() -> String) {
+        if !fits(expected, actual) {
+            self.report(Lint::TypeMismatch {
+                context: context(),
+                expected: expected.clone(),
+                actual: actual.clone(),
+            });
+        }
+    }
+
+    fn bound_once(&self, name: &str) -> bool {
+        self.bindings.get(name) == Some(&1)
+    }
+
+    fn name_type(&self, name: &str) -> Type {
+        match self.bindings.get(name) {
+            Some(1) => self.types.get(name).cloned().unwrap_or(Type::Any),
+            Some(_) => Type::Any,
+            None => match self.env {
+                None => builtin_type(name),
+                Some(env) => match env.borrow().get(name) {
+                    Some(value) if is_builtin(name, &value) => builtin_type(name),
+                    _ => Type::Any,
+                },
+            },
+        }
+    }
+
+    /// Checks the statements of `block`, leaving `line` on the statement the block is part of,
+    /// and returns the type of the block's value.
+    fn block(&mut self, block: &BlockStatement) -> Type {
+        let line = self.line;
+        let mut ty = Type::Null;
+        for statement in &block.statements {
+            self.line = self.lines.get(self.statements).copied().unwrap_or(0);
+            self.statements += 1;
+            ty = self.statement(statement);
+        }
+        self.line = line;
+        ty
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Type {
+        match statement {
+            Statement::Let(name, ty, value) => {
+                self.bind(name, ty.as_ref(), value);
+                Type::Null
+            }
+            Statement::LetLocal(name, _, value) => {
+                self.bind(name, None, value);
+                Type::Null
+            }
+            Statement::Return(value) => {
+                let ty = self.expression(value);
+                if let Some(returns) = self.returns.last_mut() {
+                    returns.push(ty.clone());
+                }
+                ty
+            }
+            Statement::ExpressionStatement(e) => self.expression(e),
+            Statement::Import(..) => Type::Null,
+            // The `let` an `export`, `fn` or `const` wraps shares its line, so it isn't counted
+            // as a statement of its own.
+            Statement::Export(declared)
+            | Statement::FnDeclaration(declared)
+            | Statement::Const(declared) => self.statement(declared),
+            Statement::Throw(value) => {
+                self.expression(value);
+                Type::Any
+            }
+            Statement::LetPattern(pattern, value) => {
+                let actual = self.expression(value);
+                self.expect(&pattern_type(pattern), &actual, || pattern.to_string());
+                Type::Null
+            }
+        }
+    }
+
+    fn bind(&mut self, name: &Identifier, annotation: Option<&Type>, value: &Expression) {
+        // A function may call itself by name, so its signature is known before its body is
+        // checked.
+        if let Expression::FnExpression(params, _, signature) = value {
+            if self.bound_once(name) {
+                let ty = signature_type(params.len(), signature.as_ref(), Type::Any);
+                self.types.insert(name.clone(), ty);
+            }
+        }
+        let actual = self.expression(value);
+        if let Some(annotation) = annotation {
+            self.expect(annotation, &actual, || name.clone());
+        }
+        if self.bound_once(name) {
+            self.types
+                .insert(name.clone(), annotation.cloned().unwrap_or(actual));
+        }
+    }
+
+    fn expression(&mut self, e: &Expression) -> Type {
+        match e {
+            Expression::IntLiteral(_) => Type::Int,
+            Expression::StringLiteral(_) => Type::String,
+            Expression::Bool(_) => Type::Bool,
+            Expression::Identifier(name) | Expression::Local(name, _, _) => self.name_type(name),
+            Expression::PrefixExpression(op, right) => {
+                let right = self.expression(right);
+                match (op, right) {
+                    (Token::Bang, _) => Type::Bool,
+                    // Negating anything but an int gives null rather than failing.
+                    (Token::Dash, Type::Int) => Type::Int,
+                    (Token::Dash, Type::Any) => Type::Any,
+                    (Token::Dash, _) => Type::Null,
+                    _ => Type::Any,
+                }
+            }
+            Expression::InfixExpression(op, left, right) => {
+                let left = self.expression(left);
+                let right = self.expression(right);
+                self.infix(op, left, right)
+            }
+            Expression::IfExpression(cond, if_block, else_block) => {
+                self.expression(cond);
+                let then = self.block(if_block);
+                let otherwise = match else_block {
+                    Some(else_block) => self.block(else_block),
+                    None => Type::Null,
+                };
+                join(then, otherwise)
+            }
+            Expression::FnExpression(params, body, signature) => {
+                self.function(params, body, signature.as_ref())
+            }
+            Expression::CallExpression(callee, args) => {
+                let function = self.expression(callee);
+                // Spread and keyword arguments don't line up with the params by position.
+                let positional = args
+                    .iter()
+                    .all(|arg| !matches!(arg, Expression::Spread(_) | Expression::KeywordArg(..)));
+                let args: Vec<Type> = args.iter().map(|arg| self.expression(arg)).collect();
+                let (Type::Fn(params, ret), true) = (function, positional) else {
+                    return Type::Any;
+                };
+                for (i, (param, arg)) in params.iter().zip(&args).enumerate() {
+                    self.expect(param, arg, || format!("argument {} of {callee}", i + 1));
+                }
+                *ret
+            }
+            Expression::Spread(value) => {
+                let ty = self.expression(value);
+                self.expect(&Type::Array, &ty, || format!("spread {value}"));
+                Type::Any
+            }
+            Expression::KeywordArg(_, value) => self.expression(value),
+            Expression::Arrary(values) => {
+                for value in values {
+                    self.expression(value);
+                }
+                Type::Array
+            }
+            Expression::Map(pairs) => {
+                for (key, value) in pairs {
+                    self.expression(key);
+                    self.expression(value);
+                }
+                Type::Hash
+            }
+            Expression::IndexExpression(left, index) => {
+                let left = self.expression(left);
+                let index = self.expression(index);
+                if left == Type::Array {
+                    self.expect(&Type::Int, &index, || "array index".to_string());
+                }
+                Type::Any
+            }
+            Expression::TryExpression(body, catch, finally) => {
+                let mut ty = self.block(body);
+                if let Some((_, handler)) = catch {
+                    let handled = self.block(handler);
+                    ty = join(ty, handled);
+                }
+                if let Some(finally) = finally {
+                    self.block(finally);
+                }
+                ty
+            }
+            Expression::Propagate(value) => {
+                self.expression(value);
+                Type::Any
+            }
+            Expression::Match(value, arms) => {
+                self.expression(value);
+                let types: Vec<Type> = arms
+                    .iter()
+                    .map(|arm| {
+                        if let Some(guard) = &arm.guard {
+                            self.expression(guard);
+                        }
+                        self.expression(&arm.value)
+                    })
+                    .collect();
+                types.into_iter().reduce(join).unwrap_or(Type::Any)
+            }
+        }
+    }
+
+    fn function(
+        &mut self,
+        params: &[Param],
+        body: &BlockStatement,
+        signature: Option<&Signature>,
+    ) -> Type {
+        let Type::Fn(param_types, _) = signature_type(params.len(), signature, Type::Any) else {
+            unreachable!("signature_type always gives a function type");
+        };
+        for (param, ty) in params.iter().zip(&param_types) {
+            if let Some(default) = &param.default {
+                let actual = self.expression(default);
+                self.expect(ty, &actual, || format!("default of {}", param.name));
+            }
+            let ty = match ty {
+                Type::Any if param.rest => &Type::Array,
+                ty => ty,
+            };
+            if let Some(pattern) = &param.pattern {
+                self.expect(&pattern_type(pattern), ty, || pattern.to_string());
+            } else if self.bound_once(&param.name) {
+                self.types.insert(param.name.clone(), ty.clone());
+            }
+        }
+
+        self.returns.push(Vec::new());
+        let last = self.block(body);
+        let returns = self.returns.pop().expect("pushed above");
+        let actual = returns.into_iter().fold(last, join);
+        let ret = match signature.and_then(|signature| signature.ret.as_ref()) {
+            Some(ret) => {
+                self.expect(ret, &actual, || "return value".to_string());
+                ret.clone()
+            }
+            None => actual,
+        };
+        Type::Fn(param_types, Box::new(ret))
+    }
+
+    /// The type of `left op right` when it succeeds, reporting operands it never accepts.
+    fn infix(&mut self, op: &Token, left: Type, right: Type) -> Type {
+        let (operands, result): (&[Type], Option<Type>) = match op {
+            Token::Plus => (&[Type::Int, Type::String], None),
+            Token::Dash | Token::Asterisk | Token::ForwardSlash => (&[Type::Int], Some(Type::Int)),
+            Token::LessThan
+            | Token::LessThanEqual
+            | Token::GreaterThan
+            | Token::GreaterThanEqual => (&[Type::Int], Some(Type::Bool)),
+            Token::Equal | Token::NotEqual => (
+                &[Type::Int, Type::String, Type::Bool, Type::Null],
+                Some(Type::Bool),
+            ),
+            _ => return Type::Any,
+        };
+        let known: Vec<&Type> = [&left, &right]
+            .into_iter()
+            .filter(|ty| **ty != Type::Any)
+            .collect();
+        let accepted =
+            known.iter().all(|ty| operands.contains(ty)) && (known.len() < 2 || left == right);
+        if !accepted {
+            self.report(Lint::InvalidOperands {
+                op: op.clone(),
+                left,
+                right,
+            });
+            return result.unwrap_or(Type::Any);
+        }
+        // `+` gives whatever it adds: two ints or two strings.
+        result.unwrap_or_else(|| known.first().map_or(Type::Any, |ty| (*ty).clone()))
+    }
+}
+
+/// The type of a function literal with `params` params as far as its annotations tell,
+/// with `ret` as the return type if it doesn't declare one.
+fn signature_type(params: usize, signature: Option<&Signature>, ret: Type) -> Type {
+    let param_types = (0..params)
+        .map(|i| {
+            signature
+                .and_then(|signature| signature.params.get(i).cloned().flatten())
+                .unwrap_or(Type::Any)
+        })
+        .collect();
+    let ret = signature
+        .and_then(|signature| signature.ret.clone())
+        .unwrap_or(ret);
+    Type::Fn(param_types, Box::new(ret))
+}
+
+/// The type of the values `pattern` can destructure.
+fn pattern_type(pattern: &Pattern) -> Type {
+    match pattern {
+        Pattern::Array(..) => Type::Array,
+        Pattern::Hash(_) => Type::Hash,
+        _ => Type::Any,
+    }
+}
+
+/// Whether a value of type `actual` can be used where `expected` is needed.
+fn fits(expected: &Type, actual: &Type) -> bool {
+    match (expected, actual) {
+        (Type::Any, _) | (_, Type::Any) => true,
+        (Type::Fn(expected_params, expected_ret), Type::Fn(actual_params, actual_ret)) => {
+            expected_params.len() == actual_params.len()
+                && expected_params
+                    .iter()
+                    .zip(actual_params)
+                    .all(|(e, a)| fits(e, a))
+                && fits(expected_ret, actual_ret)
+        }
+        (expected, actual) => expected == actual,
+    }
+}
+
+/// The type of a value that is either an `a` or a `b`.
+fn join(a: Type, b: Type) -> Type {
+    if a == b {
+        a
+    } else {
+        Type::Any
+    }
+}
+
+/// Types of the Rust builtins; anything else not bound in the program (the prelude, or globals
+/// the host defines) is `Any`.
+pub fn builtin_type(name: &str) -> Type {
+    let function = |params: Vec<Type>, ret| Type::Fn(params, Box::new(ret));
+    match name {
+        "len" => function(vec![Type::Any], Type::Int),
+        "first" | "last" => function(vec![Type::Array], Type::Any),
+        "rest" => function(vec![Type::Array], Type::Array),
+        "push" => function(vec![Type::Array, Type::Any], Type::Array),
+        "str" | "type" => function(vec![Type::Any], Type::String),
+        "split" => function(vec![Type::String, Type::String], Type::Array),
+        "error" => function(vec![Type::String], Type::Any),
+        "ok" | "err" | "unwrap" | "unwrap_err" => function(vec![Type::Any], Type::Any),
+        "is_ok" => function(vec![Type::Any], Type::Bool),
+        "unwrap_or" => function(vec![Type::Any, Type::Any], Type::Any),
+        _ => Type::Any,
+    }
+}
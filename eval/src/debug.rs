@@ -0,0 +1,278 @@
+//! Hooks for stepping through a program while it runs.
+//!
+//! A `Debugger` installed on an `Interpreter` is asked before every statement whether to stop
+//! there. When it does stop, it sees the call stack and every frame's variables, and says how
+//! to go on: to the next breakpoint, into the next statement, over calls or out of the current
+//! function. Without a debugger the hooks cost one thread-local lookup per statement.
+//!
+//! Statements without a line are run without stopping. The prelude and imported modules are
+//! `hide`n that way, so the debugger only stops in the program it was asked to run.
+
+use std::cell::RefCell;
+
+use parser::ast::visit_mut::walk_block_mut;
+use parser::ast::{BlockStatement, Identifier, Param, Program, VisitorMut};
+
+use crate::environment::Env;
+use crate::eval_error::EvalError;
+use crate::object::Object;
+use crate::resolver;
+use crate::sync::{MaybeSendSync, Rc};
+
+pub trait Debugger: MaybeSendSync {
+    /// Whether to stop before running a statement that starts on `line`.
+    fn breaks_at(&self, line: usize) -> bool;
+
+    /// Called when the program stops, before the statement on `pause.line` runs.
+    fn pause(&mut self, pause: &Pause) -> Step;
+
+    /// Whether to stop before the first statement, breakpoint or not.
+    fn stop_on_entry(&self) -> bool {
+        false
+    }
+}
+
+/// How to go on after a pause.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Step {
+    /// Run until the next breakpoint.
+    Continue,
+    /// Stop at the next statement, inside a call if that is where it is.
+    In,
+    /// Stop at the next statement in this function or a caller.
+    Over,
+    /// Stop at the next statement in a caller.
+    Out,
+    /// Abandon the program, which fails with `EvalError::Interrupted`.
+    Stop,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PauseReason {
+    Entry,
+    Breakpoint,
+    Step,
+}
+
+pub struct Pause<'a> {
+    pub reason: PauseReason,
+    /// Line of the statement about to run.
+    pub line: usize,
+    /// The call stack, with the program itself first and the current function last.
+    pub frames: &'a [Frame],
+}
+
+/// The program or a function call on the call stack.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// The name of the `let` the function was defined in, `<anonymous>` if it had none, or
+    /// `<program>`.
+    pub function: String,
+    /// Line of the statement running in this frame.
+    pub line: usize,
+    /// Environment of the running statement, once one has run.
+    pub env: Option<Env>,
+    /// Names of the frame's slots: its params, then its locals. A destructured param's slot has
+    /// no name, as only the names in its pattern are variables.
+    locals: Vec<Identifier>,
+}
+
+impl Frame {
+    fn new(function: String, locals: Vec<Identifier>) -> Self {
+        Frame {
+            function,
+            line: 0,
+            env: None,
+            locals,
+        }
+    }
+
+    /// The frame's params and the locals that have been assigned, then anything it bound by
+    /// name: `import`s in a function, globals in the program.
+    pub fn variables(&self) -> Vec<(String, Rc<Object>)> {
+        let Some(env) = &self.env else {
+            return Vec::new();
+        };
+        let env = env.borrow();
+        let mut variables: Vec<(String, Rc<Object>)> = self
+            .locals
+            .iter()
+            .enumerate()
+            .filter(|(_, name)| !name.is_empty())
+            .filter_map(|(slot, name)| Some((name.clone(), env.get_local(0, slot)?)))
+            .collect();
+        let mut bindings: Vec<(String, Rc<Object>)> = env
+            .bindings()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        bindings.sort_by(|(a, _), (b, _)| a.cmp(b));
+        variables.extend(bindings);
+        variables
+    }
+
+    /// The environments the frame's code can see: its own, then the one its function was
+    /// defined in, and so on out to the globals.
+    pub fn scopes(&self) -> Vec<Env> {
+        let mut scopes = Vec::new();
+        let mut env = self.env.clone();
+        while let Some(e) = env {
+            env = e.borrow().outer().cloned();
+            scopes.push(e);
+        }
+        scopes
+    }
+}
+
+struct Session {
+    debugger: Box<dyn Debugger>,
+    frames: Vec<Frame>,
+    stepping: Stepping,
+    /// Whether a statement has run yet.
+    started: bool,
+}
+
+enum Stepping {
+    Continue,
+    In,
+    /// Stop once the call stack is no deeper than this.
+    Over(usize),
+    /// Stop once the call stack is shallower than this.
+    Out(usize),
+}
+
+thread_local! {
+    static SESSION: RefCell<Option<Session>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` with `debugger` (if any) stepping through it, and hands the debugger back.
+pub(crate) fn with_debugger<T>(
+    debugger: Option<Box<dyn Debugger>>,
+    f: impl FnOnce() -> T,
+) -> (T, Option<Box<dyn Debugger>>) {
+    let session = debugger.map(|debugger| Session {
+        debugger,
+        frames: vec![Frame::new("<program>".to_string(), Vec::new())],
+        stepping: Stepping::Continue,
+        started: false,
+    });
+    let previous = SESSION.with(|s| s.replace(session));
+    let result = f();
+    let session = SESSION.with(|s| s.replace(previous));
+    (result, session.map(|session| session.debugger))
+}
+
+/// The frame of a function call on the debugger's call stack, popped when the call returns.
+#[derive(Default)]
+pub(crate) struct CallFrame {
+    pushed: bool,
+}
+
+impl CallFrame {
+    /// Puts a call to a function named `name` with `params` and `body` on the call stack. A
+    /// tail call replaces the frame of the call it ends, as it does on the Rust stack.
+    pub(crate) fn enter(
+        &mut self,
+        name: Option<&str>,
+        params: &[Param],
+        body: &BlockStatement,
+    ) {
+        SESSION.with(|s| {
+            let mut session = s.borrow_mut();
+            let Some(session) = session.as_mut() else {
+                return;
+            };
+            let name = name.unwrap_or("<anonymous>").to_string();
+            let frame = Frame::new(name, resolver::slot_names(params, body));
+            if self.pushed {
+                *session.frames.last_mut().expect("pushed before") = frame;
+            } else {
+                session.frames.push(frame);
+                self.pushed = true;
+            }
+        });
+    }
+}
+
+impl Drop for CallFrame {
+    fn drop(&mut self) {
+        if self.pushed {
+            SESSION.with(|s| {
+                if let Some(session) = s.borrow_mut().as_mut() {
+                    session.frames.pop();
+                }
+            });
+        }
+    }
+}
+
+/// Forgets the lines of `program`'s statements, so the debugger never stops in it.
+pub(crate) fn hide(program: &mut Program) {
+    Hide.visit_block_mut(program);
+}
+
+struct Hide;
+
+impl VisitorMut for Hide {
+    fn visit_block_mut(&mut self, block: &mut BlockStatement) {
+        block.lines.clear();
+        walk_block_mut(self, block);
+    }
+}
+
+/// Called before each statement, with the line it starts on and the environment it runs in.
+pub(crate) fn statement(line: usize, env: &Env) -> Result<(), EvalError> {
+    if line == 0 {
+        return Ok(());
+    }
+    // The session is taken out while the debugger looks around, so whatever it does (like
+    // evaluating an expression) runs undisturbed.
+    let Some(mut session) = SESSION.with(|s| s.borrow_mut().take()) else {
+        return Ok(());
+    };
+    let depth = session.frames.len();
+    let frame = session
+        .frames
+        .last_mut()
+        .expect("the program's frame is never popped");
+    frame.line = line;
+    frame.env = Some(env.clone());
+
+    let reason = if !session.started && session.debugger.stop_on_entry() {
+        Some(PauseReason::Entry)
+    } else {
+        match session.stepping {
+            Stepping::In => Some(PauseReason::Step),
+            Stepping::Over(max) if depth <= max => Some(PauseReason::Step),
+            Stepping::Out(max) if depth < max => Some(PauseReason::Step),
+            _ => None,
+        }
+    };
+    let reason = reason.or_else(|| {
+        session
+            .debugger
+            .breaks_at(line)
+            .then_some(PauseReason::Breakpoint)
+    });
+    session.started = true;
+
+    let mut result = Ok(());
+    if let Some(reason) = reason {
+        let step = session.debugger.pause(&Pause {
+            reason,
+            line,
+            frames: &session.frames,
+        });
+        session.stepping = match step {
+            Step::Continue => Stepping::Continue,
+            Step::In => Stepping::In,
+            Step::Over => Stepping::Over(depth),
+            Step::Out => Stepping::Out(depth),
+            Step::Stop => {
+                result = Err(EvalError::Interrupted);
+                Stepping::Continue
+            }
+        };
+    }
+    SESSION.with(|s| *s.borrow_mut() = Some(session));
+    result
+}
@@ -1,50 +1,173 @@
 
 
 use crate::builtin::get_builtin_fns;
+use crate::eval_error::EvalError;
 use crate::object::Object;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use crate::prelude;
+use crate::sync::{RefCell, Rc};
+use std::collections::{HashMap, HashSet};
 use std::fmt;
-use std::rc::Rc;
 
 pub type Env = Rc<RefCell<Environment>>;
 
 #[derive(Debug, PartialEq, Default)]
 pub struct Environment {
     store: HashMap<String, Rc<Object>>,
+    /// A function call's params and locals, by the slot the resolver gave them. `None` until
+    /// the `let` for that slot has run.
+    slots: Vec<Option<Rc<Object>>>,
+    /// Names that can't be bound again here: those bound by `const`, and in an environment from
+    /// `new_with_builtin`, the Rust builtins.
+    constants: HashSet<String>,
+    /// Slots bound by `const`.
+    constant_slots: HashSet<usize>,
     outer: Option<Rc<RefCell<Environment>>>,
 }
 
 impl Environment {
-    pub fn get(&self, key: impl Into<String>) -> Option<Rc<Object>> {
-        let key = key.into();
-        match self.store.get(&key) {
+    pub fn get(&self, key: &str) -> Option<Rc<Object>> {
+        match self.store.get(key) {
             Some(s) => Some(s.clone()),
-            None => self.outer.clone()?.borrow().get(key),
+            None => self.outer.as_ref()?.borrow().get(key),
         }
     }
 
+    /// Binds `key` whether or not it's a constant, as the host may.
     pub fn set(&mut self, key: impl Into<String>, value: Rc<Object>) {
         self.store.insert(key.into(), value);
     }
 
+    /// Binds `key` for a script, as a constant if `constant` is set. Binding a constant again is
+    /// an error.
+    pub fn declare(
+        &mut self,
+        key: impl Into<String>,
+        value: Rc<Object>,
+        constant: bool,
+    ) -> Result<(), EvalError> {
+        let key = key.into();
+        if self.constants.contains(&key) {
+            return Err(EvalError::AssignToConstant(key));
+        }
+        if constant {
+            self.constants.insert(key.clone());
+        }
+        self.store.insert(key, value);
+        Ok(())
+    }
+
+    /// Whether `key` is a constant in this scope, leaving out the enclosing ones.
+    pub fn is_constant(&self, key: &str) -> bool {
+        self.constants.contains(key)
+    }
+
+    /// The value in `slot` of the function frame `depth` scopes out from this one.
+    pub fn get_local(&self, depth: usize, slot: usize) -> Option<Rc<Object>> {
+        match depth {
+            0 => self.slots.get(slot)?.clone(),
+            _ => self.outer.as_ref()?.borrow().get_local(depth - 1, slot),
+        }
+    }
+
+    pub fn set_local(&mut self, slot: usize, value: Rc<Object>) {
+        if slot >= self.slots.len() {
+            self.slots.resize(slot + 1, None);
+        }
+        self.slots[slot] = Some(value);
+    }
+
+    /// Like `declare`, for the local `name` in `slot`.
+    pub fn declare_local(
+        &mut self,
+        name: &str,
+        slot: usize,
+        value: Rc<Object>,
+        constant: bool,
+    ) -> Result<(), EvalError> {
+        if self.constant_slots.contains(&slot) {
+            return Err(EvalError::AssignToConstant(name.to_string()));
+        }
+        if constant {
+            self.constant_slots.insert(slot);
+        }
+        self.set_local(slot, value);
+        Ok(())
+    }
+
+    pub fn outer(&self) -> Option<&Env> {
+        self.outer.as_ref()
+    }
+
+    /// Bindings made by name rather than in a slot, in no particular order.
+    pub fn bindings(&self) -> impl Iterator<Item = (&String, &Rc<Object>)> {
+        self.store.iter()
+    }
+
+    /// This frame's slots, `None` where the `let` hasn't run yet.
+    pub fn slots(&self) -> &[Option<Rc<Object>>] {
+        &self.slots
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Rc<Object>> {
+        self.store.values().chain(self.slots.iter().flatten())
+    }
+
+    /// Drops every binding and the link to the enclosing scope.
+    pub fn clear(&mut self) {
+        self.store.clear();
+        self.slots.clear();
+        self.constants.clear();
+        self.constant_slots.clear();
+        self.outer = None;
+    }
+
+    /// An environment holding the Rust builtins and the Monkey prelude. Scripts can shadow a
+    /// Rust builtin inside a function, but not bind it again in this scope. The prelude's names
+    /// stay ordinary bindings, as its own functions are closed over a separate environment and
+    /// keep working whatever a script binds in their place. Should the bundled
+    /// prelude fail to load, this holds only the Rust builtins; `try_new_with_builtin` reports
+    /// the error instead.
     pub fn new_with_builtin() -> Self {
+        Environment::try_new_with_builtin().unwrap_or_else(|_| Environment {
+            constants: get_builtin_fns().into_keys().collect(),
+            ..Environment::new_without_prelude()
+        })
+    }
+
+    pub fn try_new_with_builtin() -> Result<Self, EvalError> {
+        Ok(Environment {
+            store: prelude::load()?,
+            constants: get_builtin_fns().into_keys().collect(),
+            ..Default::default()
+        })
+    }
+
+    /// An environment holding only the Rust builtins, for embedders that want to control
+    /// exactly what scripts can see.
+    pub fn new_without_prelude() -> Self {
         Environment {
-            outer: None,
             store: get_builtin_fns(),
+            ..Default::default()
         }
     }
     pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn new_closed(env: Rc<RefCell<Environment>>) -> Self {
         Environment {
-            outer: None,
-            store: Default::default(),
+            outer: Some(env),
+            ..Default::default()
         }
     }
 
-    pub fn new_closed(env: Rc<RefCell<Environment>>) -> Self {
+    /// The frame for a call to a function closed over `env`, with `args` in the first slots:
+    /// `None` for a param the call left to its default.
+    pub fn new_frame(env: Rc<RefCell<Environment>>, args: Vec<Option<Rc<Object>>>) -> Self {
         Environment {
             outer: Some(env),
-            store: Default::default(),
+            slots: args,
+            ..Default::default()
         }
     }
 }
@@ -54,6 +177,11 @@ impl fmt::Display for Environment {
         for (k, v) in self.store.iter() {
             writeln!(f, "[{k} : {v}]")?;
         }
+        for (slot, v) in self.slots.iter().enumerate() {
+            if let Some(v) = v {
+                writeln!(f, "[#{slot} : {v}]")?;
+            }
+        }
         match self.outer.clone() {
             Some(outer) => {
                 writeln!(f, "<><><>OUTER<><><>")?;
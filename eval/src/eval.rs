@@ -1,31 +1,50 @@
-use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt::Display;
-use std::rc::Rc;
 
+use crate::backtrace::{self, TailCalls};
+use crate::debug::{self, CallFrame};
 use crate::environment::{Env, Environment};
 use crate::eval_error::EvalError;
-use crate::object::{HashKey, HashPair};
+use crate::gc;
+use crate::limits::{self, CallGuard, Limits};
+use crate::modules;
+use crate::resolver;
+use crate::sync::Rc;
+use crate::object::{HashKey, HashPair, NativeFn};
 use crate::{node::Node, object::Object};
 use lexer::token::Token;
-use parser::ast::{BlockStatement, Expression, Program, Statement};
+use parser::ast::{
+    BlockStatement, Expression, Identifier, MatchArm, Param, Pattern, Program, Statement,
+};
 
 pub type EvalResponse = Result<Rc<Object>, EvalError>;
 
+/// Resolves `node` (see `resolver`) and evaluates it in `env`.
 pub fn eval(node: impl Into<Node>, env: &Env) -> EvalResponse {
-    Ok(match &node.into() {
-        Node::BlockStatement(s) => eval_block(s, env)?,
-        Node::Program(p) => eval_program(p, env)?,
-        Node::Statement(s) => eval_statement(s, env)?,
-        Node::Expression(e) => eval_expression(e, env)?,
-        Node::Object(o) => o.clone(),
-    })
+    let result = match &resolver::resolve_node(node.into(), env)? {
+        Node::BlockStatement(s) => eval_block(s, env),
+        Node::Program(p) => eval_program(p, env),
+        Node::Statement(s) => eval_statement(s, env),
+        Node::Expression(e) => eval_expression(e, env),
+        Node::Object(o) => Ok(o.clone()),
+    };
+    match result {
+        // Like `return`, `?` outside any function ends the program, with the `err` as its value.
+        Err(EvalError::Propagated(err)) => Ok(err),
+        result => result,
+    }
+}
+
+/// Like `eval`, but stops with a dedicated `EvalError` as soon as any of `limits` is hit.
+pub fn eval_with_limits(node: impl Into<Node>, env: &Env, limits: Limits) -> EvalResponse {
+    limits::with_limits(limits, || eval(node, env))
 }
 
 fn eval_block(block: &BlockStatement, env: &Env) -> EvalResponse {
+    hoist_functions(block, env)?;
     let mut result: Rc<Object> = Object::Null.into();
-    for st in &block.statements {
-        result = eval_statement(st, env)?;
+    for (i, st) in block.statements.iter().enumerate() {
+        result = eval_lined_statement(st, block.line(i), env)?;
         if let Object::Return(_) = result.as_ref() {
             return Ok(result);
         }
@@ -34,9 +53,10 @@ fn eval_block(block: &BlockStatement, env: &Env) -> EvalResponse {
 }
 
 fn eval_program(block: &Program, env: &Env) -> EvalResponse {
+    hoist_functions(block, env)?;
     let mut result: Rc<Object> = Object::Null.into();
-    for st in &block.statements {
-        result = eval_statement(st, env)?;
+    for (i, st) in block.statements.iter().enumerate() {
+        result = eval_lined_statement(st, block.line(i), env)?;
         if let Object::Return(r) = result.as_ref() {
             return Ok(r.clone());
         }
@@ -44,29 +64,124 @@ fn eval_program(block: &Program, env: &Env) -> EvalResponse {
     Ok(result)
 }
 
+/// Binds the functions `block` declares with `fn name() {}` before any of its statements run,
+/// so they can be called from anywhere in the block, and call each other. Each declaration
+/// binds its function again when it's reached.
+fn hoist_functions(block: &BlockStatement, env: &Env) -> Result<(), EvalError> {
+    for statement in &block.statements {
+        let declared = match statement {
+            Statement::Export(exported) => exported.as_ref(),
+            statement => statement,
+        };
+        if let Statement::FnDeclaration(_) = declared {
+            eval_statement(statement, env)?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs a statement of a block that starts on `line`, letting the debugger stop before it and
+/// the backtrace know where it failed.
+fn eval_lined_statement(statement: &Statement, line: usize, env: &Env) -> EvalResponse {
+    debug::statement(line, env)
+        .and_then(|()| eval_statement(statement, env))
+        .map_err(|e| backtrace::statement_failed(line, e))
+}
+
 fn eval_statement(statement: &Statement, env: &Env) -> EvalResponse {
+    limits::tick()?;
     match statement {
         Statement::ExpressionStatement(exp) => eval_expression(exp, env),
         Statement::Return(exp) => {
             let ex = eval_expression(exp, env)?;
             Ok(Object::Return(ex).into())
         }
-        Statement::Let(ident, exp) => {
-            let val = eval_expression(exp, env)?;
-            env.clone().borrow_mut().set(ident, val);
+        Statement::Let(..) | Statement::LetLocal(..) | Statement::LetPattern(..) => {
+            eval_binding(statement, env, false)
+        }
+        Statement::Const(declared) => eval_binding(declared, env, true),
+        Statement::Import(path, alias) => {
+            let module = modules::import(path)?;
+            let name = alias.clone().unwrap_or_else(|| modules::default_binding(path));
+            env.borrow_mut().declare(name, module, false)?;
             Ok(Object::Null.into())
         }
+        Statement::Export(statement) | Statement::FnDeclaration(statement) => {
+            eval_statement(statement, env)
+        }
+        Statement::Throw(exp) => Err(backtrace::throw(eval_expression(exp, env)?)),
+    }
+}
+
+/// Runs a `let`, making the names it binds constants if `constant` is set.
+fn eval_binding(statement: &Statement, env: &Env, constant: bool) -> EvalResponse {
+    match statement {
+        Statement::Let(ident, _, exp) => {
+            let val = eval_let_value(ident, exp, env)?;
+            env.borrow_mut().declare(ident, val, constant)?;
+        }
+        Statement::LetLocal(ident, slot, exp) => {
+            let val = eval_let_value(ident, exp, env)?;
+            env.borrow_mut()
+                .declare_local(ident, *slot, val, constant)?;
+        }
+        Statement::LetPattern(pattern, exp) => eval_let_pattern(pattern, exp, env, constant)?,
+        statement => return eval_statement(statement, env),
+    }
+    Ok(Object::Null.into())
+}
+
+/// Binds the names in `pattern` to the parts of the value of `exp`, which must have its shape.
+fn eval_let_pattern(
+    pattern: &Pattern,
+    exp: &Expression,
+    env: &Env,
+    constant: bool,
+) -> Result<(), EvalError> {
+    let value = eval_expression(exp, env)?;
+    destructure(pattern, &value, env, constant)
+}
+
+/// Binds the names in `pattern` to the parts of `value`, which must have its shape.
+fn destructure(
+    pattern: &Pattern,
+    value: &Rc<Object>,
+    env: &Env,
+    constant: bool,
+) -> Result<(), EvalError> {
+    let mut bindings = Vec::new();
+    if !match_pattern(pattern, value, &mut bindings) {
+        return Err(EvalError::ShapeMismatch(
+            pattern.to_string(),
+            value.to_string(),
+        ));
+    }
+    bind(bindings, env, constant)
+}
+
+/// The value of `let name = exp`. A function literal is named after the `let`, for backtraces.
+fn eval_let_value(name: &str, exp: &Expression, env: &Env) -> EvalResponse {
+    match exp {
+        Expression::FnExpression(idents, blk, _) => {
+            limits::tick()?;
+            Ok(Object::Function(idents.clone(), blk.clone(), Rc::clone(env), Some(name.into())).into())
+        }
+        exp => eval_expression(exp, env),
     }
 }
 
 pub fn eval_expression(exp: &Expression, env: &Env) -> EvalResponse {
-    return match exp {
+    limits::tick()?;
+    match exp {
         Expression::IntLiteral(i) => Ok(Object::Int(*i).into()),
         Expression::StringLiteral(s) => Ok(Object::String(s.to_owned()).into()),
         Expression::Bool(b) => Ok(Object::Bool(*b).into()),
         Expression::PrefixExpression(t, right) => eval_prefix_expression(t, right, env),
         Expression::InfixExpression(t, left, right) => {
-            eval_infix_objects(t, eval_expression(left, env)?, eval_expression(right, env)?)
+            let result =
+                eval_infix_objects(t, eval_expression(left, env)?, eval_expression(right, env)?)?;
+            limits::check_size(&result)?;
+            Ok(result)
         }
         Expression::IfExpression(con, if_exp, else_exp) => {
             eval_if_else_expression(con, if_exp, else_exp, env)
@@ -75,14 +190,172 @@ pub fn eval_expression(exp: &Expression, env: &Env) -> EvalResponse {
             Some(v) => Ok(v),
             None => Err(EvalError::IdentifierNotFount(ident.to_string())),
         },
-        Expression::FnExpression(idents, blk) => {
-            Ok(Object::Function(idents.clone(), blk.clone(), Rc::clone(env)).into())
+        Expression::Local(ident, depth, slot) => match env.borrow().get_local(*depth, *slot) {
+            Some(v) => Ok(v),
+            None => Err(EvalError::IdentifierNotFount(ident.to_string())),
+        },
+        Expression::FnExpression(idents, blk, _) => {
+            Ok(Object::Function(idents.clone(), blk.clone(), Rc::clone(env), None).into())
         }
         Expression::CallExpression(fun, values) => eval_call_expression(fun, values, env),
         Expression::Arrary(a) => eval_array_expression(a, env),
         Expression::IndexExpression(left, index_exp) => eval_index_expression(left, index_exp, env),
         Expression::Map(map) => eval_map_expression(map, env), //eval_map_expression(map, env),
+        Expression::TryExpression(body, catch, finally) => {
+            eval_try_expression(body, catch, finally, env)
+        }
+        Expression::Propagate(exp) => eval_propagate(exp, env),
+        Expression::Match(value, arms) => eval_match_expression(value, arms, env),
+        Expression::Spread(_) | Expression::KeywordArg(..) => Err(misplaced_argument(exp)),
+    }
+}
+
+/// Spread and keyword arguments only mean something as the arguments of a call, which
+/// `eval_args` evaluates itself.
+fn misplaced_argument(exp: &Expression) -> EvalError {
+    EvalError::ImpossibleState(format!("{exp} outside the arguments of a call"))
+}
+
+/// Runs the first arm whose pattern matches and whose guard, if any, holds. The names a
+/// pattern binds are set in `env`, like a `let`, before its guard runs.
+fn eval_match_expression(value: &Expression, arms: &[MatchArm], env: &Env) -> EvalResponse {
+    let (arm, scope) = match_arm(value, arms, env)?;
+    eval_expression(&arm.value, &scope)
+}
+
+/// The first of `arms` matching `value`, and the scope its names are bound in.
+fn match_arm<'a>(
+    value: &Expression,
+    arms: &'a [MatchArm],
+    env: &Env,
+) -> Result<(&'a MatchArm, Env), EvalError> {
+    let value = eval_expression(value, env)?;
+    for arm in arms {
+        let mut bindings = Vec::new();
+        if !match_pattern(&arm.pattern, &value, &mut bindings) {
+            continue;
+        }
+        // Inside a function the resolver gave the arm's names slots only the arm uses. Outside
+        // one they are bound by name, in a scope of the arm's own.
+        let scope = if bindings.iter().any(|(name, _)| matches!(name, Pattern::Binding(_))) {
+            gc::track(Environment::new_closed(env.clone()))
+        } else {
+            env.clone()
+        };
+        bind(bindings, &scope, false)?;
+        if let Some(guard) = &arm.guard {
+            if !is_truthy(eval_expression(guard, &scope)?) {
+                continue;
+            }
+        }
+        return Ok((arm, scope));
+    }
+    Err(EvalError::NoMatch(value.to_string()))
+}
+
+/// Binds the names `match_pattern` collected: in their slots inside a function, by name outside.
+fn bind(bindings: Vec<(&Pattern, Rc<Object>)>, env: &Env, constant: bool) -> Result<(), EvalError> {
+    for (name, bound) in bindings {
+        match name {
+            Pattern::Local(name, slot) => env
+                .borrow_mut()
+                .declare_local(name, *slot, bound, constant)?,
+            Pattern::Binding(name) => env.borrow_mut().declare(name, bound, constant)?,
+            _ => unreachable!("only names bind values"),
+        }
+    }
+    Ok(())
+}
+
+/// Whether `value` has the shape of `pattern`, collecting the names it binds, with their
+/// values, in `bindings`.
+fn match_pattern<'a>(
+    pattern: &'a Pattern,
+    value: &Rc<Object>,
+    bindings: &mut Vec<(&'a Pattern, Rc<Object>)>,
+) -> bool {
+    match (pattern, value.as_ref()) {
+        (Pattern::Wildcard, _) => true,
+        (Pattern::Binding(_) | Pattern::Local(..), _) => {
+            bindings.push((pattern, value.clone()));
+            true
+        }
+        (Pattern::Literal(literal), value) => *value == Object::from(literal),
+        (Pattern::Array(elements, rest), Object::Array(values)) => {
+            let fits = match rest {
+                None => values.len() == elements.len(),
+                Some(_) => values.len() >= elements.len(),
+            };
+            let matched = fits
+                && elements
+                    .iter()
+                    .zip(values)
+                    .all(|(element, value)| match_pattern(element, value, bindings));
+            match rest.as_deref() {
+                _ if !matched => false,
+                None | Some(Pattern::Wildcard) => true,
+                Some(rest) => {
+                    let remaining = Object::Array(values[elements.len()..].to_vec());
+                    match_pattern(rest, &remaining.into(), bindings)
+                }
+            }
+        }
+        (Pattern::Hash(pairs), Object::Hash(hash)) => pairs.iter().all(|(key, pattern)| {
+            let key = Object::from(key)
+                .hash_key()
+                .expect("strings, ints and bools are always valid hash keys");
+            match hash.get(&key) {
+                Some(pair) => match_pattern(pattern, &pair.value, bindings),
+                None => false,
+            }
+        }),
+        _ => false,
+    }
+}
+
+fn eval_propagate(exp: &Expression, env: &Env) -> EvalResponse {
+    let value = eval_expression(exp, env)?;
+    match value.as_ref() {
+        Object::Result(Ok(inner)) => Ok(inner.clone()),
+        Object::Result(Err(_)) => Err(EvalError::Propagated(value)),
+        _ => Err(EvalError::InvalidObjectType("Result".into(), value.to_string())),
+    }
+}
+
+fn eval_try_expression(
+    body: &BlockStatement,
+    catch: &Option<(Pattern, BlockStatement)>,
+    finally: &Option<BlockStatement>,
+    env: &Env,
+) -> EvalResponse {
+    let mut result = eval_block(body, env);
+    if let Some((name, handler)) = catch {
+        result = match result {
+            Err(e) if e.is_catchable() => {
+                // Like a `match` arm's names, the caught error gets a slot of its own inside a
+                // function, and a scope of its own outside one.
+                let scope = match name {
+                    Pattern::Binding(_) => gc::track(Environment::new_closed(env.clone())),
+                    _ => env.clone(),
+                };
+                let caught = bind(vec![(name, backtrace::catch(e))], &scope, false);
+                caught.and_then(|()| eval_block(handler, &scope))
+            }
+            result => result,
+        };
+    }
+    let Some(finally) = finally else {
+        return result;
     };
+    if matches!(&result, Err(e) if e.is_limit()) {
+        return result;
+    }
+    // A `return` in the `finally` wins over whatever the rest did.
+    let cleanup = backtrace::aside(|| eval_block(finally, env))?;
+    if let Object::Return(_) = cleanup.as_ref() {
+        return Ok(cleanup);
+    }
+    result
 }
 
 fn eval_map_expression(map: &[(Expression, Expression)], env: &Env) -> EvalResponse {
@@ -92,8 +365,10 @@ fn eval_map_expression(map: &[(Expression, Expression)], env: &Env) -> EvalRespo
         let value = eval_expression(v, env)?;
         mapped.insert(key.as_ref().hash_key()?, HashPair { key, value });
     }
+    let mapped = Object::Hash(mapped);
+    limits::check_size(&mapped)?;
 
-    Ok(Object::Hash(mapped).into())
+    Ok(mapped.into())
 }
 fn eval_index_expression(left: &Expression, index_exp: &Expression, env: &Env) -> EvalResponse {
     let left = eval_expression(left, env)?;
@@ -112,13 +387,32 @@ fn eval_index_expression(left: &Expression, index_exp: &Expression, env: &Env) -
         },
         Object::Hash(map) => {
             let key = eval_expression(index_exp, env)?;
-            println!("wowee");
             let hash_key = key.hash_key()?;
             Ok( match map.get(&hash_key){
                 Some(s) => s.value.clone(),
                 None => Object::Null.into()}
             )
         },
+        Object::Error(error) => {
+            let key = eval_expression(index_exp, env)?;
+            let Object::String(name) = key.as_ref() else {
+                return Err(EvalError::InvalidObjectType("String".into(), key.to_string()));
+            };
+            match error.field(name) {
+                Some(value) => Ok(value.into()),
+                None => Err(EvalError::HashKeyNotFound(format!("Error.{name}"))),
+            }
+        }
+        Object::Native(native) => {
+            let key = eval_expression(index_exp, env)?;
+            let Object::String(name) = key.as_ref() else {
+                return Err(EvalError::InvalidObjectType("String".into(), key.to_string()));
+            };
+            match native.method(&left, name) {
+                Some(method) => Ok(Object::NativeFn(method).into()),
+                None => Err(EvalError::HashKeyNotFound(format!("{}.{name}", native.type_name()))),
+            }
+        }
         _ => Err(EvalError::IndexOperatorNotSupported(left.to_string())),
     }
     // let Object::Array(array) = left.as_ref() else {
@@ -127,35 +421,283 @@ fn eval_index_expression(left: &Expression, index_exp: &Expression, env: &Env) -
 }
 
 fn eval_array_expression(values: &[Expression], env: &Env) -> EvalResponse {
-    Ok(Object::Array(expressions_to_objects(values, env)?).into())
+    let array = Object::Array(expressions_to_objects(values, env)?);
+    limits::check_size(&array)?;
+    Ok(array.into())
 }
 
 fn eval_call_expression(fun: &Expression, values: &[Expression], env: &Env) -> EvalResponse {
-    let res = eval_expression(fun, env)?;
-    let (idents, blk, new_env) = match res.as_ref() {
-        Object::Function(idents, blk, new_env) => (idents, blk, new_env),
-        Object::Builtin(builtin) => {
-            return builtin(&expressions_to_objects(values, env)?);
+    let function = eval_expression(fun, env)?;
+    let args = eval_args(values, env)?;
+    call_function(function, args)
+}
+
+/// The arguments of a call: the positional ones, with spread arrays flattened into them, then
+/// the keyword ones.
+#[derive(Default)]
+struct Args {
+    positional: Vec<Rc<Object>>,
+    keywords: Vec<(Identifier, Rc<Object>)>,
+}
+
+fn eval_args(values: &[Expression], env: &Env) -> Result<Args, EvalError> {
+    let mut args = Args::default();
+    for value in values {
+        match value {
+            Expression::Spread(array) => match eval_expression(array, env)?.as_ref() {
+                Object::Array(values) => args.positional.extend(values.iter().cloned()),
+                value => {
+                    let value = value.to_string();
+                    return Err(EvalError::InvalidObjectType("Array".into(), value));
+                }
+            },
+            Expression::KeywordArg(name, value) => {
+                args.keywords.push((name.clone(), eval_expression(value, env)?));
+            }
+            value => args.positional.push(eval_expression(value, env)?),
         }
-        _ => unreachable!(),
-    };
-    let args: Vec<Rc<Object>> = values
-        .iter()
-        .map(|v| eval_expression(v, env))
-        .collect::<Result<Vec<Rc<Object>>, EvalError>>()?;
-    let scoped: Env = Rc::new(RefCell::new(Environment::new_closed(new_env.clone())));
-    for (i, key) in idents.iter().enumerate() {
-        scoped.borrow_mut().set(key, args[i].clone());
     }
+    Ok(args)
+}
 
-    let result = eval_block(blk, &scoped)?;
+/// Calls `function` with `args`, looping instead of recursing whenever the body finishes with
+/// another call in tail position so that recursive Monkey loops run in constant Rust stack.
+pub(crate) fn apply_function(function: Rc<Object>, args: Vec<Rc<Object>>) -> EvalResponse {
+    call_function(
+        function,
+        Args {
+            positional: args,
+            keywords: Vec::new(),
+        },
+    )
+}
+
+fn call_function(function: Rc<Object>, args: Args) -> EvalResponse {
+    let _guard = CallGuard::enter()?;
+    // Deep non-tail recursion is bounded by `max_call_depth`, not by the thread's stack: when
+    // little stack is left the call continues on a fresh segment allocated on the heap.
+    stacker::maybe_grow(limits::STACK_RED_ZONE, limits::STACK_SEGMENT_SIZE, || {
+        let mut calls = TailCalls::default();
+        match apply_tail_calls(function, args, &mut calls) {
+            Err(EvalError::Propagated(err)) => Ok(err),
+            result => result.map_err(|e| calls.failed(e)),
+        }
+    })
+}
+
+/// The loop of `apply_function`, noting the Monkey functions it runs in `calls`.
+fn apply_tail_calls(mut function: Rc<Object>, mut args: Args, calls: &mut TailCalls) -> EvalResponse {
+    let mut frame = CallFrame::default();
+    loop {
+        limits::tick()?;
+        let (params, blk, new_env, name) = match function.as_ref() {
+            Object::Function(params, blk, new_env, name) => (params, blk, new_env, name),
+            Object::NativeFn(native) => return call_native(native, args),
+            f => return Err(EvalError::InvalidObjectType("fn".into(), f.to_string())),
+        };
+        frame.enter(name.as_deref(), params, blk);
+        calls.enter(&function);
+        let scoped = new_call_frame(params, args, new_env, blk)?;
+
+        match eval_tail_block(blk, &scoped, true)? {
+            Tail::Call(next_function, next_args, line) => {
+                calls.tail_call(line);
+                function = next_function;
+                args = next_args;
+            }
+            Tail::Value(result) => {
+                if let Object::Return(val) = result.as_ref() {
+                    return Ok(val.clone());
+                }
+                return Ok(result);
+            }
+        }
+    }
+}
 
-    if let Object::Return(val) = result.as_ref() {
-        return Ok(val.clone());
+/// Builtins take positional arguments only.
+fn call_native(native: &NativeFn, args: Args) -> EvalResponse {
+    if let Some((name, _)) = args.keywords.first() {
+        return Err(EvalError::UnknownParam(name.clone()));
     }
+    let result = native.call(&args.positional)?;
+    limits::check_size(&result)?;
     Ok(result)
 }
 
+/// The environment a call to a function with `params`, closing over `env`, runs `body` in, with
+/// the functions `body` declares already bound.
+fn new_call_frame(
+    params: &[Param],
+    args: Args,
+    env: &Env,
+    body: &BlockStatement,
+) -> Result<Env, EvalError> {
+    let slots = bind_args(params, args)?;
+    let scoped: Env = gc::track(Environment::new_frame(env.clone(), slots));
+    eval_params(params, &scoped)?;
+    hoist_functions(body, &scoped)?;
+    Ok(scoped)
+}
+
+/// Lines up `args` with `params`: positional arguments in order, any past the last param in
+/// the rest param, keyword arguments by name. A param left without an argument is `None`, for
+/// its default to fill in; leaving out one without a default is an error.
+fn bind_args(params: &[Param], args: Args) -> Result<Vec<Option<Rc<Object>>>, EvalError> {
+    let Args {
+        mut positional,
+        keywords,
+    } = args;
+    let rest = params.last().is_some_and(|param| param.rest);
+    let fixed = params.len() - usize::from(rest);
+    let actual = positional.len() + keywords.len();
+    let extra = positional.split_off(fixed.min(positional.len()));
+    if !extra.is_empty() && !rest {
+        return Err(arity_error(params, actual));
+    }
+
+    let mut slots: Vec<Option<Rc<Object>>> = positional.into_iter().map(Some).collect();
+    slots.resize(fixed, None);
+    for (name, value) in keywords {
+        let Some(i) = params[..fixed].iter().position(|param| param.name == name) else {
+            return Err(EvalError::UnknownParam(name));
+        };
+        if slots[i].is_some() {
+            return Err(EvalError::DuplicateArgument(name));
+        }
+        slots[i] = Some(value);
+    }
+    let missing = slots
+        .iter()
+        .zip(params)
+        .any(|(slot, param)| slot.is_none() && param.default.is_none());
+    if missing {
+        return Err(arity_error(params, actual));
+    }
+    if rest {
+        slots.push(Some(Object::Array(extra).into()));
+    }
+    Ok(slots)
+}
+
+/// The error for a call passing `actual` arguments to a function with `params`.
+fn arity_error(params: &[Param], actual: usize) -> EvalError {
+    if let Some(expected) = Param::fixed_arity(params) {
+        return EvalError::InvalidParamCount { expected, actual };
+    }
+    let required = |param: &&Param| param.default.is_none() && !param.rest;
+    let rest = params.iter().any(|param| param.rest);
+    EvalError::InvalidParamRange {
+        min: params.iter().filter(required).count(),
+        max: (!rest).then_some(params.len()),
+        actual,
+    }
+}
+
+/// Evaluates the defaults of the params a call left out and destructures the params with a
+/// pattern, in order, in the call's frame.
+fn eval_params(params: &[Param], env: &Env) -> Result<(), EvalError> {
+    for (slot, param) in params.iter().enumerate() {
+        let passed = env.borrow().get_local(0, slot);
+        let value = match (passed, &param.default) {
+            (Some(value), _) => value,
+            (None, Some(default)) => {
+                let value = eval_expression(default, env)?;
+                env.borrow_mut().set_local(slot, value.clone());
+                value
+            }
+            (None, None) => continue,
+        };
+        if let Some(pattern) = &param.pattern {
+            destructure(pattern, &value, env, false)?;
+        }
+    }
+    Ok(())
+}
+
+/// Outcome of evaluating a function body with tail calls left unevaluated.
+enum Tail {
+    Value(Rc<Object>),
+    /// The function, its args and the line of the statement making the call.
+    Call(Rc<Object>, Args, usize),
+}
+
+/// Evaluates a function body (or a branch of an `if` inside one). When `tail` is set the last
+/// statement is in tail position; `return` statements always are.
+fn eval_tail_block(block: &BlockStatement, env: &Env, tail: bool) -> Result<Tail, EvalError> {
+    let mut result: Rc<Object> = Object::Null.into();
+    for (i, st) in block.statements.iter().enumerate() {
+        let is_last = tail && i == block.statements.len() - 1;
+        let line = block.line(i);
+        let tail = debug::statement(line, env)
+            .and_then(|()| eval_tail_statement(st, env, is_last))
+            .map_err(|e| backtrace::statement_failed(line, e))?;
+        match tail {
+            Tail::Value(value) => {
+                if let Object::Return(_) = value.as_ref() {
+                    return Ok(Tail::Value(value));
+                }
+                result = value;
+            }
+            // The innermost block around the call knows its line.
+            Tail::Call(function, args, 0) => return Ok(Tail::Call(function, args, line)),
+            call => return Ok(call),
+        }
+    }
+    Ok(Tail::Value(result))
+}
+
+fn eval_tail_statement(statement: &Statement, env: &Env, tail: bool) -> Result<Tail, EvalError> {
+    match statement {
+        Statement::Return(exp) => Ok(match eval_tail_expression(exp, env)? {
+            Tail::Value(value) => Tail::Value(Object::Return(value).into()),
+            call => call,
+        }),
+        Statement::ExpressionStatement(exp) if tail => eval_tail_expression(exp, env),
+        Statement::ExpressionStatement(Expression::IfExpression(con, if_exp, else_exp)) => {
+            eval_tail_if_else(con, if_exp, else_exp, env, false)
+        }
+        st => Ok(Tail::Value(eval_statement(st, env)?)),
+    }
+}
+
+fn eval_tail_expression(exp: &Expression, env: &Env) -> Result<Tail, EvalError> {
+    match exp {
+        Expression::CallExpression(fun, values) => {
+            let function = eval_expression(fun, env)?;
+            let args = eval_args(values, env)?;
+            Ok(Tail::Call(function, args, 0))
+        }
+        Expression::IfExpression(con, if_exp, else_exp) => {
+            eval_tail_if_else(con, if_exp, else_exp, env, true)
+        }
+        Expression::Match(value, arms) => {
+            let (arm, scope) = match_arm(value, arms, env)?;
+            eval_tail_expression(&arm.value, &scope)
+        }
+        exp => Ok(Tail::Value(eval_expression(exp, env)?)),
+    }
+}
+
+fn eval_tail_if_else(
+    cond: &Expression,
+    if_exp: &BlockStatement,
+    else_exp: &Option<BlockStatement>,
+    env: &Env,
+    tail: bool,
+) -> Result<Tail, EvalError> {
+    let block = if is_truthy(eval_expression(cond, env)?) {
+        if_exp
+    } else {
+        match else_exp {
+            Some(exp) => exp,
+            None => return Ok(Tail::Value(Object::Null.into())),
+        }
+    };
+    hoist_functions(block, env)?;
+    eval_tail_block(block, env, tail)
+}
+
 fn eval_if_else_expression(
     cond: &Expression,
     if_exp: &BlockStatement,
@@ -172,7 +714,7 @@ fn eval_if_else_expression(
     }
 }
 
-fn is_truthy(obj: impl Into<Rc<Object>>) -> bool {
+pub(crate) fn is_truthy(obj: impl Into<Rc<Object>>) -> bool {
     match *obj.into() {
         Object::Bool(b) => b,
         Object::Null => false,
@@ -180,8 +722,8 @@ fn is_truthy(obj: impl Into<Rc<Object>>) -> bool {
     }
 }
 
-fn eval_infix_objects(token: &Token, left: Rc<Object>, right: Rc<Object>) -> EvalResponse {
-    return match token {
+pub(crate) fn eval_infix_objects(token: &Token, left: Rc<Object>, right: Rc<Object>) -> EvalResponse {
+    match token {
         Token::Dash => left.as_ref() - right.as_ref(),
         Token::Plus => left.as_ref() + right.as_ref(),
         Token::ForwardSlash => left.as_ref() / right.as_ref(),
@@ -199,7 +741,7 @@ fn eval_infix_objects(token: &Token, left: Rc<Object>, right: Rc<Object>) -> Eva
             t.to_string(),
             right.to_string(),
         )),
-    };
+    }
 }
 
 fn eval_obj_comparison(
@@ -230,6 +772,7 @@ fn eval_obj_comparison(
                 (Object::String(l), Object::String(r)) => l == r,
                 (Object::Int(l), Object::Int(r)) => l == r,
                 (Object::Null, Object::Null) => true,
+                (Object::Native(l), Object::Native(r)) => l == r,
                 (l, r) => {
                     return Err(EvalError::InvalidOperator(
                         l.to_string(),
@@ -272,7 +815,7 @@ fn eval_prefix_expression(token: &Token, exp: &Expression, env: &Env) -> EvalRes
     }
 }
 
-fn eval_bang_operator_expression(right: Rc<Object>) -> Result<Object, EvalError> {
+pub(crate) fn eval_bang_operator_expression(right: Rc<Object>) -> Result<Object, EvalError> {
     let result = match right.as_ref() {
         Object::Bool(b) => !*b,
         Object::Null => true,
@@ -281,9 +824,12 @@ fn eval_bang_operator_expression(right: Rc<Object>) -> Result<Object, EvalError>
     Ok(Object::Bool(result))
 }
 
-fn eval_minus_operator_expression(right: Rc<Object>) -> Result<Object, EvalError> {
+pub(crate) fn eval_minus_operator_expression(right: Rc<Object>) -> Result<Object, EvalError> {
     match right.as_ref() {
-        Object::Int(i) => Ok(Object::Int(-*i)),
+        Object::Int(i) => i
+            .checked_neg()
+            .map(Object::Int)
+            .ok_or_else(|| EvalError::IntegerOverflow(format!("-({i})"))),
         _ => Ok(Object::Null),
     }
 }
@@ -1,6 +1,9 @@
 use lexer::token::Token;
 use std::fmt;
 
+use crate::object::Object;
+use crate::sync::Rc;
+
 #[derive(Debug, PartialEq)]
 pub enum EvalError {
     TypeMismatch(String, String),
@@ -10,6 +13,10 @@ pub enum EvalError {
     },
     InvalidPrefix(Token),
     InvalidOperator(String, String, String),
+    /// An integer divided by zero.
+    DivisionByZero,
+    /// Integer arithmetic whose result doesn't fit in an `i64`: the operation, as written.
+    IntegerOverflow(String),
     IdentifierNotFount(String),
     ImpossibleState(String),
     InvalidParamTypes {
@@ -20,6 +27,17 @@ pub enum EvalError {
         expected: usize,
         actual: usize,
     },
+    /// A call to a function with optional params passed too few or too many arguments. `max`
+    /// is `None` when a rest param takes any number.
+    InvalidParamRange {
+        min: usize,
+        max: Option<usize>,
+        actual: usize,
+    },
+    /// A keyword argument named no param of the function called.
+    UnknownParam(String),
+    /// A param was given both a positional and a keyword argument, or two keyword ones.
+    DuplicateArgument(String),
     InvalidObjectType(String, String),
     IndexOperatorNotSupported(String),
     IndexOutOfBounds {
@@ -28,6 +46,94 @@ pub enum EvalError {
     },
     InvalidHashKeyType (String),
     HashKeyNotFound(String),
+    StackOverflow(usize),
+    StepLimitExceeded(u64),
+    CollectionTooLarge {
+        size: usize,
+        max: usize,
+    },
+    DeadlineExceeded,
+    Interrupted,
+    ImportFailed {
+        path: String,
+        reason: String,
+    },
+    CircularImport(Vec<String>),
+    /// The bundled prelude didn't parse or evaluate, so no environment with it can be made.
+    PreludeFailed(String),
+    AssertionFailed(String),
+    /// A value passed to `throw` that no `catch` caught.
+    Thrown(Rc<Object>),
+    UnwrapFailed(String),
+    /// No arm of a `match` matched the value.
+    NoMatch(String),
+    /// A destructuring `let` or param got a value without the pattern's shape: the pattern, then
+    /// the value.
+    ShapeMismatch(String, String),
+    /// A name declared with `const`, or a builtin in the global scope, was bound again.
+    AssignToConstant(String),
+    /// An `err` returned early by `?`. It is the value of the call it leaves, so it never gets
+    /// further than `apply_function`, or than the program at the top level.
+    Propagated(Rc<Object>),
+}
+
+impl EvalError {
+    /// Name of the variant, the `kind` of the `Error` value a `catch` turns it into.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            EvalError::TypeMismatch(..) => "TypeMismatch",
+            EvalError::InvalidOperation { .. } => "InvalidOperation",
+            EvalError::InvalidPrefix(_) => "InvalidPrefix",
+            EvalError::InvalidOperator(..) => "InvalidOperator",
+            EvalError::DivisionByZero => "DivisionByZero",
+            EvalError::IntegerOverflow(_) => "IntegerOverflow",
+            EvalError::IdentifierNotFount(_) => "IdentifierNotFount",
+            EvalError::ImpossibleState(_) => "ImpossibleState",
+            EvalError::InvalidParamTypes { .. } => "InvalidParamTypes",
+            EvalError::InvalidParamCount { .. } => "InvalidParamCount",
+            EvalError::InvalidParamRange { .. } => "InvalidParamRange",
+            EvalError::UnknownParam(_) => "UnknownParam",
+            EvalError::DuplicateArgument(_) => "DuplicateArgument",
+            EvalError::InvalidObjectType(..) => "InvalidObjectType",
+            EvalError::IndexOperatorNotSupported(_) => "IndexOperatorNotSupported",
+            EvalError::IndexOutOfBounds { .. } => "IndexOutOfBounds",
+            EvalError::InvalidHashKeyType(_) => "InvalidHashKeyType",
+            EvalError::HashKeyNotFound(_) => "HashKeyNotFound",
+            EvalError::StackOverflow(_) => "StackOverflow",
+            EvalError::StepLimitExceeded(_) => "StepLimitExceeded",
+            EvalError::CollectionTooLarge { .. } => "CollectionTooLarge",
+            EvalError::DeadlineExceeded => "DeadlineExceeded",
+            EvalError::Interrupted => "Interrupted",
+            EvalError::ImportFailed { .. } => "ImportFailed",
+            EvalError::CircularImport(_) => "CircularImport",
+            EvalError::PreludeFailed(_) => "PreludeFailed",
+            EvalError::AssertionFailed(_) => "AssertionFailed",
+            EvalError::Thrown(_) => "Thrown",
+            EvalError::UnwrapFailed(_) => "UnwrapFailed",
+            EvalError::NoMatch(_) => "NoMatch",
+            EvalError::ShapeMismatch(..) => "ShapeMismatch",
+            EvalError::AssignToConstant(_) => "AssignToConstant",
+            EvalError::Propagated(_) => "Propagated",
+        }
+    }
+
+    /// Whether the script ran out of one of the host's limits.
+    pub fn is_limit(&self) -> bool {
+        matches!(
+            self,
+            EvalError::StackOverflow(_)
+                | EvalError::StepLimitExceeded(_)
+                | EvalError::CollectionTooLarge { .. }
+                | EvalError::DeadlineExceeded
+                | EvalError::Interrupted
+        )
+    }
+
+    /// Whether a `catch` may handle the error. Limits can't be caught, or a script could keep
+    /// running past them, and a `?` return isn't an error at all.
+    pub fn is_catchable(&self) -> bool {
+        !self.is_limit() && !matches!(self, EvalError::Propagated(_))
+    }
 }
 
 impl std::error::Error for EvalError {}
@@ -44,6 +150,8 @@ impl fmt::Display for EvalError {
             EvalError::InvalidOperator(l, opp, r) => {
                 write!(f, "{l} {opp} {r} is an invalid operation")
             }
+            EvalError::DivisionByZero => write!(f, "division by zero"),
+            EvalError::IntegerOverflow(operation) => write!(f, "{operation} overflows"),
             EvalError::IdentifierNotFount(i) => write!(f, "could not find {i}"),
             EvalError::ImpossibleState(i) => write!(
                 f,
@@ -55,6 +163,14 @@ impl fmt::Display for EvalError {
             EvalError::InvalidParamCount { expected, actual } => {
                 write!(f, "got {} params but was expecting {}", actual, expected)
             }
+            EvalError::InvalidParamRange { min, max: Some(max), actual } => {
+                write!(f, "got {actual} params but was expecting {min} to {max}")
+            }
+            EvalError::InvalidParamRange { min, max: None, actual } => {
+                write!(f, "got {actual} params but was expecting at least {min}")
+            }
+            EvalError::UnknownParam(name) => write!(f, "the function has no param called {name}"),
+            EvalError::DuplicateArgument(name) => write!(f, "{name} was passed more than once"),
             EvalError::InvalidObjectType(expected, acutal) => {
                 write!(f, "{expected} was expected, but got {acutal} ")
             }
@@ -67,7 +183,37 @@ impl fmt::Display for EvalError {
                 max, index
             ),
             EvalError::InvalidHashKeyType(k) => write!(f,"{} is not a valid hash key type",k),
-            EvalError::HashKeyNotFound(k) => write!(f,"key: {k} does not exsist")
+            EvalError::HashKeyNotFound(k) => write!(f,"key: {k} does not exsist"),
+            EvalError::StackOverflow(max) => {
+                write!(f, "maximum call depth of {max} exceeded")
+            }
+            EvalError::StepLimitExceeded(max) => {
+                write!(f, "evaluation took more than {max} steps")
+            }
+            EvalError::CollectionTooLarge { size, max } => {
+                write!(f, "collection of size {size} is bigger than the limit of {max}")
+            }
+            EvalError::DeadlineExceeded => write!(f, "evaluation ran past its deadline"),
+            EvalError::Interrupted => write!(f, "evaluation was interrupted"),
+            EvalError::ImportFailed { path, reason } => {
+                write!(f, "could not import {path}: {reason}")
+            }
+            EvalError::CircularImport(chain) => {
+                write!(f, "circular import: {}", chain.join(" -> "))
+            }
+            EvalError::PreludeFailed(reason) => write!(f, "prelude failed to load: {reason}"),
+            EvalError::AssertionFailed(message) => write!(f, "assertion failed: {message}"),
+            EvalError::Thrown(value) => match value.as_ref() {
+                Object::Error(error) => write!(f, "{error}"),
+                value => write!(f, "uncaught {value}"),
+            },
+            EvalError::UnwrapFailed(value) => write!(f, "could not unwrap {value}"),
+            EvalError::NoMatch(value) => write!(f, "no arm of the match matches {value}"),
+            EvalError::ShapeMismatch(pattern, value) => {
+                write!(f, "can't destructure {value} as {pattern}")
+            }
+            EvalError::AssignToConstant(name) => write!(f, "{name} is a constant and can't be bound again"),
+            EvalError::Propagated(value) => write!(f, "{value} returned by ? outside a function"),
         }
     }
 }
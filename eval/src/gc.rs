@@ -0,0 +1,237 @@
+//! Cycle collection for environments.
+//!
+//! A closure holds the environment it was created in, and a `let` stores the closure back into
+//! that same environment, so every function defined inside a scope forms an `Rc` cycle that
+//! reference counting alone never frees. Every environment created through `track` is
+//! remembered here, and `collect` finds the ones that are only kept alive by such cycles: for
+//! each environment and object reachable from the tracked environments it compares the strong
+//! count with the number of references coming from inside that graph. Anything with references
+//! from outside (the host, or a Rust stack frame mid-evaluation) is live, as is everything it
+//! reaches. The remaining environments are cleared, which breaks their cycles.
+//!
+//! Collection is per thread and conservative: references hidden inside native closures or
+//! host objects count as outside references, so their targets are always kept.
+
+use std::cell::{Cell, RefCell as StdRefCell};
+use std::collections::{HashMap, HashSet};
+
+use crate::environment::{Env, Environment};
+use crate::object::Object;
+use crate::sync::{RefCell, Rc, Weak};
+
+/// Tracked environments needed before the next automatic collection.
+const MIN_COLLECTION_THRESHOLD: usize = 1024;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Environments currently alive on this thread.
+    pub environments: usize,
+    /// Objects reachable from those environments.
+    pub objects: usize,
+    /// Collections run on this thread so far.
+    pub collections: usize,
+    /// Environments freed by the collector so far.
+    pub freed_environments: usize,
+}
+
+thread_local! {
+    static TRACKED: StdRefCell<Vec<Weak<RefCell<Environment>>>> = const { StdRefCell::new(Vec::new()) };
+    static THRESHOLD: Cell<usize> = const { Cell::new(MIN_COLLECTION_THRESHOLD) };
+    static COLLECTIONS: Cell<usize> = const { Cell::new(0) };
+    static FREED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Wraps `env` up for use by the evaluator and registers it with the collector, running a
+/// collection first if enough environments have piled up since the last one.
+pub fn track(env: Environment) -> Env {
+    let threshold = THRESHOLD.with(Cell::get);
+    if tracked_len() >= threshold {
+        // Most environments die as soon as their call returns, so only pay for a full
+        // collection when a good share of them is still hanging around.
+        prune();
+        if tracked_len() >= threshold / 2 {
+            collect();
+        }
+        THRESHOLD.with(|t| t.set((tracked_len() * 2).max(MIN_COLLECTION_THRESHOLD)));
+    }
+    let env = Rc::new(RefCell::new(env));
+    TRACKED.with(|tracked| tracked.borrow_mut().push(Rc::downgrade(&env)));
+    env
+}
+
+/// Frees every tracked environment that is only reachable through reference cycles.
+pub fn collect() -> HeapStats {
+    let heap = Heap::scan();
+    let garbage = heap.garbage();
+    for id in &garbage {
+        heap.envs[id].borrow_mut().clear();
+    }
+    let freed = garbage.len();
+    drop(heap);
+    prune();
+
+    COLLECTIONS.with(|c| c.set(c.get() + 1));
+    FREED.with(|f| f.set(f.get() + freed));
+    heap_stats()
+}
+
+/// Reports the current size of the heap without collecting anything.
+pub fn heap_stats() -> HeapStats {
+    let heap = Heap::scan();
+    HeapStats {
+        environments: heap.tracked,
+        objects: heap.objects.len(),
+        collections: COLLECTIONS.with(Cell::get),
+        freed_environments: FREED.with(Cell::get),
+    }
+}
+
+fn tracked_len() -> usize {
+    TRACKED.with(|tracked| tracked.borrow().len())
+}
+
+fn prune() {
+    TRACKED.with(|tracked| {
+        tracked
+            .borrow_mut()
+            .retain(|env| env.strong_count() > 0)
+    });
+}
+
+type NodeId = *const ();
+
+/// A snapshot of the environment/object graph. Holds exactly one strong reference to every
+/// node it found, which is accounted for when counting outside references.
+struct Heap {
+    envs: HashMap<NodeId, Env>,
+    objects: HashMap<NodeId, Rc<Object>>,
+    edges: HashMap<NodeId, Vec<NodeId>>,
+    tracked: usize,
+}
+
+impl Heap {
+    fn scan() -> Self {
+        let roots: Vec<Env> = TRACKED.with(|tracked| {
+            tracked
+                .borrow()
+                .iter()
+                .filter_map(Weak::upgrade)
+                .collect()
+        });
+        let mut heap = Heap {
+            envs: HashMap::new(),
+            objects: HashMap::new(),
+            edges: HashMap::new(),
+            tracked: roots.len(),
+        };
+        let mut pending_envs = roots;
+        let mut pending_objects: Vec<Rc<Object>> = Vec::new();
+        loop {
+            if let Some(env) = pending_envs.pop() {
+                let id = env_id(&env);
+                if heap.envs.contains_key(&id) {
+                    continue;
+                }
+                let mut children = Vec::new();
+                {
+                    let borrowed = env.borrow();
+                    if let Some(outer) = borrowed.outer() {
+                        children.push(env_id(outer));
+                        pending_envs.push(outer.clone());
+                    }
+                    for value in borrowed.values() {
+                        children.push(object_id(value));
+                        pending_objects.push(value.clone());
+                    }
+                }
+                heap.edges.insert(id, children);
+                heap.envs.insert(id, env);
+            } else if let Some(obj) = pending_objects.pop() {
+                let id = object_id(&obj);
+                if heap.objects.contains_key(&id) {
+                    continue;
+                }
+                let mut children = Vec::new();
+                match obj.as_ref() {
+                    Object::Function(_, _, env, _) => {
+                        children.push(env_id(env));
+                        pending_envs.push(env.clone());
+                    }
+                    Object::Return(inner) | Object::Result(Ok(inner) | Err(inner)) => {
+                        children.push(object_id(inner));
+                        pending_objects.push(inner.clone());
+                    }
+                    Object::Array(values) => {
+                        for value in values {
+                            children.push(object_id(value));
+                            pending_objects.push(value.clone());
+                        }
+                    }
+                    Object::Hash(hash) => {
+                        for pair in hash.values() {
+                            children.push(object_id(&pair.key));
+                            children.push(object_id(&pair.value));
+                            pending_objects.push(pair.key.clone());
+                            pending_objects.push(pair.value.clone());
+                        }
+                    }
+                    _ => {}
+                }
+                heap.edges.insert(id, children);
+                heap.objects.insert(id, obj);
+            } else {
+                break;
+            }
+        }
+        heap
+    }
+
+    /// Environments not reachable from anything outside the graph.
+    fn garbage(&self) -> Vec<NodeId> {
+        let mut internal: HashMap<NodeId, usize> = HashMap::new();
+        for children in self.edges.values() {
+            for child in children {
+                *internal.entry(*child).or_default() += 1;
+            }
+        }
+        let outside_refs = |id: &NodeId, strong: usize| {
+            // One of the strong references is the one `Heap` itself holds.
+            strong.saturating_sub(1 + internal.get(id).copied().unwrap_or(0)) > 0
+        };
+
+        let mut live: HashSet<NodeId> = HashSet::new();
+        let mut pending: Vec<NodeId> = self
+            .envs
+            .iter()
+            .filter(|(id, env)| outside_refs(id, Rc::strong_count(env)))
+            .map(|(id, _)| *id)
+            .chain(
+                self.objects
+                    .iter()
+                    .filter(|(id, obj)| outside_refs(id, Rc::strong_count(obj)))
+                    .map(|(id, _)| *id),
+            )
+            .collect();
+        while let Some(id) = pending.pop() {
+            if live.insert(id) {
+                if let Some(children) = self.edges.get(&id) {
+                    pending.extend(children.iter().copied());
+                }
+            }
+        }
+
+        self.envs
+            .keys()
+            .filter(|id| !live.contains(id))
+            .copied()
+            .collect()
+    }
+}
+
+fn env_id(env: &Env) -> NodeId {
+    Rc::as_ptr(env) as NodeId
+}
+
+fn object_id(obj: &Rc<Object>) -> NodeId {
+    Rc::as_ptr(obj) as NodeId
+}
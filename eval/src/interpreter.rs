@@ -0,0 +1,208 @@
+use std::fmt;
+use std::path::Path;
+
+use lexer::lexer::Lexer;
+use parser::parse_error::ParserError;
+use parser::parser::Parser;
+
+use crate::backtrace::{self, Backtrace};
+use crate::check::check_in;
+use crate::debug::{self, Debugger};
+use crate::environment::{Env, Environment};
+use crate::eval::{apply_function, eval_with_limits, EvalResponse};
+use crate::eval_error::EvalError;
+use crate::gc::{self, HeapStats};
+use crate::limits::{self, Limits};
+use crate::lint::Diagnostic;
+use crate::modules;
+use crate::object::{NativeFn, Object};
+use crate::sync::{MaybeSendSync, Rc};
+
+/// Everything needed to run Monkey code from a host program: a global environment seeded with
+/// the builtins and prelude, and the limits every evaluation runs under.
+pub struct Interpreter {
+    env: Env,
+    limits: Limits,
+    debugger: Option<Box<dyn Debugger>>,
+}
+
+#[derive(Debug)]
+pub enum InterpreterError {
+    Parse(Vec<ParserError>),
+    /// Type errors `check` found, which stop the program before it runs.
+    Check(Vec<Diagnostic>),
+    /// A runtime error, and the calls it happened in.
+    Eval(EvalError, Backtrace),
+}
+
+impl std::error::Error for InterpreterError {}
+
+impl fmt::Display for InterpreterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterpreterError::Parse(errors) => {
+                for e in errors {
+                    writeln!(f, "{e}")?;
+                }
+                Ok(())
+            }
+            InterpreterError::Check(diagnostics) => {
+                for d in diagnostics {
+                    writeln!(f, "{d}")?;
+                }
+                Ok(())
+            }
+            InterpreterError::Eval(e, backtrace) => {
+                write!(f, "{e}")?;
+                for call in backtrace.to_string().lines() {
+                    write!(f, "\n    {call}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl From<EvalError> for InterpreterError {
+    fn from(e: EvalError) -> Self {
+        InterpreterError::Eval(e, Backtrace::default())
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter {
+            env: gc::track(Environment::new_with_builtin()),
+            limits: Limits::default(),
+            debugger: None,
+        }
+    }
+
+    /// Creates an interpreter with the Rust builtins but without the Monkey prelude, for sandboxed
+    /// embedding where the host decides what scripts can call.
+    pub fn without_prelude() -> Self {
+        Interpreter {
+            env: gc::track(Environment::new_without_prelude()),
+            limits: Limits::default(),
+            debugger: None,
+        }
+    }
+
+    /// Creates an interpreter whose globals live in a fresh scope on top of `globals`, so several
+    /// interpreters (on several threads with the `sync` feature) can share one read-mostly
+    /// environment without seeing each other's bindings.
+    pub fn with_globals(globals: &Env) -> Self {
+        Interpreter {
+            env: gc::track(Environment::new_closed(globals.clone())),
+            limits: Limits::default(),
+            debugger: None,
+        }
+    }
+
+    pub fn with_limits(limits: Limits) -> Self {
+        Interpreter {
+            limits,
+            ..Interpreter::new()
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Has `debugger` stop the code this interpreter runs from now on, at its breakpoints and
+    /// wherever it steps to.
+    pub fn set_debugger(&mut self, debugger: impl Debugger + 'static) {
+        self.debugger = Some(Box::new(debugger));
+    }
+
+    pub fn clear_debugger(&mut self) {
+        self.debugger = None;
+    }
+
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+
+    /// Parses, type checks and evaluates `input` in the global environment, keeping any
+    /// bindings it makes.
+    pub fn run(&mut self, input: &str) -> Result<Rc<Object>, InterpreterError> {
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+        if !parser.parse_errors.is_empty() {
+            return Err(InterpreterError::Parse(parser.parse_errors));
+        }
+        let type_errors = check_in(&program, &parser.statement_lines, &self.env);
+        if !type_errors.is_empty() {
+            return Err(InterpreterError::Check(type_errors));
+        }
+        let ((result, backtrace), debugger) = debug::with_debugger(self.debugger.take(), || {
+            backtrace::collect(|| eval_with_limits(program, &self.env, self.limits.clone()))
+        });
+        self.debugger = debugger;
+        result.map_err(|e| InterpreterError::Eval(e, backtrace))
+    }
+
+    /// Like `run`, but reads the program from `path` and resolves its imports relative to it.
+    pub fn run_file(&mut self, path: impl AsRef<Path>) -> Result<Rc<Object>, InterpreterError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(|e| EvalError::ImportFailed {
+            path: path.display().to_string(),
+            reason: e.to_string(),
+        })?;
+        let file = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        modules::with_current_file(file, || self.run(&source))
+    }
+
+    /// Calls the global function `name` with `args`.
+    pub fn call(&mut self, name: &str, args: Vec<Object>) -> EvalResponse {
+        let function = self
+            .get_global(name)
+            .ok_or_else(|| EvalError::IdentifierNotFount(name.to_string()))?;
+        self.call_value(function, args)
+    }
+
+    /// Calls a function value the host got hold of earlier, e.g. a callback returned by `run`.
+    pub fn call_value(&mut self, function: Rc<Object>, args: Vec<Object>) -> EvalResponse {
+        let args = args.into_iter().map(Rc::new).collect();
+        let (result, debugger) = debug::with_debugger(self.debugger.take(), || {
+            limits::with_limits(self.limits.clone(), || apply_function(function, args))
+        });
+        self.debugger = debugger;
+        result
+    }
+
+    /// Frees environments that are only kept alive by closures referring back to them.
+    pub fn collect_garbage(&self) -> HeapStats {
+        gc::collect()
+    }
+
+    pub fn heap_stats(&self) -> HeapStats {
+        gc::heap_stats()
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Rc<Object>> {
+        self.env.borrow().get(name)
+    }
+
+    pub fn set_global(&mut self, name: &str, value: impl Into<Object>) {
+        self.env.borrow_mut().set(name, Rc::new(value.into()));
+    }
+
+    /// Exposes `func` to scripts as the global function `name`. Calls with a different number
+    /// of arguments than `arity` fail before reaching `func`.
+    pub fn register_fn(
+        &mut self,
+        name: &str,
+        arity: Option<usize>,
+        func: impl Fn(&[Rc<Object>]) -> EvalResponse + MaybeSendSync + 'static,
+    ) {
+        self.set_global(name, Object::NativeFn(NativeFn::new(name, arity, func)));
+    }
+}
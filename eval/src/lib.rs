@@ -1,10 +1,22 @@
 #![allow(dead_code)]
 
-mod builtin;
+pub mod backtrace;
+pub mod builtin;
+pub mod check;
+pub mod debug;
 pub mod environment;
 pub mod eval;
-mod eval_error;
+pub mod eval_error;
+pub mod gc;
+pub mod interpreter;
+pub mod limits;
+pub mod lint;
+pub mod modules;
 mod node;
 pub mod object;
+pub mod optimize;
+pub mod prelude;
+pub mod resolver;
+pub mod sync;
 #[cfg(test)]
 mod tests;
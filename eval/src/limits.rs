@@ -0,0 +1,143 @@
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::eval_error::EvalError;
+use crate::object::Object;
+
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 10_000;
+
+/// Stack left below which a call moves onto a new segment, and the size of that segment.
+pub(crate) const STACK_RED_ZONE: usize = 256 * 1024;
+pub(crate) const STACK_SEGMENT_SIZE: usize = 2 * 1024 * 1024;
+
+/// How many steps run between checks of the deadline and the interrupt flag.
+const CHECK_INTERVAL: u64 = 256;
+
+/// Caps on how much work a single evaluation may do. Anything left as `None` is unbounded.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Statements and expressions evaluated before giving up.
+    pub max_steps: Option<u64>,
+    /// Nested (non-tail) function calls allowed at once.
+    pub max_call_depth: usize,
+    /// Largest array, hash or string (in bytes) a script may build.
+    pub max_collection_size: Option<usize>,
+    /// Wall-clock time after which evaluation stops.
+    pub deadline: Option<Instant>,
+    /// Set from another thread to stop evaluation at the next check.
+    pub interrupt: Option<Arc<AtomicBool>>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_steps: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            max_collection_size: None,
+            deadline: None,
+            interrupt: None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Budget {
+    limits: Limits,
+    steps: u64,
+    depth: usize,
+}
+
+thread_local! {
+    static BUDGET: RefCell<Budget> = RefCell::new(Budget::default());
+}
+
+/// Runs `f` with `limits` in force on the current thread, restoring whatever was active before.
+pub(crate) fn with_limits<T>(limits: Limits, f: impl FnOnce() -> T) -> T {
+    let previous = BUDGET.with(|budget| {
+        budget.replace(Budget {
+            limits,
+            steps: 0,
+            depth: 0,
+        })
+    });
+    let _restore = RestoreBudget(Some(previous));
+    f()
+}
+
+/// Puts the budget it holds back in place when dropped, even if evaluation panicked.
+struct RestoreBudget(Option<Budget>);
+
+impl Drop for RestoreBudget {
+    fn drop(&mut self) {
+        if let Some(previous) = self.0.take() {
+            BUDGET.with(|budget| budget.replace(previous));
+        }
+    }
+}
+
+/// Counts one evaluation step against the budget.
+pub(crate) fn tick() -> Result<(), EvalError> {
+    BUDGET.with(|budget| {
+        let mut budget = budget.borrow_mut();
+        budget.steps += 1;
+        let limits = &budget.limits;
+        if let Some(max) = limits.max_steps {
+            if budget.steps > max {
+                return Err(EvalError::StepLimitExceeded(max));
+            }
+        }
+        if budget.steps % CHECK_INTERVAL == 0 {
+            if let Some(interrupt) = &limits.interrupt {
+                if interrupt.load(Ordering::Relaxed) {
+                    return Err(EvalError::Interrupted);
+                }
+            }
+            if let Some(deadline) = limits.deadline {
+                if Instant::now() >= deadline {
+                    return Err(EvalError::DeadlineExceeded);
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Fails if `obj` is a collection bigger than the budget allows.
+pub(crate) fn check_size(obj: &Object) -> Result<(), EvalError> {
+    let size = match obj {
+        Object::Array(a) => a.len(),
+        Object::Hash(h) => h.len(),
+        Object::String(s) => s.len(),
+        _ => return Ok(()),
+    };
+    BUDGET.with(|budget| match budget.borrow().limits.max_collection_size {
+        Some(max) if size > max => Err(EvalError::CollectionTooLarge { size, max }),
+        _ => Ok(()),
+    })
+}
+
+/// Tracks how many non-tail calls are currently active on this thread, erroring out once a
+/// script recurses deeper than the limit allows.
+pub(crate) struct CallGuard;
+
+impl CallGuard {
+    pub(crate) fn enter() -> Result<CallGuard, EvalError> {
+        BUDGET.with(|budget| {
+            let mut budget = budget.borrow_mut();
+            let max = budget.limits.max_call_depth;
+            if budget.depth >= max {
+                return Err(EvalError::StackOverflow(max));
+            }
+            budget.depth += 1;
+            Ok(CallGuard)
+        })
+    }
+}
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        BUDGET.with(|budget| budget.borrow_mut().depth -= 1);
+    }
+}
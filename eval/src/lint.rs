@@ -0,0 +1,549 @@
+//! Static checks over a parsed program, for catching mistakes before running it.
+//!
+//! Scopes follow the evaluator: only function bodies open a new one, `if` blocks bind into the
+//! enclosing scope. Since a function body only looks names up when it is called, a name it uses
+//! may be defined after it; such references stay pending until the enclosing scope closes, and
+//! only names no enclosing scope ever defines are reported as undefined.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use lexer::lexer::Lexer;
+use lexer::token::Token;
+use parser::ast::visit::walk_statement;
+use parser::ast::{
+    BlockStatement, Expression, Identifier, Literal, MatchArm, Param, Pattern, Program,
+    Signature, Statement, Type, Visitor,
+};
+use parser::parse_error::ParserError;
+use parser::parser::Parser;
+
+use crate::builtin::get_builtin_fns;
+use crate::modules;
+use crate::object::Object;
+use crate::prelude;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Lint {
+    Parse(ParserError),
+    UnusedBinding(String),
+    ShadowedBuiltin(String),
+    UnreachableCode,
+    NotCallable(String),
+    WrongArity {
+        name: String,
+        expected: usize,
+        actual: usize,
+    },
+    UndefinedIdentifier(String),
+    ConstantCondition(String),
+    /// A `match` on the value without an arm for every value it could have.
+    NonExhaustiveMatch(String),
+    /// Found by `check`: a value of type `actual` where `context` needs an `expected`.
+    TypeMismatch {
+        context: String,
+        expected: Type,
+        actual: Type,
+    },
+    /// Found by `check`: an operator applied to types it never accepts, like `"a" - 1`.
+    InvalidOperands {
+        op: Token,
+        left: Type,
+        right: Type,
+    },
+}
+
+impl Lint {
+    /// Short stable name for the check, for filtering and editor integration.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Lint::Parse(_) => "parse-error",
+            Lint::UnusedBinding(_) => "unused-binding",
+            Lint::ShadowedBuiltin(_) => "shadowed-builtin",
+            Lint::UnreachableCode => "unreachable-code",
+            Lint::NotCallable(_) => "not-callable",
+            Lint::WrongArity { .. } => "wrong-arity",
+            Lint::UndefinedIdentifier(_) => "undefined-identifier",
+            Lint::ConstantCondition(_) => "constant-condition",
+            Lint::NonExhaustiveMatch(_) => "non-exhaustive-match",
+            Lint::TypeMismatch { .. } => "type-mismatch",
+            Lint::InvalidOperands { .. } => "invalid-operands",
+        }
+    }
+
+    /// Errors are certain to fail when that code runs; warnings are merely suspicious.
+    pub fn severity(&self) -> Severity {
+        match self {
+            Lint::Parse(_)
+            | Lint::NotCallable(_)
+            | Lint::WrongArity { .. }
+            | Lint::UndefinedIdentifier(_)
+            | Lint::TypeMismatch { .. }
+            | Lint::InvalidOperands { .. } => Severity::Error,
+            Lint::UnusedBinding(_)
+            | Lint::ShadowedBuiltin(_)
+            | Lint::UnreachableCode
+            | Lint::ConstantCondition(_)
+            | Lint::NonExhaustiveMatch(_) => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Lint::Parse(e) => write!(f, "{e}"),
+            Lint::UnusedBinding(name) => write!(f, "{name} is never used"),
+            Lint::ShadowedBuiltin(name) => write!(f, "{name} shadows the builtin of the same name"),
+            Lint::UnreachableCode => write!(f, "unreachable statement after return or throw"),
+            Lint::NotCallable(value) => write!(f, "{value} is not a function"),
+            Lint::WrongArity {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{name} takes {expected} params but is called with {actual}"
+            ),
+            Lint::UndefinedIdentifier(name) => write!(f, "{name} is never defined"),
+            Lint::ConstantCondition(cond) => write!(f, "condition {cond} is always the same"),
+            Lint::NonExhaustiveMatch(value) => {
+                write!(f, "match on {value} has no arm for some values")
+            }
+            Lint::TypeMismatch {
+                context,
+                expected,
+                actual,
+            } => write!(f, "{context} should be {expected} but is {actual}"),
+            Lint::InvalidOperands { op, left, right } => {
+                write!(f, "{op} can't be applied to {left} and {right}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub lint: Lint,
+    /// Line of the statement the problem was found in, counting from 1.
+    pub line: usize,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.lint.severity() {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(
+            f,
+            "{}: {severity}[{}]: {}",
+            self.line,
+            self.lint.code(),
+            self.lint
+        )
+    }
+}
+
+/// Parses `input` and lints it. Parse errors are reported as diagnostics of their own, in which
+/// case the program isn't linted any further.
+pub fn lint_source(input: &str) -> Vec<Diagnostic> {
+    match parse(input) {
+        Ok((program, statement_lines)) => lint(&program, &statement_lines),
+        Err(diagnostics) => diagnostics,
+    }
+}
+
+/// Parses `input`, returning the program and its statement lines, or the parse errors as
+/// diagnostics.
+pub(crate) fn parse(input: &str) -> Result<(Program, Vec<usize>), Vec<Diagnostic>> {
+    let mut parser = Parser::new(Lexer::new(input));
+    let program = parser.parse_program();
+    if !parser.parse_errors.is_empty() {
+        return Err(parser
+            .parse_errors
+            .into_iter()
+            .zip(parser.error_lines)
+            .map(|(e, line)| Diagnostic {
+                lint: Lint::Parse(e),
+                line,
+            })
+            .collect());
+    }
+    Ok((program, parser.statement_lines))
+}
+
+/// Lints `program`. `statement_lines` is the parser's table of statement lines; without it every
+/// diagnostic is reported on line 0.
+pub fn lint(program: &Program, statement_lines: &[usize]) -> Vec<Diagnostic> {
+    let mut globals: HashMap<String, Option<usize>> = prelude::arities();
+    let mut builtins = HashSet::new();
+    for (name, builtin) in get_builtin_fns() {
+        if let Object::NativeFn(native) = builtin.as_ref() {
+            globals.insert(name.clone(), native.arity);
+        }
+        builtins.insert(name);
+    }
+    let mut linter = Linter {
+        lines: statement_lines,
+        statements: 0,
+        line: 0,
+        exporting: false,
+        globals,
+        builtins,
+        scopes: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    linter.push_scope();
+    linter.visit_program(program);
+    let mut undefined = linter.pop_scope();
+    undefined.dedup();
+    for (name, line) in undefined {
+        linter.report_at(Lint::UndefinedIdentifier(name), line);
+    }
+    linter.diagnostics.sort_by_key(|d| d.line);
+    linter.diagnostics
+}
+
+#[derive(Default)]
+struct Scope {
+    bindings: HashMap<String, Binding>,
+    /// Names used here (or in a nested function) that weren't defined yet, with the line of
+    /// their first use.
+    pending: Vec<(String, usize)>,
+}
+
+struct Binding {
+    line: usize,
+    used: bool,
+    /// Number of params, when the name is bound straight to a function literal.
+    arity: Option<usize>,
+}
+
+struct Linter<'a> {
+    lines: &'a [usize],
+    statements: usize,
+    line: usize,
+    exporting: bool,
+    globals: HashMap<String, Option<usize>>,
+    builtins: HashSet<String>,
+    scopes: Vec<Scope>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, lint: Lint) {
+        self.report_at(lint, self.line);
+    }
+
+    fn report_at(&mut self, lint: Lint, line: usize) {
+        self.diagnostics.push(Diagnostic { lint, line });
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    /// Closes the innermost scope, reporting its unused bindings, and returns the references it
+    /// couldn't resolve.
+    fn pop_scope(&mut self) -> Vec<(String, usize)> {
+        let mut scope = self.scopes.pop().expect("scope stack is never empty here");
+        let mut unresolved = Vec::new();
+        for (name, line) in scope.pending {
+            match scope.bindings.get_mut(&name) {
+                Some(binding) => binding.used = true,
+                None if self.globals.contains_key(&name) => {}
+                None => unresolved.push((name, line)),
+            }
+        }
+        let mut unused: Vec<_> = scope
+            .bindings
+            .into_iter()
+            .filter(|(_, binding)| !binding.used)
+            .collect();
+        unused.sort_by_key(|(_, binding)| binding.line);
+        for (name, binding) in unused {
+            self.report_at(Lint::UnusedBinding(name), binding.line);
+        }
+        unresolved
+    }
+
+    fn declare(&mut self, name: &str, used: bool, arity: Option<usize>) {
+        if self.builtins.contains(name) {
+            self.report(Lint::ShadowedBuiltin(name.to_string()));
+        }
+        let line = self.line;
+        let scope = self
+            .scopes
+            .last_mut()
+            .expect("scope stack is never empty here");
+        let binding =
+            scope
+                .bindings
+                .entry(name.to_string())
+                .or_insert(Binding { line, used, arity });
+        binding.used |= used;
+        binding.arity = arity;
+    }
+
+    fn reference(&mut self, name: &str) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(binding) = scope.bindings.get_mut(name) {
+                binding.used = true;
+                return;
+            }
+        }
+        if !self.globals.contains_key(name) {
+            let line = self.line;
+            let scope = self
+                .scopes
+                .last_mut()
+                .expect("scope stack is never empty here");
+            scope.pending.push((name.to_string(), line));
+        }
+    }
+
+    /// Params of the function `name` currently refers to, if known.
+    fn arity_of(&self, name: &str) -> Option<usize> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(binding) = scope.bindings.get(name) {
+                return binding.arity;
+            }
+        }
+        self.globals.get(name).copied().flatten()
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        let mut returned = false;
+        for statement in statements {
+            if returned {
+                self.report_at(Lint::UnreachableCode, self.next_line());
+            }
+            self.visit_statement(statement);
+            returned = matches!(statement, Statement::Return(_) | Statement::Throw(_));
+        }
+    }
+
+    fn next_line(&self) -> usize {
+        self.lines.get(self.statements).copied().unwrap_or(0)
+    }
+
+    fn call(&mut self, function: &Expression, actual: usize) {
+        let (name, expected) = match function {
+            Expression::IntLiteral(_)
+            | Expression::StringLiteral(_)
+            | Expression::Bool(_)
+            | Expression::Arrary(_)
+            | Expression::Map(_) => {
+                self.report(Lint::NotCallable(function.to_string()));
+                return;
+            }
+            Expression::FnExpression(params, ..) => ("fn".to_string(), Param::fixed_arity(params)),
+            Expression::Identifier(name) => (name.clone(), self.arity_of(name)),
+            _ => return,
+        };
+        if let Some(expected) = expected.filter(|expected| *expected != actual) {
+            self.report(Lint::WrongArity {
+                name,
+                expected,
+                actual,
+            });
+        }
+    }
+}
+
+impl Visitor for Linter<'_> {
+    fn visit_program(&mut self, program: &Program) {
+        self.statements(&program.statements);
+    }
+
+    /// Lints the statements of `block`, leaving `line` on the statement the block is part of.
+    fn visit_block(&mut self, block: &BlockStatement) {
+        let line = self.line;
+        self.statements(&block.statements);
+        self.line = line;
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) {
+        self.line = self.next_line();
+        self.statements += 1;
+        walk_statement(self, statement);
+    }
+
+    fn visit_let(&mut self, name: &Identifier, _ty: Option<&Type>, value: &Expression) {
+        self.visit_expression(value);
+        let arity = match value {
+            Expression::FnExpression(params, ..) => Param::fixed_arity(params),
+            _ => None,
+        };
+        self.declare(name, self.exporting, arity);
+    }
+
+    fn visit_import(&mut self, path: &str, alias: Option<&Identifier>) {
+        let name = alias
+            .cloned()
+            .unwrap_or_else(|| modules::default_binding(path));
+        self.declare(&name, false, None);
+    }
+
+    /// The exported `let` shares its line with the `export`, so it isn't counted as a statement
+    /// of its own.
+    fn visit_export(&mut self, statement: &Statement) {
+        self.exporting = true;
+        walk_statement(self, statement);
+        self.exporting = false;
+    }
+
+    /// Like an exported `let`, the declaring `let` isn't a statement of its own.
+    fn visit_fn_declaration(&mut self, statement: &Statement) {
+        walk_statement(self, statement);
+    }
+
+    fn visit_const(&mut self, statement: &Statement) {
+        walk_statement(self, statement);
+    }
+
+    fn visit_identifier(&mut self, name: &Identifier) {
+        self.reference(name);
+    }
+
+    fn visit_match(&mut self, value: &Expression, arms: &[MatchArm]) {
+        if !is_exhaustive(arms) {
+            self.report(Lint::NonExhaustiveMatch(value.to_string()));
+        }
+        self.visit_expression(value);
+        for arm in arms {
+            // An arm's names are only bound in its guard and value.
+            self.push_scope();
+            for name in arm.pattern.names() {
+                self.declare(name, false, None);
+            }
+            if let Some(guard) = &arm.guard {
+                self.visit_expression(guard);
+            }
+            self.visit_expression(&arm.value);
+            let unresolved = self.pop_scope();
+            let scope = self
+                .scopes
+                .last_mut()
+                .expect("scope stack is never empty here");
+            scope.pending.extend(unresolved);
+        }
+    }
+
+    fn visit_let_pattern(&mut self, pattern: &Pattern, value: &Expression) {
+        self.visit_expression(value);
+        for name in pattern.names() {
+            self.declare(name, self.exporting, None);
+        }
+    }
+
+    /// An unused catch name is how a script ignores the error, so it isn't reported.
+    fn visit_try(
+        &mut self,
+        body: &BlockStatement,
+        catch: Option<(&Pattern, &BlockStatement)>,
+        finally: Option<&BlockStatement>,
+    ) {
+        self.visit_block(body);
+        if let Some((name, handler)) = catch {
+            // The caught error is only bound in the handler.
+            self.push_scope();
+            for name in name.names() {
+                self.declare(name, true, None);
+            }
+            self.visit_block(handler);
+            let unresolved = self.pop_scope();
+            let scope = self
+                .scopes
+                .last_mut()
+                .expect("scope stack is never empty here");
+            scope.pending.extend(unresolved);
+        }
+        if let Some(finally) = finally {
+            self.visit_block(finally);
+        }
+    }
+
+    fn visit_if(
+        &mut self,
+        cond: &Expression,
+        if_block: &BlockStatement,
+        else_block: Option<&BlockStatement>,
+    ) {
+        if is_constant(cond) {
+            self.report(Lint::ConstantCondition(cond.to_string()));
+        }
+        self.visit_expression(cond);
+        self.visit_block(if_block);
+        if let Some(else_block) = else_block {
+            self.visit_block(else_block);
+        }
+    }
+
+    fn visit_fn(
+        &mut self,
+        params: &[Param],
+        body: &BlockStatement,
+        _signature: Option<&Signature>,
+    ) {
+        self.push_scope();
+        for param in params {
+            for name in param.names() {
+                self.declare(name, true, None);
+            }
+            if let Some(default) = &param.default {
+                self.visit_expression(default);
+            }
+        }
+        self.visit_block(body);
+        let unresolved = self.pop_scope();
+        let scope = self
+            .scopes
+            .last_mut()
+            .expect("scope stack is never empty here");
+        scope.pending.extend(unresolved);
+    }
+
+    /// A spread array passes an unknown number of arguments, so the call's arity is unknown.
+    fn visit_call(&mut self, function: &Expression, args: &[Expression]) {
+        if !args.iter().any(|arg| matches!(arg, Expression::Spread(_))) {
+            self.call(function, args.len());
+        }
+        self.visit_expression(function);
+        for arg in args {
+            self.visit_expression(arg);
+        }
+    }
+}
+
+/// Whether some arm of a `match` always matches: one without a guard whose pattern matches
+/// anything, or, between them, both bools. Other patterns are too open-ended to tell.
+fn is_exhaustive(arms: &[MatchArm]) -> bool {
+    let unguarded = || arms.iter().filter(|arm| arm.guard.is_none());
+    unguarded().any(|arm| arm.pattern.is_irrefutable())
+        || [true, false].iter().all(|b| {
+            unguarded().any(|arm| arm.pattern == Pattern::Literal(Literal::Bool(*b)))
+        })
+}
+
+/// Whether `e` evaluates to the same value every time, i.e. it doesn't mention any names.
+fn is_constant(e: &Expression) -> bool {
+    match e {
+        Expression::IntLiteral(_)
+        | Expression::StringLiteral(_)
+        | Expression::Bool(_)
+        | Expression::FnExpression(..) => true,
+        Expression::PrefixExpression(_, right) => is_constant(right),
+        Expression::InfixExpression(_, left, right) => is_constant(left) && is_constant(right),
+        Expression::Arrary(values) => values.iter().all(is_constant),
+        Expression::Map(pairs) => pairs.iter().all(|(k, v)| is_constant(k) && is_constant(v)),
+        _ => false,
+    }
+}
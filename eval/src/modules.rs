@@ -0,0 +1,136 @@
+use std::cell::RefCell as StdRefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use lexer::lexer::Lexer;
+use parser::ast::{Program, Statement};
+use parser::parser::Parser;
+
+use crate::debug;
+use crate::environment::Environment;
+use crate::eval::{eval, EvalResponse};
+use crate::eval_error::EvalError;
+use crate::gc;
+use crate::node::Node;
+use crate::object::{HashPair, Object};
+use crate::sync::Rc;
+
+/// Extension tried when an import path names a file without one.
+pub const MODULE_EXTENSION: &str = "monkey";
+
+thread_local! {
+    /// Modules already evaluated on this thread, by canonical path.
+    static CACHE: StdRefCell<HashMap<PathBuf, Rc<Object>>> = StdRefCell::new(HashMap::new());
+    /// Files currently being evaluated, innermost last. Relative imports resolve against the
+    /// directory of the innermost one.
+    static LOADING: StdRefCell<Vec<PathBuf>> = const { StdRefCell::new(Vec::new()) };
+}
+
+/// Loads the module at `path`, evaluating it the first time it is imported, and returns its
+/// exported bindings as a hash.
+pub fn import(path: &str) -> EvalResponse {
+    let resolved = resolve(path)?;
+    if let Some(module) = CACHE.with(|cache| cache.borrow().get(&resolved).cloned()) {
+        return Ok(module);
+    }
+    if let Some(start) = LOADING.with(|loading| loading.borrow().iter().position(|p| *p == resolved)) {
+        let chain = LOADING.with(|loading| {
+            loading.borrow()[start..]
+                .iter()
+                .chain(std::iter::once(&resolved))
+                .map(|p| p.display().to_string())
+                .collect()
+        });
+        return Err(EvalError::CircularImport(chain));
+    }
+
+    let source = std::fs::read_to_string(&resolved).map_err(|e| EvalError::ImportFailed {
+        path: path.to_string(),
+        reason: e.to_string(),
+    })?;
+    let mut parser = Parser::new(Lexer::new(&source));
+    let mut program = parser.parse_program();
+    if !parser.parse_errors.is_empty() {
+        return Err(EvalError::ImportFailed {
+            path: path.to_string(),
+            reason: parser
+                .parse_errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join("; "),
+        });
+    }
+
+    debug::hide(&mut program);
+    let module = with_current_file(resolved.clone(), || eval_module(&program))?;
+    CACHE.with(|cache| cache.borrow_mut().insert(resolved, module.clone()));
+    Ok(module)
+}
+
+/// Name a module is bound to when imported without `as`: its file name minus the extension.
+pub fn default_binding(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string())
+}
+
+/// Runs `f` with `file` as the file relative imports are resolved against.
+pub fn with_current_file<T>(file: PathBuf, f: impl FnOnce() -> T) -> T {
+    LOADING.with(|loading| loading.borrow_mut().push(file));
+    let result = f();
+    LOADING.with(|loading| loading.borrow_mut().pop());
+    result
+}
+
+/// Forgets every cached module on this thread so the next import reads the file again.
+pub fn clear_cache() {
+    CACHE.with(|cache| cache.borrow_mut().clear());
+}
+
+fn resolve(path: &str) -> Result<PathBuf, EvalError> {
+    let base = LOADING
+        .with(|loading| {
+            loading
+                .borrow()
+                .last()
+                .and_then(|file| file.parent().map(Path::to_path_buf))
+        })
+        .unwrap_or_default();
+    let mut candidate = base.join(path);
+    if !candidate.exists() && candidate.extension().is_none() {
+        candidate.set_extension(MODULE_EXTENSION);
+    }
+    candidate.canonicalize().map_err(|e| EvalError::ImportFailed {
+        path: path.to_string(),
+        reason: e.to_string(),
+    })
+}
+
+fn eval_module(program: &Program) -> EvalResponse {
+    let env = gc::track(Environment::try_new_with_builtin()?);
+    eval(Node::Program(program.clone()), &env)?;
+
+    let mut exports = HashMap::new();
+    for statement in &program.statements {
+        let Statement::Export(exported) = statement else {
+            continue;
+        };
+        let mut declared = exported.as_ref();
+        while let Statement::FnDeclaration(inner) | Statement::Const(inner) = declared {
+            declared = inner;
+        }
+        let names = match declared {
+            Statement::Let(name, _, _) => vec![name],
+            Statement::LetPattern(pattern, _) => pattern.names(),
+            _ => Vec::new(),
+        };
+        for name in names {
+            let key: Rc<Object> = Rc::new(name.as_str().into());
+            let value = env.borrow().get(name).unwrap_or_default();
+            exports.insert(key.hash_key()?, HashPair { key, value });
+        }
+    }
+    Ok(Object::Hash(exports).into())
+}
@@ -1,8 +1,7 @@
-use std::rc::Rc;
-
 use parser::ast::{BlockStatement, Expression, Program, Statement};
 
 use crate::object::Object;
+use crate::sync::Rc;
 
 pub enum Node {
     BlockStatement(BlockStatement),
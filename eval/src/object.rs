@@ -1,17 +1,42 @@
 use itertools::Itertools;
-use parser::ast::{BlockStatement, Identifier};
-use std::cell::RefCell;
+use parser::ast::{BlockStatement, Identifier, Literal, Param};
+use std::any::Any;
 use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::ops::{Add, Div, Mul, Sub};
-use std::rc::Rc;
 
+use crate::backtrace::Call;
 use crate::environment::Environment;
 use crate::eval::EvalResponse;
 use crate::eval_error::EvalError;
+use crate::sync::{MaybeSendSync, RefCell, Rc};
 pub type BuiltinFn = fn(&[Rc<Object>]) -> EvalResponse;
+pub type NativeFnPtr = Rc<dyn NativeCallable>;
+pub type NativeDisplayFn = Rc<dyn NativeDisplay>;
+pub type NativeEqFn = Rc<dyn NativeEq>;
+
+/// Anything callable as a native function.
+pub trait NativeCallable: Fn(&[Rc<Object>]) -> EvalResponse + MaybeSendSync {}
+impl<F: Fn(&[Rc<Object>]) -> EvalResponse + MaybeSendSync> NativeCallable for F {}
+
+pub trait NativeDisplay: Fn(&dyn Any) -> String + MaybeSendSync {}
+impl<F: Fn(&dyn Any) -> String + MaybeSendSync> NativeDisplay for F {}
+
+pub trait NativeEq: Fn(&dyn Any, &dyn Any) -> bool + MaybeSendSync {}
+impl<F: Fn(&dyn Any, &dyn Any) -> bool + MaybeSendSync> NativeEq for F {}
+
+/// A Rust value that can be stored in a `NativeObject`.
+pub trait NativeValue: Any + MaybeSendSync {
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: Any + MaybeSendSync> NativeValue for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
 
 #[derive(Debug, PartialEq, Default)]
 pub enum Object {
@@ -21,10 +46,247 @@ pub enum Object {
     Int(i64),
     Bool(bool),
     Return(Rc<Object>),
-    Function(Vec<Identifier>, BlockStatement, Rc<RefCell<Environment>>),
-    Builtin(BuiltinFn),
+    /// Params, body, the environment the function closes over and the name of the `let` it
+    /// was defined in, if any.
+    Function(
+        Vec<Param>,
+        BlockStatement,
+        Rc<RefCell<Environment>>,
+        Option<Identifier>,
+    ),
+    NativeFn(NativeFn),
+    Native(NativeObject),
     Array(Vec<Rc<Object>>),
     Hash(HashMap<HashKey, HashPair>),
+    Error(ErrorValue),
+    /// `ok(value)` or `err(error)`.
+    Result(Result<Rc<Object>, Rc<Object>>),
+}
+
+/// A runtime error caught by `catch`, or a value made by the `error` builtin. Scripts read
+/// its fields by indexing: `e["message"]`, `e["kind"]` and `e["trace"]`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ErrorValue {
+    /// The `EvalError` variant it was made from, or `Error` for `error(msg)`.
+    pub kind: String,
+    pub message: String,
+    /// The calls the error left before it was caught, innermost first.
+    pub trace: Vec<Call>,
+}
+
+impl ErrorValue {
+    pub fn new(message: impl Into<String>) -> Self {
+        ErrorValue {
+            kind: "Error".to_string(),
+            message: message.into(),
+            trace: Vec::new(),
+        }
+    }
+
+    /// The value of `error[key]`.
+    pub fn field(&self, key: &str) -> Option<Object> {
+        match key {
+            "kind" => Some(self.kind.as_str().into()),
+            "message" => Some(self.message.as_str().into()),
+            "trace" => Some(self.trace.iter().map(Call::to_string).collect::<Vec<_>>().into()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ErrorValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.kind, self.message)
+    }
+}
+
+/// A function implemented in Rust, either one of the builtins or one registered by the host.
+/// It can capture state (a counter, a database handle, ...) from wherever it was created.
+#[derive(Clone)]
+pub struct NativeFn {
+    pub name: String,
+    /// Number of arguments the function takes, or `None` if it accepts any number.
+    pub arity: Option<usize>,
+    pub func: NativeFnPtr,
+}
+
+impl NativeFn {
+    pub fn new(
+        name: impl Into<String>,
+        arity: Option<usize>,
+        func: impl Fn(&[Rc<Object>]) -> EvalResponse + MaybeSendSync + 'static,
+    ) -> Self {
+        NativeFn {
+            name: name.into(),
+            arity,
+            func: Rc::new(func),
+        }
+    }
+
+    pub fn call(&self, args: &[Rc<Object>]) -> EvalResponse {
+        if let Some(expected) = self.arity {
+            if expected != args.len() {
+                return Err(EvalError::InvalidParamCount {
+                    expected,
+                    actual: args.len(),
+                });
+            }
+        }
+        (self.func)(args)
+    }
+}
+
+impl fmt::Display for NativeFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.arity {
+            Some(arity) => write!(f, "fn {}({})", self.name, vec!["_"; arity].join(", ")),
+            None => write!(f, "fn {}(...)", self.name),
+        }
+    }
+}
+
+impl fmt::Debug for NativeFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NativeFn({})", self.name)
+    }
+}
+
+impl PartialEq for NativeFn {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.func, &other.func)
+    }
+}
+
+/// Describes a kind of host value that scripts can hold onto without converting it, e.g. a
+/// file handle or a game entity. Shared by every `NativeObject` of that kind.
+pub struct NativeType {
+    pub name: String,
+    /// Methods reachable with index syntax (`file["read"]()`). They receive the object itself
+    /// as their first argument.
+    pub methods: HashMap<String, NativeFn>,
+    pub display: Option<NativeDisplayFn>,
+    pub eq: Option<NativeEqFn>,
+}
+
+impl NativeType {
+    pub fn new(name: impl Into<String>) -> Self {
+        NativeType {
+            name: name.into(),
+            methods: HashMap::new(),
+            display: None,
+            eq: None,
+        }
+    }
+
+    /// Adds a method taking `arity` arguments, not counting the object it is called on.
+    pub fn with_method(
+        mut self,
+        name: &str,
+        arity: Option<usize>,
+        func: impl Fn(&[Rc<Object>]) -> EvalResponse + MaybeSendSync + 'static,
+    ) -> Self {
+        let method = NativeFn::new(name, arity.map(|a| a + 1), func);
+        self.methods.insert(name.to_string(), method);
+        self
+    }
+
+    pub fn with_display<T: Any>(
+        mut self,
+        display: impl Fn(&T) -> String + MaybeSendSync + 'static,
+    ) -> Self {
+        self.display = Some(Rc::new(move |value: &dyn Any| match value.downcast_ref::<T>() {
+            Some(v) => display(v),
+            None => String::new(),
+        }));
+        self
+    }
+
+    pub fn with_eq<T: Any>(
+        mut self,
+        eq: impl Fn(&T, &T) -> bool + MaybeSendSync + 'static,
+    ) -> Self {
+        self.eq = Some(Rc::new(move |l: &dyn Any, r: &dyn Any| {
+            match (l.downcast_ref::<T>(), r.downcast_ref::<T>()) {
+                (Some(l), Some(r)) => eq(l, r),
+                _ => false,
+            }
+        }));
+        self
+    }
+}
+
+/// A host value living inside the interpreter. Builtins get the Rust value back with
+/// `downcast_ref`.
+#[derive(Clone)]
+pub struct NativeObject {
+    pub native_type: Rc<NativeType>,
+    pub value: Rc<dyn NativeValue>,
+}
+
+impl NativeObject {
+    pub fn new(native_type: &Rc<NativeType>, value: impl NativeValue) -> Self {
+        NativeObject {
+            native_type: native_type.clone(),
+            value: Rc::new(value),
+        }
+    }
+
+    pub fn type_name(&self) -> &str {
+        &self.native_type.name
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        NativeValue::as_any(self.value.as_ref()).downcast_ref::<T>()
+    }
+
+    /// Looks up `name` in the type's method table, returning it bound to `this`.
+    pub fn method(&self, this: &Rc<Object>, name: &str) -> Option<NativeFn> {
+        let method = self.native_type.methods.get(name)?.clone();
+        let this = this.clone();
+        Some(NativeFn::new(
+            format!("{}.{}", self.type_name(), name),
+            method.arity.map(|a| a - 1),
+            move |args: &[Rc<Object>]| {
+                let mut bound = Vec::with_capacity(args.len() + 1);
+                bound.push(this.clone());
+                bound.extend_from_slice(args);
+                method.call(&bound)
+            },
+        ))
+    }
+}
+
+impl fmt::Debug for NativeObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NativeObject({})", self.type_name())
+    }
+}
+
+impl PartialEq for NativeObject {
+    fn eq(&self, other: &Self) -> bool {
+        if Rc::ptr_eq(&self.value, &other.value) {
+            return true;
+        }
+        if !Rc::ptr_eq(&self.native_type, &other.native_type) {
+            return false;
+        }
+        match &self.native_type.eq {
+            Some(eq) => eq(
+                NativeValue::as_any(self.value.as_ref()),
+                NativeValue::as_any(other.value.as_ref()),
+            ),
+            None => false,
+        }
+    }
+}
+
+impl fmt::Display for NativeObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.native_type.display {
+            Some(display) => write!(f, "{}", display(NativeValue::as_any(self.value.as_ref()))),
+            None => write!(f, "<{}>", self.type_name()),
+        }
+    }
 }
 
 #[derive(Debug, Default)]
@@ -58,6 +320,23 @@ impl From<u64> for HashKey {
 }
 
 impl Object {
+    /// Name of the value's type, as reported by the `type` builtin.
+    pub fn type_name(&self) -> &str {
+        match self {
+            Object::Null => "Null",
+            Object::String(_) => "String",
+            Object::Int(_) => "Int",
+            Object::Bool(_) => "Bool",
+            Object::Return(inner) => inner.type_name(),
+            Object::Function(..) | Object::NativeFn(_) => "Function",
+            Object::Native(native) => native.type_name(),
+            Object::Array(_) => "Array",
+            Object::Hash(_) => "Hash",
+            Object::Error(_) => "Error",
+            Object::Result(_) => "Result",
+        }
+    }
+
     pub fn hash_key(&self) -> Result<HashKey,EvalError> {
         let mut hasher = DefaultHasher::new();
         match self {
@@ -80,6 +359,12 @@ impl Object {
     }
 }
 
+impl From<NativeObject> for Object {
+    fn from(v: NativeObject) -> Self {
+        Object::Native(v)
+    }
+}
+
 impl From<i64> for Object {
     fn from(v: i64) -> Self {
         Object::Int(v)
@@ -98,12 +383,144 @@ impl<'a> From<&'a str> for Object {
     }
 }
 
+impl From<String> for Object {
+    fn from(s: String) -> Self {
+        Object::String(s)
+    }
+}
+
+impl From<&Literal> for Object {
+    fn from(literal: &Literal) -> Self {
+        match literal {
+            Literal::Int(i) => Object::Int(*i),
+            Literal::String(s) => Object::String(s.clone()),
+            Literal::Bool(b) => Object::Bool(*b),
+        }
+    }
+}
+
+impl From<()> for Object {
+    fn from(_: ()) -> Self {
+        Object::Null
+    }
+}
+
+impl<T: Into<Object>> From<Option<T>> for Object {
+    fn from(v: Option<T>) -> Self {
+        match v {
+            Some(v) => v.into(),
+            None => Object::Null,
+        }
+    }
+}
+
+impl<T: Into<Object>> From<Vec<T>> for Object {
+    fn from(v: Vec<T>) -> Self {
+        Object::Array(v.into_iter().map(|x| Rc::new(x.into())).collect())
+    }
+}
+
+macro_rules! impl_from_hash_map {
+    ($($key:ty),*) => {
+        $(
+            impl<V: Into<Object>> From<HashMap<$key, V>> for Object {
+                fn from(map: HashMap<$key, V>) -> Self {
+                    let mut hash = HashMap::new();
+                    for (k, v) in map {
+                        let key: Rc<Object> = Rc::new(k.into());
+                        let hash_key = key
+                            .hash_key()
+                            .expect("strings, ints and bools are always valid hash keys");
+                        hash.insert(hash_key, HashPair { key, value: Rc::new(v.into()) });
+                    }
+                    Object::Hash(hash)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_hash_map!(String, &str, i64, bool);
+
+impl TryFrom<&Object> for i64 {
+    type Error = EvalError;
+
+    fn try_from(obj: &Object) -> Result<Self, Self::Error> {
+        match obj {
+            Object::Int(i) => Ok(*i),
+            o => Err(EvalError::InvalidObjectType("Int".into(), o.to_string())),
+        }
+    }
+}
+
+impl TryFrom<&Object> for bool {
+    type Error = EvalError;
+
+    fn try_from(obj: &Object) -> Result<Self, Self::Error> {
+        match obj {
+            Object::Bool(b) => Ok(*b),
+            o => Err(EvalError::InvalidObjectType("Bool".into(), o.to_string())),
+        }
+    }
+}
+
+impl TryFrom<&Object> for String {
+    type Error = EvalError;
+
+    fn try_from(obj: &Object) -> Result<Self, Self::Error> {
+        match obj {
+            Object::String(s) => Ok(s.clone()),
+            o => Err(EvalError::InvalidObjectType("String".into(), o.to_string())),
+        }
+    }
+}
+
+impl<T> TryFrom<&Object> for Vec<T>
+where
+    T: for<'a> TryFrom<&'a Object, Error = EvalError>,
+{
+    type Error = EvalError;
+
+    fn try_from(obj: &Object) -> Result<Self, Self::Error> {
+        match obj {
+            Object::Array(a) => a.iter().map(|x| T::try_from(x.as_ref())).collect(),
+            o => Err(EvalError::InvalidObjectType("Array".into(), o.to_string())),
+        }
+    }
+}
+
+impl<K, V> TryFrom<&Object> for HashMap<K, V>
+where
+    K: for<'a> TryFrom<&'a Object, Error = EvalError> + Eq + Hash,
+    V: for<'a> TryFrom<&'a Object, Error = EvalError>,
+{
+    type Error = EvalError;
+
+    fn try_from(obj: &Object) -> Result<Self, Self::Error> {
+        match obj {
+            Object::Hash(h) => h
+                .values()
+                .map(|pair| Ok((K::try_from(pair.key.as_ref())?, V::try_from(pair.value.as_ref())?)))
+                .collect(),
+            o => Err(EvalError::InvalidObjectType("Hash".into(), o.to_string())),
+        }
+    }
+}
+
+/// The result of integer arithmetic, or an overflow error for `l op r`.
+fn checked(result: Option<i64>, l: &i64, op: &str, r: &i64) -> Result<Rc<Object>, EvalError> {
+    match result {
+        Some(value) => Ok(Object::Int(value).into()),
+        None => Err(EvalError::IntegerOverflow(format!("{l} {op} {r}"))),
+    }
+}
+
 impl Add for &Object {
     type Output = Result<Rc<Object>, EvalError>;
 
     fn add(self, rhs: Self) -> Result<Rc<Object>, EvalError> {
         match (self, rhs) {
-            (Object::Int(l), Object::Int(r)) => Ok(Object::Int(*l + *r).into()),
+            (Object::Int(l), Object::Int(r)) => checked(l.checked_add(*r), l, "+", r),
             (Object::String(l), Object::String(r)) => Ok(Object::String(format!("{l}{r}")).into()),
             (Object::Int(l), rhs) => Err(EvalError::TypeMismatch(l.to_string(), rhs.to_string())),
             (lhs, rhs) => Err(EvalError::InvalidOperator(
@@ -120,7 +537,7 @@ impl Sub for &Object {
 
     fn sub(self, rhs: Self) -> Result<Rc<Object>, EvalError> {
         match (self, rhs) {
-            (Object::Int(l), Object::Int(r)) => Ok(Object::Int(*l - *r).into()),
+            (Object::Int(l), Object::Int(r)) => checked(l.checked_sub(*r), l, "-", r),
             (Object::Int(l), rhs) => Err(EvalError::TypeMismatch(l.to_string(), rhs.to_string())),
             (lhs, rhs) => Err(EvalError::InvalidOperator(
                 lhs.to_string(),
@@ -136,7 +553,8 @@ impl Div for &Object {
 
     fn div(self, rhs: Self) -> Result<Rc<Object>, EvalError> {
         match (self, rhs) {
-            (Object::Int(l), Object::Int(r)) => Ok(Object::Int(*l / *r).into()),
+            (Object::Int(_), Object::Int(0)) => Err(EvalError::DivisionByZero),
+            (Object::Int(l), Object::Int(r)) => checked(l.checked_div(*r), l, "/", r),
             (Object::Int(lhs), rhs) => {
                 Err(EvalError::TypeMismatch(lhs.to_string(), rhs.to_string()))
             }
@@ -154,7 +572,7 @@ impl Mul for &Object {
 
     fn mul(self, rhs: Self) -> Result<Rc<Object>, EvalError> {
         match (self, rhs) {
-            (Object::Int(l), Object::Int(r)) => Ok(Object::Int(*l * *r).into()),
+            (Object::Int(l), Object::Int(r)) => checked(l.checked_mul(*r), l, "*", r),
             (Object::Int(lhs), rhs) => {
                 Err(EvalError::TypeMismatch(lhs.to_string(), rhs.to_string()))
             }
@@ -175,10 +593,21 @@ impl fmt::Display for Object {
             Object::Bool(b) => write!(f, "{}", b),
             Object::Return(r) => write!(f, "return {}", r),
             Object::Null => write!(f, "null"),
-            Object::Function(idents, blk, _) => write!(f, "fn({}) {}", idents.join(" ,"), blk),
-            Object::Builtin(func) => write!(f, "fn({:?}) ", func),
+            Object::Function(params, blk, _, name) => {
+                let body = blk.statements.iter().format("");
+                let params = params.iter().format(" ,");
+                match name {
+                    Some(name) => write!(f, "fn {name}({params}) {body}"),
+                    None => write!(f, "fn({params}) {body}"),
+                }
+            }
+            Object::NativeFn(native) => write!(f, "{native}"),
+            Object::Native(native) => write!(f, "{native}"),
             Object::Array(array) => write!(f, "[{}] ", array.iter().format(", ")),
             Object::Hash(hash) => write!(f, "{:?}", hash),
+            Object::Error(error) => write!(f, "{error}"),
+            Object::Result(Ok(value)) => write!(f, "ok({value})"),
+            Object::Result(Err(error)) => write!(f, "err({error})"),
         }
     }
 }
@@ -0,0 +1,281 @@
+//! An optimization pass over a parsed program that leaves its results unchanged:
+//!
+//! - infix and prefix operators applied to literals are evaluated ahead of time, with the
+//!   evaluator's own operator code, so `1 + 2 * 3` becomes `7` and `!true` becomes `false`;
+//! - `if` expressions whose condition is a literal lose the branch that can never run, and at
+//!   statement level the remaining branch is spliced into the enclosing block;
+//! - a `let` bound to a literal is inlined into the code after it, as long as that name is bound
+//!   nowhere else in the program, so no closure could see a different value.
+//!
+//! Operations that would fail (`1 + true`), overflow or divide by zero are left for the
+//! evaluator, so they still fail when and only when they run. The pass assumes `program` is the
+//! whole program: a later `let` of the same name from another `Interpreter::run` could change
+//! what an inlined name referred to.
+
+use std::collections::HashMap;
+
+use lexer::token::Token;
+use parser::ast::{
+    BlockStatement, Expression, Fold, Identifier, MatchArm, Param, Pattern, Program, Signature,
+    Statement, Type, Visitor,
+};
+
+use crate::eval::{
+    eval_bang_operator_expression, eval_infix_objects, eval_minus_operator_expression, is_truthy,
+};
+use crate::object::Object;
+use crate::sync::Rc;
+
+pub fn optimize(program: Program) -> Program {
+    let mut optimizer = Optimizer {
+        bindings: count_bindings(&program),
+        constants: Vec::new(),
+    };
+    optimizer.fold_program(program)
+}
+
+/// How many times each name is bound anywhere in `program`, by `let`, as a param or by an
+/// import.
+pub(crate) fn count_bindings(program: &Program) -> HashMap<Identifier, usize> {
+    let mut bindings = Bindings::default();
+    bindings.visit_program(program);
+    bindings.0
+}
+
+#[derive(Default)]
+struct Bindings(HashMap<Identifier, usize>);
+
+impl Bindings {
+    fn bind(&mut self, name: &Identifier) {
+        *self.0.entry(name.clone()).or_default() += 1;
+    }
+}
+
+impl Visitor for Bindings {
+    fn visit_let(&mut self, name: &Identifier, _ty: Option<&Type>, value: &Expression) {
+        self.bind(name);
+        self.visit_expression(value);
+    }
+
+    fn visit_import(&mut self, path: &str, alias: Option<&Identifier>) {
+        let name = alias
+            .cloned()
+            .unwrap_or_else(|| crate::modules::default_binding(path));
+        self.bind(&name);
+    }
+
+    fn visit_let_pattern(&mut self, pattern: &Pattern, value: &Expression) {
+        for name in pattern.names() {
+            self.bind(name);
+        }
+        self.visit_expression(value);
+    }
+
+    fn visit_match(&mut self, value: &Expression, arms: &[MatchArm]) {
+        self.visit_expression(value);
+        for arm in arms {
+            for name in arm.pattern.names() {
+                self.bind(name);
+            }
+            if let Some(guard) = &arm.guard {
+                self.visit_expression(guard);
+            }
+            self.visit_expression(&arm.value);
+        }
+    }
+
+    fn visit_try(
+        &mut self,
+        body: &BlockStatement,
+        catch: Option<(&Pattern, &BlockStatement)>,
+        finally: Option<&BlockStatement>,
+    ) {
+        self.visit_block(body);
+        if let Some((name, handler)) = catch {
+            for name in name.names() {
+                self.bind(name);
+            }
+            self.visit_block(handler);
+        }
+        if let Some(finally) = finally {
+            self.visit_block(finally);
+        }
+    }
+
+    fn visit_fn(
+        &mut self,
+        params: &[Param],
+        body: &BlockStatement,
+        _signature: Option<&Signature>,
+    ) {
+        for param in params {
+            for name in param.names() {
+                self.bind(name);
+            }
+            if let Some(default) = &param.default {
+                self.visit_expression(default);
+            }
+        }
+        self.visit_block(body);
+    }
+}
+
+struct Optimizer {
+    bindings: HashMap<Identifier, usize>,
+    /// Literal `let`s seen so far in each enclosing block, innermost last.
+    constants: Vec<HashMap<Identifier, Expression>>,
+}
+
+impl Optimizer {
+    fn constant(&self, name: &str) -> Option<&Expression> {
+        self.constants
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+    }
+}
+
+/// Whether `statement` declares a function with `fn name() {}`, which is hoisted to the start
+/// of its block.
+fn is_fn_declaration(statement: &Statement) -> bool {
+    match statement {
+        Statement::FnDeclaration(_) => true,
+        Statement::Export(exported) => is_fn_declaration(exported),
+        _ => false,
+    }
+}
+
+impl Fold for Optimizer {
+    fn fold_program(&mut self, program: Program) -> Program {
+        self.fold_block(program)
+    }
+
+    fn fold_block(&mut self, block: BlockStatement) -> BlockStatement {
+        self.constants.push(HashMap::new());
+        let count = block.statements.len();
+        let mut statements = Vec::with_capacity(count);
+        let mut lines = Vec::with_capacity(count);
+        for (i, statement) in block.statements.into_iter().enumerate() {
+            let last = i + 1 == count;
+            let line = block.lines.get(i).copied().unwrap_or(0);
+            let statement = if is_fn_declaration(&statement) {
+                // Declarations are hoisted, so they can run before the block's earlier `let`s.
+                let scope = self.constants.last_mut().expect("pushed above");
+                let hidden = std::mem::take(scope);
+                let statement = self.fold_statement(statement);
+                *self.constants.last_mut().expect("pushed above") = hidden;
+                statement
+            } else {
+                self.fold_statement(statement)
+            };
+            match statement {
+                Statement::Let(name, ty, value) => {
+                    if to_object(&value).is_some() && self.bindings.get(&name) == Some(&1) {
+                        let scope = self.constants.last_mut().expect("pushed above");
+                        scope.insert(name.clone(), value.clone());
+                    }
+                    statements.push(Statement::Let(name, ty, value));
+                    lines.push(line);
+                }
+                // `if` blocks run in the enclosing environment, so a branch that always runs
+                // can take the place of the `if`. An empty one is only kept when it gives the
+                // block its (null) value, and one declaring functions, which would then be
+                // hoisted to the start of the enclosing block, is always kept.
+                Statement::ExpressionStatement(Expression::IfExpression(cond, branch, None))
+                    if to_object(&cond).is_some()
+                        && (!last || !branch.statements.is_empty())
+                        && !branch.statements.iter().any(is_fn_declaration) =>
+                {
+                    if is_truthy(to_object(&cond).expect("checked above")) {
+                        let branch_lines = (0..branch.statements.len()).map(|i| branch.line(i));
+                        lines.extend(branch_lines);
+                        statements.extend(branch.statements);
+                    }
+                }
+                statement => {
+                    statements.push(statement);
+                    lines.push(line);
+                }
+            }
+        }
+        self.constants.pop();
+        BlockStatement { statements, lines }
+    }
+
+    fn fold_identifier(&mut self, name: Identifier) -> Expression {
+        match self.constant(&name) {
+            Some(value) => value.clone(),
+            None => Expression::Identifier(name),
+        }
+    }
+
+    fn fold_prefix(&mut self, op: Token, right: Expression) -> Expression {
+        let right = self.fold_expression(right);
+        let folded = match (&op, to_object(&right)) {
+            (Token::Bang, Some(value)) => eval_bang_operator_expression(value).ok(),
+            (Token::Dash, Some(value)) => eval_minus_operator_expression(value).ok(),
+            _ => None,
+        };
+        folded
+            .and_then(|value| to_expression(&value))
+            .unwrap_or_else(|| Expression::PrefixExpression(op, Box::new(right)))
+    }
+
+    fn fold_infix(&mut self, op: Token, left: Expression, right: Expression) -> Expression {
+        let left = self.fold_expression(left);
+        let right = self.fold_expression(right);
+        let folded = match (to_object(&left), to_object(&right)) {
+            (Some(l), Some(r)) => eval_infix_objects(&op, l, r).ok(),
+            _ => None,
+        };
+        folded
+            .and_then(|value| to_expression(&value))
+            .unwrap_or_else(|| Expression::InfixExpression(op, Box::new(left), Box::new(right)))
+    }
+
+    fn fold_if(
+        &mut self,
+        cond: Expression,
+        if_block: BlockStatement,
+        else_block: Option<BlockStatement>,
+    ) -> Expression {
+        let cond = self.fold_expression(cond);
+        let Some(value) = to_object(&cond) else {
+            let if_block = self.fold_block(if_block);
+            let else_block = else_block.map(|block| self.fold_block(block));
+            return Expression::IfExpression(Box::new(cond), if_block, else_block);
+        };
+        // Keep only the branch that runs, as the `then` branch of an `if` that always takes it.
+        let (cond, branch) = match (is_truthy(value), else_block) {
+            (true, _) => (cond, self.fold_block(if_block)),
+            (false, Some(else_block)) => (Expression::Bool(true), self.fold_block(else_block)),
+            (false, None) => (
+                cond,
+                BlockStatement {
+                    statements: vec![],
+                    lines: vec![],
+                },
+            ),
+        };
+        Expression::IfExpression(Box::new(cond), branch, None)
+    }
+}
+
+/// The value of a literal expression.
+fn to_object(expression: &Expression) -> Option<Rc<Object>> {
+    match expression {
+        Expression::IntLiteral(i) => Some(Object::Int(*i).into()),
+        Expression::StringLiteral(s) => Some(Object::String(s.clone()).into()),
+        Expression::Bool(b) => Some(Object::Bool(*b).into()),
+        _ => None,
+    }
+}
+
+fn to_expression(value: &Object) -> Option<Expression> {
+    match value {
+        Object::Int(i) => Some(Expression::IntLiteral(*i)),
+        Object::String(s) => Some(Expression::StringLiteral(s.clone())),
+        Object::Bool(b) => Some(Expression::Bool(*b)),
+        _ => None,
+    }
+}
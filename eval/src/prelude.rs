@@ -0,0 +1,78 @@
+//! The standard library written in Monkey. Its source is bundled into the binary and evaluated
+//! once per thread, the first time an environment is created with
+//! `Environment::new_with_builtin`; later environments get copies of the same bindings.
+
+use std::cell::OnceCell;
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+
+use lexer::lexer::Lexer;
+use parser::ast::{Expression, Param, Program, Statement};
+use parser::parser::Parser;
+
+use crate::debug;
+use crate::environment::Environment;
+use crate::eval::eval_with_limits;
+use crate::eval_error::EvalError;
+use crate::gc;
+use crate::limits::Limits;
+use crate::node::Node;
+use crate::object::Object;
+use crate::sync::Rc;
+
+pub const SOURCE: &str = include_str!("prelude.monkey");
+
+/// The parsed prelude, or its parse errors.
+static PROGRAM: Lazy<Result<Program, String>> = Lazy::new(|| {
+    let mut parser = Parser::new(Lexer::new(SOURCE));
+    let mut program = parser.parse_program();
+    if !parser.parse_errors.is_empty() {
+        return Err(format!("{:?}", parser.parse_errors));
+    }
+    debug::hide(&mut program);
+    Ok(program)
+});
+
+type Bindings = HashMap<String, Rc<Object>>;
+
+thread_local! {
+    /// The prelude's bindings, or why it failed to load.
+    static LOADED: OnceCell<Result<Bindings, String>> = const { OnceCell::new() };
+}
+
+/// Names the prelude defines, with the number of params of those bound to a function.
+pub(crate) fn arities() -> HashMap<String, Option<usize>> {
+    let Ok(program) = PROGRAM.as_ref() else {
+        return HashMap::new();
+    };
+    program
+        .statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::Let(name, _, Expression::FnExpression(params, ..)) => {
+                Some((name.clone(), Param::fixed_arity(params)))
+            }
+            Statement::Let(name, ..) => Some((name.clone(), None)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The Rust builtins and everything the prelude binds. The prelude is evaluated into its own
+/// environment holding only the Rust builtins, so its functions keep working however their
+/// caller shadows the names they use. It runs without the caller's limits, as it would
+/// otherwise fail whenever it's first needed under a tight budget.
+pub(crate) fn load() -> Result<Bindings, EvalError> {
+    LOADED.with(|loaded| loaded.get_or_init(evaluate).clone())
+        .map_err(EvalError::PreludeFailed)
+}
+
+fn evaluate() -> Result<Bindings, String> {
+    let program = PROGRAM.as_ref()?;
+    let env = gc::track(Environment::new_without_prelude());
+    eval_with_limits(Node::Program(program.clone()), &env, Limits::default())
+        .map_err(|e| e.to_string())?;
+    let bindings = env.borrow().bindings().map(|(k, v)| (k.clone(), v.clone())).collect();
+    Ok(bindings)
+}
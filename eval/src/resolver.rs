@@ -0,0 +1,423 @@
+//! Static scope resolution, run on every tree before it is evaluated.
+//!
+//! Inside a function, each name bound by a param or by a `let` anywhere in its body (`if` blocks
+//! included, nested functions not) gets a slot in the function's frame, params first. The names
+//! a `match` arm or a `catch` binds get slots of their own after those, which only the arm's
+//! guard and value, or the handler, see, so they don't outlive it. Uses of the name become `Expression::Local`s pointing at
+//! that frame and slot, and its `let`s become `Statement::LetLocal`s (and patterns binding it
+//! `Pattern::Local`s), so the evaluator reads and writes locals by index instead of hashing the
+//! name through a chain of scopes. Reading a local before its `let` has run is still an error.
+//!
+//! Names no enclosing function binds are globals and stay `Expression::Identifier`s, since the
+//! REPL, the host and `import`s keep adding globals as they run. A global that is neither in the
+//! environment the program runs in nor bound anywhere at the program's top level could never be
+//! found, so it is reported before anything runs, as is a block binding a name again after a
+//! `const` of its own (or, at the top level, the environment) made it a constant.
+
+use std::collections::{HashMap, HashSet};
+
+use parser::ast::{
+    BlockStatement, Expression, Fold, Identifier, MatchArm, Param, Pattern, Program, Signature,
+    Statement, Type, Visitor,
+};
+
+use crate::environment::Env;
+use crate::eval_error::EvalError;
+use crate::node::Node;
+
+/// Resolves `program` for running in `env`.
+pub fn resolve(program: Program, env: &Env) -> Result<Program, EvalError> {
+    if let Some(name) = rebound_constant(&program, |name| env.borrow().is_constant(name)) {
+        return Err(EvalError::AssignToConstant(name));
+    }
+    let mut declarations = Declarations::default();
+    declarations.visit_program(&program);
+    let mut resolver = Resolver {
+        env,
+        globals: declarations
+            .lets
+            .into_iter()
+            .chain(declarations.named)
+            .chain(declarations.arms)
+            .collect(),
+        scopes: Vec::new(),
+        error: None,
+    };
+    let program = resolver.fold_program(program);
+    match resolver.error {
+        Some(e) => Err(e),
+        None => Ok(program),
+    }
+}
+
+/// Resolves any node `eval` accepts, as if it were a whole program.
+pub(crate) fn resolve_node(node: Node, env: &Env) -> Result<Node, EvalError> {
+    let resolve_statement = |statement| {
+        let mut program = resolve(
+            BlockStatement {
+                statements: vec![statement],
+                lines: Vec::new(),
+            },
+            env,
+        )?;
+        Ok::<_, EvalError>(program.statements.remove(0))
+    };
+    Ok(match node {
+        Node::Program(p) => Node::Program(resolve(p, env)?),
+        Node::BlockStatement(b) => Node::BlockStatement(resolve(b, env)?),
+        Node::Statement(s) => Node::Statement(resolve_statement(s)?),
+        Node::Expression(e) => match resolve_statement(Statement::ExpressionStatement(e))? {
+            Statement::ExpressionStatement(e) => Node::Expression(e),
+            _ => unreachable!("resolving keeps the kind of every statement"),
+        },
+        Node::Object(o) => Node::Object(o),
+    })
+}
+
+/// Names of the slots of a function with `params` and `body`: its params, then its locals, then
+/// the names bound by its `match` arms and `catch`es.
+pub(crate) fn slot_names(params: &[Param], body: &BlockStatement) -> Vec<Identifier> {
+    let declarations = Declarations::of_function(params, body);
+    let mut names = slots(params, declarations.lets);
+    names.extend(declarations.arms);
+    names
+}
+
+fn slots(params: &[Param], lets: Vec<Identifier>) -> Vec<Identifier> {
+    let mut names: Vec<Identifier> = params.iter().map(|param| param.name.clone()).collect();
+    let locals: Vec<Identifier> = lets.into_iter().filter(|name| !names.contains(name)).collect();
+    names.extend(locals);
+    names
+}
+
+/// The first name a statement of `block` binds that is a constant by then: made one by an
+/// earlier `const` in `block`, or before `block` runs, as `constant` tells. Only the statements
+/// of `block` itself count, since one in a nested block might never run.
+fn rebound_constant(block: &BlockStatement, constant: impl Fn(&str) -> bool) -> Option<Identifier> {
+    let mut constants = HashSet::new();
+    for statement in &block.statements {
+        let (names, is_const) = bound_names(statement);
+        if let Some(name) = names
+            .iter()
+            .find(|name| constants.contains(*name) || constant(name))
+        {
+            return Some(name.clone());
+        }
+        if is_const {
+            constants.extend(names);
+        }
+    }
+    None
+}
+
+/// The names `statement` binds in its block, and whether it makes them constants.
+fn bound_names(statement: &Statement) -> (Vec<Identifier>, bool) {
+    match statement {
+        Statement::Let(name, ..) | Statement::LetLocal(name, ..) => (vec![name.clone()], false),
+        Statement::LetPattern(pattern, _) => {
+            (pattern.names().into_iter().cloned().collect(), false)
+        }
+        Statement::Import(path, alias) => {
+            let name = alias
+                .clone()
+                .unwrap_or_else(|| crate::modules::default_binding(path));
+            (vec![name], false)
+        }
+        Statement::Export(declared) | Statement::FnDeclaration(declared) => bound_names(declared),
+        Statement::Const(declared) => (bound_names(declared).0, true),
+        Statement::Return(_) | Statement::ExpressionStatement(_) | Statement::Throw(_) => {
+            (Vec::new(), false)
+        }
+    }
+}
+
+/// Names bound by `let`, a pattern, `import`, `match` or `catch` in a block, outside any function
+/// nested in it.
+#[derive(Default)]
+struct Declarations {
+    /// `let` and pattern names in the order they first appear.
+    lets: Vec<Identifier>,
+    seen: HashSet<Identifier>,
+    /// Names bound by name rather than in a slot, by `import`.
+    named: Vec<Identifier>,
+    /// Names bound by `match` arms and `catch`es, once for every arm or `catch` binding them.
+    arms: Vec<Identifier>,
+}
+
+impl Declarations {
+    /// The declarations in `params`, their defaults and `body`. The names a destructured param
+    /// binds are locals like those of a `let`.
+    fn of_function(params: &[Param], body: &BlockStatement) -> Self {
+        let mut declarations = Declarations::default();
+        for param in params {
+            if let Some(default) = &param.default {
+                declarations.visit_expression(default);
+            }
+            if let Some(pattern) = &param.pattern {
+                for name in pattern.names() {
+                    if declarations.seen.insert(name.clone()) {
+                        declarations.lets.push(name.clone());
+                    }
+                }
+            }
+        }
+        declarations.visit_block(body);
+        declarations
+    }
+}
+
+impl Visitor for Declarations {
+    fn visit_let(&mut self, name: &Identifier, _ty: Option<&Type>, value: &Expression) {
+        if self.seen.insert(name.clone()) {
+            self.lets.push(name.clone());
+        }
+        self.visit_expression(value);
+    }
+
+    /// In a body that was already resolved, for naming its slots.
+    fn visit_let_local(&mut self, name: &Identifier, _slot: usize, value: &Expression) {
+        self.visit_let(name, None, value);
+    }
+
+    fn visit_import(&mut self, path: &str, alias: Option<&Identifier>) {
+        let name = alias
+            .cloned()
+            .unwrap_or_else(|| crate::modules::default_binding(path));
+        self.named.push(name);
+    }
+
+    fn visit_try(
+        &mut self,
+        body: &BlockStatement,
+        catch: Option<(&Pattern, &BlockStatement)>,
+        finally: Option<&BlockStatement>,
+    ) {
+        self.visit_block(body);
+        if let Some((name, handler)) = catch {
+            self.arms.extend(name.names().into_iter().cloned());
+            self.visit_block(handler);
+        }
+        if let Some(finally) = finally {
+            self.visit_block(finally);
+        }
+    }
+
+    fn visit_let_pattern(&mut self, pattern: &Pattern, value: &Expression) {
+        for name in pattern.names() {
+            if self.seen.insert(name.clone()) {
+                self.lets.push(name.clone());
+            }
+        }
+        self.visit_expression(value);
+    }
+
+    fn visit_match(&mut self, value: &Expression, arms: &[MatchArm]) {
+        self.visit_expression(value);
+        for arm in arms {
+            let start = self.arms.len();
+            for name in arm.pattern.names() {
+                if !self.arms[start..].contains(name) {
+                    self.arms.push(name.clone());
+                }
+            }
+            if let Some(guard) = &arm.guard {
+                self.visit_expression(guard);
+            }
+            self.visit_expression(&arm.value);
+        }
+    }
+
+    fn visit_fn(
+        &mut self,
+        _params: &[Param],
+        _body: &BlockStatement,
+        _signature: Option<&Signature>,
+    ) {
+    }
+}
+
+/// The slots of one function's frame.
+#[derive(Default)]
+struct Scope {
+    slots: HashMap<Identifier, usize>,
+    /// Names the function binds by name (see `Declarations::named`), so these stay identifiers.
+    named: HashSet<Identifier>,
+    /// Slots of the names bound by the `match` arms and `catch`es being resolved, innermost
+    /// last.
+    arms: Vec<HashMap<Identifier, usize>>,
+    /// The first slot not given to any name yet.
+    next_slot: usize,
+}
+
+struct Resolver<'a> {
+    env: &'a Env,
+    /// Names bound at the top level of the program.
+    globals: HashSet<Identifier>,
+    /// Enclosing functions, innermost last.
+    scopes: Vec<Scope>,
+    /// The first undefined name found.
+    error: Option<EvalError>,
+}
+
+impl Fold for Resolver<'_> {
+    fn fold_let(&mut self, name: Identifier, ty: Option<Type>, value: Expression) -> Statement {
+        let value = self.fold_expression(value);
+        match self.scopes.last() {
+            Some(scope) => {
+                let slot = scope.slots[&name];
+                Statement::LetLocal(name, slot, value)
+            }
+            None => Statement::Let(name, ty, value),
+        }
+    }
+
+    fn fold_let_pattern(&mut self, pattern: Pattern, value: Expression) -> Statement {
+        let value = self.fold_expression(value);
+        let slots = self.scopes.last().map(|scope| &scope.slots);
+        Statement::LetPattern(resolve_pattern(pattern, slots), value)
+    }
+
+    fn fold_match(&mut self, value: Expression, arms: Vec<MatchArm>) -> Expression {
+        let value = self.fold_expression(value);
+        let arms = arms.into_iter().map(|arm| self.arm(arm)).collect();
+        Expression::Match(Box::new(value), arms)
+    }
+
+    fn fold_try(
+        &mut self,
+        body: BlockStatement,
+        catch: Option<(Pattern, BlockStatement)>,
+        finally: Option<BlockStatement>,
+    ) -> Expression {
+        let body = self.fold_block(body);
+        let catch = catch.map(|(name, handler)| {
+            let name = self.enter(name);
+            let handler = self.fold_block(handler);
+            self.leave();
+            (name, handler)
+        });
+        let finally = finally.map(|block| self.fold_block(block));
+        Expression::TryExpression(body, catch, finally)
+    }
+
+    fn fold_fn(
+        &mut self,
+        params: Vec<Param>,
+        body: BlockStatement,
+        signature: Option<Signature>,
+    ) -> Expression {
+        if let Some(name) = rebound_constant(&body, |_| false) {
+            self.error.get_or_insert(EvalError::AssignToConstant(name));
+        }
+        let declarations = Declarations::of_function(&params, &body);
+        let mut scope = Scope::default();
+        // A repeated param takes the last argument passed for it, as it did when params were
+        // bound by name one after another.
+        let names = slots(&params, declarations.lets);
+        scope.next_slot = names.len();
+        for (slot, name) in names.into_iter().enumerate() {
+            scope.slots.insert(name, slot);
+        }
+        scope.named.extend(declarations.named);
+
+        // Defaults are evaluated in the call's frame, so they can use the params before them.
+        self.scopes.push(scope);
+        let params = params
+            .into_iter()
+            .map(|param| Param {
+                default: param.default.map(|default| self.fold_expression(default)),
+                pattern: param.pattern.map(|pattern| {
+                    resolve_pattern(pattern, self.scopes.last().map(|scope| &scope.slots))
+                }),
+                ..param
+            })
+            .collect();
+        let body = self.fold_block(body);
+        self.scopes.pop();
+        Expression::FnExpression(params, body, signature)
+    }
+
+    fn fold_identifier(&mut self, name: Identifier) -> Expression {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if let Some(&slot) = scope.arms.iter().rev().find_map(|arm| arm.get(&name)) {
+                return Expression::Local(name, depth, slot);
+            }
+            if scope.named.contains(&name) {
+                return Expression::Identifier(name);
+            }
+            if let Some(&slot) = scope.slots.get(&name) {
+                return Expression::Local(name, depth, slot);
+            }
+        }
+        let defined = self.globals.contains(&name) || self.env.borrow().get(&name).is_some();
+        if !defined && self.error.is_none() {
+            self.error = Some(EvalError::IdentifierNotFount(name.clone()));
+        }
+        Expression::Identifier(name)
+    }
+}
+
+impl Resolver<'_> {
+    /// Resolves a `match` arm, giving the names it binds inside a function fresh slots.
+    fn arm(&mut self, arm: MatchArm) -> MatchArm {
+        let pattern = self.enter(arm.pattern);
+        let guard = arm.guard.map(|guard| self.fold_expression(guard));
+        let value = self.fold_expression(arm.value);
+        self.leave();
+        MatchArm {
+            pattern,
+            guard,
+            value,
+        }
+    }
+
+    /// Gives the names `pattern` binds inside a function fresh slots, seen until `leave`.
+    fn enter(&mut self, pattern: Pattern) -> Pattern {
+        let Some(scope) = self.scopes.last_mut() else {
+            return pattern;
+        };
+        let mut slots = HashMap::new();
+        for name in pattern.names() {
+            slots.entry(name.clone()).or_insert_with(|| {
+                scope.next_slot += 1;
+                scope.next_slot - 1
+            });
+        }
+        let pattern = resolve_pattern(pattern, Some(&slots));
+        scope.arms.push(slots);
+        pattern
+    }
+
+    fn leave(&mut self) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.arms.pop();
+        }
+    }
+}
+
+/// Gives the names `pattern` binds inside a function their slots, from `slots`.
+fn resolve_pattern(pattern: Pattern, slots: Option<&HashMap<Identifier, usize>>) -> Pattern {
+    let Some(slots) = slots else {
+        return pattern;
+    };
+    match pattern {
+        Pattern::Binding(name) => {
+            let slot = slots[&name];
+            Pattern::Local(name, slot)
+        }
+        Pattern::Array(elements, rest) => Pattern::Array(
+            elements
+                .into_iter()
+                .map(|element| resolve_pattern(element, Some(slots)))
+                .collect(),
+            rest.map(|rest| Box::new(resolve_pattern(*rest, Some(slots)))),
+        ),
+        Pattern::Hash(pairs) => Pattern::Hash(
+            pairs
+                .into_iter()
+                .map(|(key, value)| (key, resolve_pattern(value, Some(slots))))
+                .collect(),
+        ),
+        pattern => pattern,
+    }
+}
@@ -0,0 +1,65 @@
+//! The pointer and cell types the runtime is built on.
+//!
+//! By default these are plain `Rc` and `RefCell`. With the `sync` feature they become `Arc` and
+//! an `RwLock` with the same `borrow`/`borrow_mut` interface, so `Object`, `Environment` and
+//! `Interpreter` are `Send + Sync` and can be moved to (or shared between) worker threads.
+
+#[cfg(not(feature = "sync"))]
+pub use std::cell::RefCell;
+#[cfg(not(feature = "sync"))]
+pub use std::rc::{Rc, Weak};
+
+#[cfg(feature = "sync")]
+pub use std::sync::{Arc as Rc, Weak};
+
+/// Bound added to host closures and values: `Send + Sync` with the `sync` feature, nothing
+/// otherwise.
+#[cfg(feature = "sync")]
+pub trait MaybeSendSync: Send + Sync {}
+#[cfg(feature = "sync")]
+impl<T: Send + Sync + ?Sized> MaybeSendSync for T {}
+
+#[cfg(not(feature = "sync"))]
+pub trait MaybeSendSync {}
+#[cfg(not(feature = "sync"))]
+impl<T: ?Sized> MaybeSendSync for T {}
+
+#[cfg(feature = "sync")]
+pub use self::lock::RefCell;
+
+#[cfg(feature = "sync")]
+mod lock {
+    use std::fmt;
+    use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+    /// An `RwLock` that reads like a `RefCell`. A poisoned lock means a host callback panicked
+    /// mid-evaluation, so we propagate the panic rather than run on inconsistent state.
+    #[derive(Default)]
+    pub struct RefCell<T>(RwLock<T>);
+
+    impl<T> RefCell<T> {
+        pub fn new(value: T) -> Self {
+            RefCell(RwLock::new(value))
+        }
+
+        pub fn borrow(&self) -> RwLockReadGuard<'_, T> {
+            self.0.read().expect("runtime lock poisoned")
+        }
+
+        pub fn borrow_mut(&self) -> RwLockWriteGuard<'_, T> {
+            self.0.write().expect("runtime lock poisoned")
+        }
+    }
+
+    impl<T: PartialEq> PartialEq for RefCell<T> {
+        fn eq(&self, other: &Self) -> bool {
+            std::ptr::eq(self, other) || *self.borrow() == *other.borrow()
+        }
+    }
+
+    impl<T: fmt::Debug> fmt::Debug for RefCell<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_tuple("RefCell").field(&*self.borrow()).finish()
+        }
+    }
+}
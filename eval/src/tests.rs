@@ -1,18 +1,245 @@
-use std::cell::RefCell;
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
+use crate::check::check_source;
+use crate::debug::{Debugger, Pause, Step};
 use crate::environment::Environment;
-use crate::eval::eval;
+use crate::eval::{eval, eval_with_limits};
 use crate::eval_error::EvalError;
+use crate::interpreter::{Interpreter, InterpreterError};
+use crate::limits::{Limits, DEFAULT_MAX_CALL_DEPTH};
+use crate::lint::{lint_source, Diagnostic, Lint, Severity};
 use crate::node::Node;
-use crate::object::{HashKey, HashPair, Object};
+use crate::object::{HashKey, HashPair, NativeObject, NativeType, Object};
+use crate::optimize::optimize;
+use crate::resolver::resolve;
+use crate::sync::{RefCell, Rc};
 use colored::Colorize;
 use lexer::lexer::Lexer;
 use lexer::token::Token;
-use parser::ast::{Expression, Program, Statement};
+use parser::ast::{Expression, Program, Statement, Type};
 use parser::parser::Parser;
 
+#[test]
+fn test_interpreter() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_global("base", 10);
+    let counter = Rc::new(RefCell::new(0));
+    let calls = counter.clone();
+    interpreter.register_fn("bump", Some(1), move |args| {
+        *calls.borrow_mut() += 1;
+        let by = i64::try_from(args[0].as_ref())?;
+        Ok(Object::Int(by + *calls.borrow()).into())
+    });
+
+    interpreter.run("let add = fn(a, b) { a + b + base };").unwrap();
+    let result = interpreter.call("add", vec![1.into(), 2.into()]).unwrap();
+    assert_eq!(i64::try_from(result.as_ref()), Ok(13));
+
+    let result = interpreter.run("bump(5) + bump(5)").unwrap();
+    assert_eq!(*result, Object::Int(13));
+    assert_eq!(*counter.borrow(), 2);
+
+    interpreter.run("let names = [\"a\", \"b\"];").unwrap();
+    let names: Vec<String> = interpreter
+        .get_global("names")
+        .unwrap()
+        .as_ref()
+        .try_into()
+        .unwrap();
+    assert_eq!(names, vec!["a", "b"]);
+
+    assert!(matches!(
+        interpreter.run("let = 5;"),
+        Err(InterpreterError::Parse(_))
+    ));
+    assert_eq!(
+        interpreter.run("bump(1, 2)").unwrap_err().to_string(),
+        "got 2 params but was expecting 1\n    at <program> (line 1)"
+    );
+    assert!(matches!(
+        interpreter.call("missing", vec![]),
+        Err(EvalError::IdentifierNotFount(_))
+    ));
+}
+
+#[test]
+fn test_native_objects() {
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+    let point_type = Rc::new(
+        NativeType::new("Point")
+            .with_method("x", Some(0), |args| {
+                let Object::Native(this) = args[0].as_ref() else {
+                    unreachable!()
+                };
+                Ok(Object::Int(this.downcast_ref::<Point>().unwrap().x).into())
+            })
+            .with_display(|p: &Point| format!("Point({}, {})", p.x, p.y))
+            .with_eq(|l: &Point, r: &Point| l.x == r.x && l.y == r.y),
+    );
+
+    let mut interpreter = Interpreter::new();
+    interpreter.set_global("a", NativeObject::new(&point_type, Point { x: 1, y: 2 }));
+    interpreter.set_global("b", NativeObject::new(&point_type, Point { x: 1, y: 2 }));
+    interpreter.set_global("c", NativeObject::new(&point_type, Point { x: 3, y: 4 }));
+    interpreter.register_fn("sum", Some(1), |args| match args[0].as_ref() {
+        Object::Native(native) => match native.downcast_ref::<Point>() {
+            Some(p) => Ok(Object::Int(p.x + p.y).into()),
+            None => Err(EvalError::InvalidObjectType(
+                "Point".into(),
+                native.type_name().into(),
+            )),
+        },
+        o => Err(EvalError::InvalidObjectType("Point".into(), o.to_string())),
+    });
+
+    let run = |interpreter: &mut Interpreter, input: &str| interpreter.run(input).unwrap();
+    assert_eq!(*run(&mut interpreter, "sum(c)"), Object::Int(7));
+    assert_eq!(*run(&mut interpreter, "c[\"x\"]()"), Object::Int(3));
+    assert_eq!(*run(&mut interpreter, "a == b"), Object::Bool(true));
+    assert_eq!(*run(&mut interpreter, "a != c"), Object::Bool(true));
+    assert_eq!(run(&mut interpreter, "[a]").to_string(), "[Point(1, 2)] ");
+    assert_eq!(
+        interpreter.run("a[\"z\"]").unwrap_err().to_string(),
+        "key: Point.z does not exsist\n    at <program> (line 1)"
+    );
+}
+
+#[test]
+fn test_shared_globals() {
+    let mut setup = Interpreter::new();
+    setup.run("let square = fn(x) { x * x };").unwrap();
+    let globals = setup.env().clone();
+
+    let mut first = Interpreter::with_globals(&globals);
+    let mut second = Interpreter::with_globals(&globals);
+    first.run("let x = 3;").unwrap();
+    assert_eq!(*first.run("square(x)").unwrap(), Object::Int(9));
+    assert!(second.run("x").is_err());
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_runtime_is_send_and_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Object>();
+    assert_send_sync::<Environment>();
+    assert_send_sync::<Interpreter>();
+
+    let mut setup = Interpreter::new();
+    setup.run("let square = fn(x) { x * x };").unwrap();
+    let globals = setup.env().clone();
+    let workers: Vec<_> = (0..4)
+        .map(|i| {
+            let globals = globals.clone();
+            std::thread::spawn(move || {
+                let mut interpreter = Interpreter::with_globals(&globals);
+                i64::try_from(interpreter.run(&format!("square({i})")).unwrap().as_ref()).unwrap()
+            })
+        })
+        .collect();
+    let results: Vec<i64> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+    assert_eq!(results, vec![0, 1, 4, 9]);
+}
+
+#[test]
+fn test_garbage_collection() {
+    let mut interpreter = Interpreter::new();
+    let baseline = interpreter.collect_garbage().environments;
+
+    interpreter
+        .run(
+            "let leak = fn() { let f = fn() { f() }; 0 }; leak(); leak(); leak(); \
+             let adder = fn(x) { let g = fn(y) { x + y }; g }; let addTwo = adder(2);",
+        )
+        .unwrap();
+    let held = interpreter.run("adder(5)").unwrap();
+    assert_eq!(interpreter.heap_stats().environments, baseline + 5);
+
+    let stats = interpreter.collect_garbage();
+    assert_eq!(stats.environments, baseline + 2);
+    assert_eq!(stats.freed_environments, 3);
+    assert_eq!(*interpreter.run("addTwo(3)").unwrap(), Object::Int(5));
+    let result = interpreter.call_value(held, vec![Object::Int(1)]).unwrap();
+    assert_eq!(*result, Object::Int(6));
+
+    interpreter
+        .run("let spin = fn(n) { if (n == 0) { 0 } else { leak(); spin(n - 1) } }; spin(5000);")
+        .unwrap();
+    let stats = interpreter.heap_stats();
+    assert!(stats.collections > 1);
+    assert!(stats.environments < 2500, "{stats:?}");
+}
+
+#[test]
+fn test_imports() {
+    let dir = std::env::temp_dir().join(format!("monkey-imports-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    let write = |name: &str, source: &str| std::fs::write(dir.join(name), source).unwrap();
+    write(
+        "lib/math.monkey",
+        "let secret = 2; export let double = fn(x) { x * secret }; export let one = 1;",
+    );
+    write("main.monkey", r#"import "lib/math" as m; import "lib/math.monkey"; m["double"](21) + math["one"]"#);
+    write("a.monkey", r#"import "b";"#);
+    write("b.monkey", r#"import "a";"#);
+    write("broken.monkey", "let = 1;");
+    write("small.monkey", "export let one = 1;");
+
+    let mut interpreter = Interpreter::new();
+    let result = interpreter.run_file(dir.join("main.monkey")).unwrap();
+    assert_eq!(*result, Object::Int(43));
+    let (m, math) = (interpreter.get_global("m"), interpreter.get_global("math"));
+    assert!(Rc::ptr_eq(&m.unwrap(), &math.unwrap()), "module should be cached");
+    assert!(interpreter.run(r#"m["secret"]"#).unwrap().as_ref() == &Object::Null);
+
+    match interpreter.run_file(dir.join("a.monkey")) {
+        Err(InterpreterError::Eval(EvalError::CircularImport(chain), _)) => {
+            assert_eq!(chain.len(), 3);
+            assert!(chain[0].ends_with("a.monkey") && chain[2].ends_with("a.monkey"));
+        }
+        r => panic!("expected circular import error, got {r:?}"),
+    }
+    let err = interpreter.run_file(dir.join("main.monkey")).map(|_| ());
+    assert!(err.is_ok(), "{err:?}");
+    let err = interpreter
+        .run(&format!(r#"import "{}";"#, dir.join("broken").display()))
+        .unwrap_err();
+    assert!(matches!(err, InterpreterError::Eval(EvalError::ImportFailed { .. }, _)));
+    let err = interpreter.run(r#"import "does/not/exist";"#).unwrap_err();
+    assert!(matches!(err, InterpreterError::Eval(EvalError::ImportFailed { .. }, _)));
+
+    // The prelude of the module's environment doesn't count against the importer's limits.
+    let mut limited = Interpreter::with_limits(Limits {
+        max_steps: Some(20),
+        ..Default::default()
+    });
+    let source = format!(r#"import "{}" as m; m["one"]"#, dir.join("small").display());
+    assert_eq!(*limited.run(&source).unwrap(), Object::Int(1));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_object_conversions() {
+    let map: HashMap<String, Vec<i64>> =
+        HashMap::from([("a".to_string(), vec![1, 2]), ("b".to_string(), vec![])]);
+    let obj = Object::from(map.clone());
+    assert_eq!(HashMap::<String, Vec<i64>>::try_from(&obj), Ok(map));
+
+    assert_eq!(Object::from(Some(true)), Object::Bool(true));
+    assert_eq!(Object::from(None::<i64>), Object::Null);
+    assert_eq!(
+        bool::try_from(&Object::Int(1)),
+        Err(EvalError::InvalidObjectType("Bool".into(), "1".into()))
+    );
+}
+
 #[test]
 fn test_hash_index_expression() {
     let tests: Vec<SingleValueTest> = vec![
@@ -110,7 +337,7 @@ fn test_complex_fns() {
     map([1,2,3,4],double);\
     ",
         Object::Array(
-            vec![2, 4, 6, 8]
+            [2, 4, 6, 8]
                 .iter()
                 .map(|x| Rc::new(Object::Int(*x)))
                 .collect(),
@@ -142,6 +369,887 @@ fn test_builtin_fns() {
     SingleValueTest::test(tests);
 }
 
+#[test]
+fn test_prelude() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .run(include_str!("prelude_tests.monkey"))
+        .unwrap();
+    let tests = interpreter.get_global("tests").unwrap();
+    let Object::Hash(tests) = tests.as_ref() else {
+        panic!("prelude tests should be a hash, got {tests}");
+    };
+    assert!(!tests.is_empty());
+    for pair in tests.values() {
+        if let Err(e) = interpreter.call_value(pair.value.clone(), vec![]) {
+            panic!("prelude test {} failed: {e}", pair.key);
+        }
+    }
+
+    let mut sandboxed = Interpreter::without_prelude();
+    assert_eq!(
+        sandboxed.run("map([1], fn(x) { x })").unwrap_err().to_string(),
+        EvalError::IdentifierNotFount("map".to_string()).to_string()
+    );
+    assert_eq!(*sandboxed.run("len([1])").unwrap(), Object::Int(1));
+}
+
+#[test]
+fn test_prelude_builtins() {
+    let tests = vec![
+        SingleValueTest::new("str(12)", "12"),
+        SingleValueTest::new("type(1)", "Int"),
+        SingleValueTest::new("type(\"a\")", "String"),
+        SingleValueTest::new("type(len)", "Function"),
+        SingleValueTest::new("type({})", "Hash"),
+        SingleValueTest::new("len(split(\"a,b,,c\", \",\"))", 4),
+        SingleValueTest::new("let map = 5; map", 5),
+    ];
+    SingleValueTest::test(tests);
+    let tests: Vec<ErrorTest> = vec![
+        ErrorTest::new(
+            "assert(1 > 2, \"one is not bigger\")",
+            EvalError::AssertionFailed("one is not bigger".to_string()),
+        ),
+        ErrorTest::new(
+            "assertEq(sum([1, 2]), 4)",
+            EvalError::AssertionFailed("expected 4 but got 3".to_string()),
+        ),
+        // The prelude's functions can be bound again, the Rust builtins only in a function.
+        ErrorTest::new(
+            "let first = fn(x) { 0 }; map([[1]], last)",
+            EvalError::AssignToConstant("first".to_string()),
+        ),
+    ];
+    ErrorTest::test(tests);
+}
+
+#[test]
+fn test_lint() {
+    let input = r#"let unused = 1;
+let len = fn(x) { x };
+let twice = fn(f, x) {
+    return f(f(x));
+    put("never");
+};
+twice(fn(x) { x }, 1, 2);
+5(1);
+later(missing);
+let later = fn(x) { if (1 < 2) { x } else { recur(x) } };
+let recur = fn(x) { later(x) };
+first([1], [2]);
+map([1]);
+export let api = 1;
+import "lib";
+let ="#;
+    let lints = |input: &str| -> Vec<(&'static str, usize)> {
+        lint_source(input)
+            .iter()
+            .map(|d| (d.lint.code(), d.line))
+            .collect()
+    };
+    assert_eq!(lints(input), vec![("parse-error", 16), ("parse-error", 16)]);
+
+    let input = input.trim_end_matches("let =");
+    assert_eq!(
+        lints(input),
+        vec![
+            ("unused-binding", 1),
+            ("shadowed-builtin", 2),
+            ("unused-binding", 2),
+            ("unreachable-code", 5),
+            ("wrong-arity", 7),
+            ("not-callable", 8),
+            ("undefined-identifier", 9),
+            ("constant-condition", 10),
+            ("wrong-arity", 12),
+            ("wrong-arity", 13),
+            ("unused-binding", 15),
+        ]
+    );
+    let diagnostics = lint_source(input);
+    assert_eq!(
+        diagnostics[4],
+        Diagnostic {
+            lint: Lint::WrongArity {
+                name: "twice".to_string(),
+                expected: 2,
+                actual: 3
+            },
+            line: 7
+        }
+    );
+    assert_eq!(diagnostics[4].lint.severity(), Severity::Error);
+    assert_eq!(
+        diagnostics[6].to_string(),
+        "9: error[undefined-identifier]: missing is never defined"
+    );
+    assert!(lint_source(include_str!("prelude_tests.monkey"))
+        .iter()
+        .all(|d| d.lint.code() == "unused-binding"));
+
+    let input = r#"let b = 1 < 2;
+match b { true => 1, false => 0 };
+match b { true => 1 };
+match [b] { [x] if x => x, [_] => b, _ => b };
+match 1 { n if n > 0 => n };
+match 2 { m => m, _ => 0 } + m;
+"#;
+    assert_eq!(
+        lints(input),
+        vec![
+            ("non-exhaustive-match", 3),
+            ("non-exhaustive-match", 5),
+            ("undefined-identifier", 6)
+        ]
+    );
+
+    let input = "ping(1);\nfn ping(n) { pong(n) }\nfn pong(n) { n }\nfn idle() { 1 }\npong(1, 2);\n";
+    assert_eq!(
+        lints(input),
+        vec![("unused-binding", 4), ("wrong-arity", 5)]
+    );
+}
+
+#[test]
+fn test_check() {
+    let input = r#"let a = "a" - 1;
+let n: int = "five";
+let inc = fn(x: int) -> int { x + 1 };
+inc("one");
+let bad = fn(x) -> string { if (x) { return 1; } 2 };
+let fact = fn(k: int) -> int { if (k == 0) { 1 } else { k * fact(k - 1) } };
+let apply = fn(f: fn(int) -> int, v) { f(v) };
+apply(fn(s: string) { s }, 2);
+apply(inc, 2) + true;
+[1, 2]["x"];
+len("abc") == "3";
+-true + 1;
+let untyped = fn(p, q) { p + q };
+untyped(1, "x");
+export let shown: bool = 1 < 2;
+"#;
+    let checks = |input: &str| -> Vec<(&'static str, usize)> {
+        check_source(input)
+            .iter()
+            .map(|d| (d.lint.code(), d.line))
+            .collect()
+    };
+    assert_eq!(
+        checks(input),
+        vec![
+            ("invalid-operands", 1),
+            ("type-mismatch", 2),
+            ("type-mismatch", 4),
+            ("type-mismatch", 5),
+            ("type-mismatch", 8),
+            ("invalid-operands", 9),
+            ("type-mismatch", 10),
+            ("invalid-operands", 11),
+            ("invalid-operands", 12),
+        ]
+    );
+    let diagnostics = check_source(input);
+    assert_eq!(
+        diagnostics[2].to_string(),
+        "4: error[type-mismatch]: argument 1 of inc should be int but is string"
+    );
+    assert_eq!(
+        diagnostics[0].lint,
+        Lint::InvalidOperands {
+            op: Token::Dash,
+            left: Type::String,
+            right: Type::Int
+        }
+    );
+    assert_eq!(
+        diagnostics[4].lint.to_string(),
+        "argument 1 of apply should be fn(int) -> int but is fn(string) -> string"
+    );
+
+    // Names bound more than once could hold anything.
+    assert!(checks("let x = 1; let x = \"s\"; x - 1").is_empty());
+    assert!(check_source(include_str!("prelude.monkey")).is_empty());
+    assert!(check_source(include_str!("prelude_tests.monkey")).is_empty());
+
+    let mut interpreter = Interpreter::new();
+    match interpreter.run("let f = fn() { \"a\" - 1 }; 1") {
+        Err(InterpreterError::Check(diagnostics)) => {
+            assert_eq!(diagnostics[0].lint.code(), "invalid-operands")
+        }
+        result => panic!("expected a type error, got {result:?}"),
+    }
+    assert_eq!(
+        *interpreter.run("let g = fn(x: int) -> int { x * 2 }; g(21)").unwrap(),
+        Object::Int(42)
+    );
+    // A builtin's type only holds while the environment still has the builtin.
+    assert!(matches!(interpreter.run("len + 1"), Err(InterpreterError::Check(_))));
+    interpreter.set_global("first", 10);
+    assert_eq!(*interpreter.run("first + 1").unwrap(), Object::Int(11));
+}
+
+/// A debugger that stops at `breakpoints`, takes `steps` in turn (then continues) and records
+/// every pause.
+#[derive(Default)]
+struct ScriptedDebugger {
+    entry: bool,
+    breakpoints: Vec<usize>,
+    steps: Vec<Step>,
+    pauses: Arc<Mutex<Vec<String>>>,
+}
+
+impl Debugger for ScriptedDebugger {
+    fn breaks_at(&self, line: usize) -> bool {
+        self.breakpoints.contains(&line)
+    }
+
+    fn pause(&mut self, pause: &Pause) -> Step {
+        let frames: Vec<String> = pause
+            .frames
+            .iter()
+            .map(|frame| format!("{}:{}", frame.function, frame.line))
+            .collect();
+        let current = pause.frames.last().unwrap();
+        let variables: Vec<String> = match current.function.as_str() {
+            // The globals hold the whole prelude.
+            "<program>" => Vec::new(),
+            _ => current
+                .variables()
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect(),
+        };
+        self.pauses.lock().unwrap().push(format!(
+            "{:?} {} [{}] {}",
+            pause.reason,
+            pause.line,
+            frames.join(" "),
+            variables.join(" ")
+        ));
+        if self.steps.is_empty() {
+            Step::Continue
+        } else {
+            self.steps.remove(0)
+        }
+    }
+
+    fn stop_on_entry(&self) -> bool {
+        self.entry
+    }
+}
+
+#[test]
+fn test_debugger() {
+    let input = "let fact = fn(n) {
+  if (n < 2) {
+    return 1;
+  }
+  let rest = fact(n - 1);
+  n * rest
+};
+let x = fact(3);
+let y = map([1, 2], fn(v) { v * 10 });
+x";
+    let debug = |debugger: ScriptedDebugger| {
+        let pauses = debugger.pauses.clone();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_debugger(debugger);
+        let result = interpreter.run(input);
+        let pauses = pauses.lock().unwrap().clone();
+        (result, pauses)
+    };
+
+    let (result, pauses) = debug(ScriptedDebugger {
+        entry: true,
+        steps: vec![Step::Over, Step::In, Step::Over, Step::Over, Step::Out],
+        ..Default::default()
+    });
+    assert_eq!(*result.unwrap(), Object::Int(6));
+    assert_eq!(
+        pauses,
+        vec![
+            "Entry 1 [<program>:1] ",
+            "Step 8 [<program>:8] ",
+            "Step 2 [<program>:8 fact:2] n=3",
+            "Step 5 [<program>:8 fact:5] n=3",
+            "Step 6 [<program>:8 fact:6] n=3 rest=2",
+            "Step 9 [<program>:9] ",
+        ]
+    );
+
+    // The prelude's `map` tail calls its `iter`, which calls the callback.
+    let (result, pauses) = debug(ScriptedDebugger {
+        breakpoints: vec![3, 9],
+        steps: vec![Step::Continue, Step::Continue, Step::Stop],
+        ..Default::default()
+    });
+    assert!(matches!(
+        result,
+        Err(InterpreterError::Eval(EvalError::Interrupted, _))
+    ));
+    assert_eq!(
+        pauses,
+        vec![
+            "Breakpoint 3 [<program>:8 fact:5 fact:5 fact:3] n=1",
+            "Breakpoint 9 [<program>:9] ",
+            "Breakpoint 9 [<program>:9 iter:0 <anonymous>:9] v=1",
+        ]
+    );
+
+    // Without a debugger nothing stops.
+    let mut interpreter = Interpreter::new();
+    interpreter.set_debugger(ScriptedDebugger {
+        breakpoints: vec![1],
+        ..Default::default()
+    });
+    interpreter.clear_debugger();
+    assert_eq!(*interpreter.run(input).unwrap(), Object::Int(6));
+}
+
+#[test]
+fn test_backtrace() {
+    let trace = |input: &str| match Interpreter::new().run(input) {
+        Err(e @ InterpreterError::Eval(..)) => e.to_string(),
+        result => panic!("expected a runtime error, got {result:?}"),
+    };
+
+    let input = "let fib = fn(n) {
+  if (n < 0) {
+    return n[0];
+  }
+  if (n < 2) { return n; }
+  fib(n - 1) + fib(n - 2)
+};
+let main = fn() {
+  let total = fib(2);
+  fib(-1)
+};
+main();";
+    assert_eq!(
+        trace(input),
+        "could not use index accessor on -1
+    at fib (line 3)
+    at main (line 10)
+    at <program> (line 12)"
+    );
+
+    let input = "let down = fn(n) {
+  if (n == 0) { len(1) } else { 1 + down(n - 1) }
+};
+down(3)";
+    assert_eq!(
+        trace(input),
+        "len is not a valid operation on 1
+    at down (line 2)
+    ... repeated 3 more times
+    at <program> (line 4)"
+    );
+
+    // The prelude has no lines to show.
+    assert_eq!(
+        trace("let xs = map([[1], 2], fn(x) { x[0] });"),
+        "could not use index accessor on 2
+    at <anonymous> (line 1)
+    at iter
+    ... repeated 1 more times
+    at map
+    at <program> (line 1)"
+    );
+}
+
+#[test]
+fn test_try_catch() {
+    let tests = vec![
+        (r#"try { [1][5] } catch (e) { e["kind"] }"#, "IndexOutOfBounds"),
+        (r#"try { throw error("boom") } catch (e) { e["message"] }"#, "boom"),
+        ("try { throw 5 } catch (e) { e + 1 }", "6"),
+        ("try { 1 } catch (e) { 2 }", "1"),
+        ("let f = fn() { try { return 1 } finally { 2 } }; f()", "1"),
+        ("let f = fn() { try { throw 1 } finally { return 2 } }; f()", "2"),
+        (
+            r#"let log = []; let r = try { 1 } finally { let log = push(log, "done") }; [r, log]"#,
+            "[1, [done] ] ",
+        ),
+        (
+            r#"let f = fn(x) { x[0] };
+let g = fn() { f(1) };
+try { g() } catch (e) { e["trace"] }"#,
+            "[at f (line 1), at g (line 2)] ",
+        ),
+        (
+            "let f = fn() { let e = 1; let r = e; try { throw 2 } catch (e) { 0 }; r }; f()",
+            "1",
+        ),
+        ("let f = fn() { let g = try { throw 3 } catch (e) { fn() { e } }; g() }; f()", "3"),
+        // The caught error is only bound in the handler.
+        ("let e = 1; try { throw 3 } catch (e) { e }; e", "1"),
+        ("let f = fn() { let e = 1; try { throw 3 } catch (e) { e }; e }; f()", "1"),
+        ("const e = 1; try { throw 3 } catch (e) { e }", "3"),
+    ];
+    for (input, expected) in tests {
+        assert_eq!(test_eval(input).unwrap().to_string(), expected, "{input}");
+    }
+
+    assert_eq!(
+        test_eval("try { 1 } finally { throw 2 }"),
+        Err(EvalError::Thrown(Object::Int(2).into()))
+    );
+    assert_eq!(
+        test_eval(r#"throw error("boom")"#).unwrap_err().to_string(),
+        "Error: boom"
+    );
+    assert_eq!(test_eval("throw 5").unwrap_err().to_string(), "uncaught 5");
+
+    // Running out of a limit can't be caught.
+    let limits = Limits {
+        max_steps: Some(100),
+        ..Limits::default()
+    };
+    let result = test_eval_with_limits(
+        "let f = fn() { f() }; try { f() } catch (e) { 1 }",
+        limits,
+    );
+    assert_eq!(result, Err(EvalError::StepLimitExceeded(100)));
+
+    // A caught error that is thrown again keeps where it came from.
+    let input = r#"let f = fn() { throw error("boom") };
+let g = fn() {
+  try { f() } catch (e) { throw e }
+};
+g();"#;
+    assert_eq!(
+        Interpreter::new().run(input).unwrap_err().to_string(),
+        "Error: boom
+    at f (line 1)
+    at g (line 3)
+    at <program> (line 5)"
+    );
+
+    let lints: Vec<_> = lint_source("let f = fn() { try { throw 1; 2 } catch (e) { 3 } };\nf();")
+        .iter()
+        .map(|d| d.lint.code())
+        .collect();
+    assert_eq!(lints, vec!["unreachable-code"]);
+}
+
+#[test]
+fn test_results() {
+    let parse = r#"let parse = fn(s) { if (s == "1") { ok(1) } else { err("bad " + s) } };
+let add = fn(a, b) { ok(parse(a)? + parse(b)?) };
+"#;
+    let tests = vec![
+        (r#"add("1", "1")"#, "ok(2)"),
+        (r#"add("1", "x")"#, "err(bad x)"),
+        (r#"unwrap_or(add("y", "1"), 0)"#, "0"),
+        (r#"[is_ok(ok(1)), is_ok(err(1))]"#, "[true, false] "),
+        (r#"map_err(add("1", "z"), fn(e) { "add: " + e })"#, "err(add: bad z)"),
+        (r#"try { unwrap(add("1", "w")) } catch (e) { e["kind"] }"#, "UnwrapFailed"),
+        // `?` outside a function ends the program with the `err`.
+        (r#"parse("q")?; 5"#, "err(bad q)"),
+    ];
+    for (input, expected) in tests {
+        let input = format!("{parse}{input}");
+        assert_eq!(test_eval(&input).unwrap().to_string(), expected, "{input}");
+    }
+
+    // Finally blocks still run when `?` returns early.
+    let input = "let f = fn() { try { err(1)? } finally { return 2 } }; f()";
+    assert_eq!(test_eval(input).unwrap().to_string(), "2");
+    assert_eq!(
+        test_eval("unwrap(err(1))"),
+        Err(EvalError::UnwrapFailed("err(1)".to_string()))
+    );
+    assert_eq!(
+        test_eval("5?"),
+        Err(EvalError::InvalidObjectType("Result".to_string(), "5".to_string()))
+    );
+}
+
+#[test]
+fn test_match() {
+    let describe = r#"let describe = fn(v) {
+    match v {
+        0 => "zero",
+        true => "yes",
+        [v, ..rest] => v + len(rest),
+        {"type": "a", "v": v} => v,
+        s if len(s) > 3 => "long " + s,
+        _ => "other",
+    }
+};
+"#;
+    let tests = vec![
+        ("describe(0)", "zero"),
+        ("describe(1 == 1)", "yes"),
+        ("describe([4, 5, 6])", "6"),
+        (r#"describe({"type": "a", "v": 9})"#, "9"),
+        (r#"describe("hello")"#, "long hello"),
+        (r#"describe("hi")"#, "other"),
+        ("match [1, 2] { [a] => a, [a, b] => a + b }", "3"),
+        ("match [1, 2] { [3, ..] => 0, [..] => 1 }", "1"),
+        ("match -1 { -1 => true, _ => false }", "true"),
+        // An arm's names are its own, and shadow any outside it.
+        ("let f = fn() { let x = 5; match [1] { [x] => x }; x }; f()", "5"),
+        ("let x = 5; match [1] { [x] => x }; x", "5"),
+        ("let h = match 2 { n => fn() { n } }; h()", "2"),
+        ("let f = fn() { let h = match 2 { n => fn() { n } }; h() }; f()", "2"),
+    ];
+    for (input, expected) in tests {
+        let input = format!("{describe}{input}");
+        assert_eq!(test_eval(&input).unwrap().to_string(), expected, "{input}");
+    }
+    assert_eq!(
+        test_eval("match 3 { 1 => 1, 2 => 2 }"),
+        Err(EvalError::NoMatch("3".to_string()))
+    );
+    let tests = vec![
+        ("let r = match 5 { v if v > 10 => 1, _ => 2 }; v", "v"),
+        ("let g = fn() { match [1] { [x] => x }; x }; g()", "x"),
+        ("let g = fn() { match 5 { v if v > 10 => 1, _ => v } }; g()", "v"),
+    ];
+    for (input, name) in tests {
+        assert_eq!(
+            test_eval(input),
+            Err(EvalError::IdentifierNotFount(name.to_string())),
+            "{input}"
+        );
+    }
+}
+
+#[test]
+fn test_destructuring() {
+    let tests = vec![
+        ("let [a, b, ...others] = [1, 2, 3, 4]; [a, b, others]", "[1, 2, [3, 4] ] "),
+        (r#"let {name, "age": [age]} = {"name": "ann", "age": [30]}; name + str(age)"#, "ann30"),
+        ("let f = fn([x, y], {k}) { x * y + k }; f([2, 3], {\"k\": 1})", "7"),
+        ("let f = fn(v) { let [h, ..t] = v; h + len(t) }; f([5, 6, 7])", "7"),
+        // A pattern name can take the name of a param.
+        ("let f = fn(v) { let [v] = v; v }; f([8])", "8"),
+        ("let [_, [x]] = [1, [2]]; x", "2"),
+        ("let f = fn(n, [a, b] = [n, 2]) { a + b }; [f(1), f(1, [5, 5])]", "[3, 10] "),
+        // The function keeps the params and body as written.
+        ("fn([x, y], z) { x }", "fn([x, y] ,z) x"),
+    ];
+    for (input, expected) in tests {
+        assert_eq!(test_eval(input).unwrap().to_string(), expected, "{input}");
+    }
+
+    assert_eq!(
+        test_eval("let [a, b] = [1]; a"),
+        Err(EvalError::ShapeMismatch(
+            "[a, b]".to_string(),
+            "[1] ".to_string()
+        ))
+    );
+    assert_eq!(
+        test_eval("let f = fn({k}) { k }; f({})"),
+        Err(EvalError::ShapeMismatch("{k}".to_string(), "{}".to_string()))
+    );
+    // A keyword argument can only name a param that has a name.
+    assert_eq!(
+        test_eval("let f = fn([x]) { x }; f(x = [1])"),
+        Err(EvalError::UnknownParam("x".to_string()))
+    );
+    let checks: Vec<_> = check_source(
+        "let [a] = 5;\nlet g = fn([x]) { x };\ng([1]);\nlet h = fn({k}: array) { k };",
+    )
+    .iter()
+    .map(|d| (d.lint.to_string(), d.line))
+    .collect();
+    assert_eq!(
+        checks,
+        vec![
+            ("[a] should be array but is int".to_string(), 1),
+            ("{k} should be hash but is array".to_string(), 4),
+        ]
+    );
+}
+
+#[test]
+fn test_params() {
+    let tests = vec![
+        ("let f = fn(a, b = 10) { a + b }; [f(1), f(1, 2)]", "[11, 3] "),
+        ("let f = fn(a, b = a * 2) { b }; f(4)", "8"),
+        ("let f = fn(first, ...others) { [first, others] }; f(1, 2, 3)", "[1, [2, 3] ] "),
+        ("let f = fn(...all) { len(all) }; f()", "0"),
+        ("let f = fn(a, b, c) { a + b + c }; let xs = [2, 3]; f(1, ...xs)", "6"),
+        ("let f = fn(a, b = 2, c = 3) { [a, b, c] }; f(1, c = 30)", "[1, 2, 30] "),
+        ("let f = fn(a, b) { a - b }; f(b = 1, a = 5)", "4"),
+        ("len(...[[1, 2]])", "2"),
+    ];
+    for (input, expected) in tests {
+        assert_eq!(test_eval(input).unwrap().to_string(), expected, "{input}");
+    }
+
+    let tests = vec![
+        ErrorTest::new(
+            "let f = fn(a, b) { b }; f(1)",
+            EvalError::InvalidParamCount {
+                expected: 2,
+                actual: 1,
+            },
+        ),
+        ErrorTest::new(
+            "let f = fn(a, b = 1) { a }; f(1, 2, 3)",
+            EvalError::InvalidParamRange {
+                min: 1,
+                max: Some(2),
+                actual: 3,
+            },
+        ),
+        ErrorTest::new(
+            "let f = fn(a, ...b) { a }; f()",
+            EvalError::InvalidParamRange {
+                min: 1,
+                max: None,
+                actual: 0,
+            },
+        ),
+        ErrorTest::new(
+            "let f = fn(a) { a }; f(b = 1)",
+            EvalError::UnknownParam("b".to_string()),
+        ),
+        ErrorTest::new(
+            "let f = fn(a) { a }; f(1, a = 2)",
+            EvalError::DuplicateArgument("a".to_string()),
+        ),
+        ErrorTest::new(
+            "let f = fn(a) { a }; f(...5)",
+            EvalError::InvalidObjectType("Array".to_string(), "5".to_string()),
+        ),
+    ];
+    ErrorTest::test(tests);
+}
+
+#[test]
+fn test_fn_declarations() {
+    let parity = "fn is_even(n) { if (n == 0) { true } else { is_odd(n - 1) } }
+fn is_odd(n) { if (n == 0) { false } else { is_even(n - 1) } }";
+    let tests = vec![
+        // Declarations are bound before the statements of their block run.
+        (format!("let r = is_even(10); {parity} r"), "true"),
+        (format!("{parity} is_odd(7)"), "true"),
+        ("let f = fn() { let x = g(); fn g() { 41 } x + 1 }; f()".to_string(), "42"),
+        ("if (true) { let y = h(); fn h() { 3 } y }".to_string(), "3"),
+        ("fn double(x) { x * 2 } double".to_string(), "fn double(x) (x * 2)"),
+        ("fn() { 1 }".to_string(), "fn() 1"),
+    ];
+    for (input, expected) in tests {
+        assert_eq!(test_eval(&input).unwrap().to_string(), expected, "{input}");
+    }
+
+    let result = Interpreter::new().run("fn boom(x) {\n  x[0]\n}\nboom(1)");
+    let Err(e @ InterpreterError::Eval(..)) = result else {
+        panic!("expected a runtime error, got {result:?}");
+    };
+    assert_eq!(
+        e.to_string(),
+        "could not use index accessor on 1
+    at boom (line 2)
+    at <program> (line 4)"
+    );
+}
+
+#[test]
+fn test_constants() {
+    let tests = vec![
+        ("const x = 1; x + 1", "2"),
+        ("const [a, {b}] = [1, {\"b\": 2}]; a + b", "3"),
+        ("let x = 1; const x = 2; x", "2"),
+        // A builtin can be shadowed inside a function.
+        ("let f = fn() { let len = 5; len }; f()", "5"),
+        ("let f = fn(n) { const m = n * 2; m }; f(1) + f(2)", "6"),
+        // A match arm or a catch binds its names in a scope of its own, so it can shadow a
+        // constant.
+        ("const v = 1; match 2 { v => v } + v", "3"),
+        ("const e = 1; try { throw 2 } catch (e) { e } + e", "3"),
+    ];
+    for (input, expected) in tests {
+        assert_eq!(test_eval(input).unwrap().to_string(), expected, "{input}");
+    }
+
+    let rebound = |name: &str| EvalError::AssignToConstant(name.to_string());
+    let tests = vec![
+        // Found by the resolver before anything runs, even in a function never called.
+        ErrorTest::new("const x = 1; let x = 2;", rebound("x")),
+        ErrorTest::new("const [a, b] = [1, 2]; fn a() { 1 }", rebound("a")),
+        ErrorTest::new("let f = fn() { const a = 1; let a = 2; }; 1", rebound("a")),
+        ErrorTest::new("let len = fn(x) { 0 };", rebound("len")),
+        // Found when it runs.
+        ErrorTest::new("const x = 1; if (true) { let x = 2; } x", rebound("x")),
+        ErrorTest::new(
+            "let f = fn() { const a = 1; if (true) { let a = 2; } a }; f()",
+            rebound("a"),
+        ),
+    ];
+    ErrorTest::test(tests);
+
+    let mut interpreter = Interpreter::new();
+    interpreter.run("const k = 1;").unwrap();
+    assert_eq!(
+        interpreter.run("let k = 2;").unwrap_err().to_string(),
+        "k is a constant and can't be bound again"
+    );
+    // The host can still replace anything.
+    interpreter.set_global("k", 3);
+    assert_eq!(interpreter.run("k").unwrap().to_string(), "3");
+}
+
+#[test]
+fn test_resolver() {
+    let env = Rc::new(RefCell::new(Environment::new()));
+    let program = get_program("let f = fn(a) { let b = a; fn(c) { a + b + c + f } }".to_string());
+    let program = resolve(program, &env).unwrap();
+    let Statement::Let(_, _, Expression::FnExpression(_, outer, _)) = &program.statements[0] else {
+        panic!("expected a top-level fn, got {program}");
+    };
+    assert_eq!(
+        outer.statements[0],
+        Statement::LetLocal("b".into(), 1, Expression::Local("a".into(), 0, 0))
+    );
+    let Statement::ExpressionStatement(Expression::FnExpression(_, inner, _)) = &outer.statements[1]
+    else {
+        panic!("expected a nested fn, got {outer}");
+    };
+    let sum =
+        |left, right| Expression::InfixExpression(Token::Plus, Box::new(left), Box::new(right));
+    let expected = sum(
+        sum(
+            sum(Expression::Local("a".into(), 1, 0), Expression::Local("b".into(), 1, 1)),
+            Expression::Local("c".into(), 0, 0),
+        ),
+        Expression::Identifier("f".into()),
+    );
+    assert_eq!(inner.statements, vec![Statement::ExpressionStatement(expected)]);
+
+    let tests = vec![
+        ("let f = fn() { let g = fn() { x }; let x = 5; g() }; f()", Ok("5")),
+        ("let f = fn() { g() }; let g = fn() { 7 }; f()", Ok("7")),
+        ("let x = 1; let f = fn(x) { x * 10 }; f(2) + x", Ok("21")),
+        ("let f = fn(a, a) { a }; f(1, 2)", Ok("2")),
+        ("let f = fn(a) { let a = a + 1; a }; f(1)", Ok("2")),
+        (
+            "let f = fn() { let fact = fn(n) { if (n == 0) { 1 } else { n * fact(n - 1) } }; fact(5) }; f()",
+            Ok("120"),
+        ),
+        ("if (true) { let y = 3; } let f = fn() { y }; f()", Ok("3")),
+        ("let f = fn(c) { if (c) { let y = 1; } y }; f(true)", Ok("1")),
+        ("let f = fn(c) { if (c) { let y = 1; } y }; f(false)", Err("y")),
+        ("let f = fn() { x; let x = 1; }; f()", Err("x")),
+        ("let f = fn() { nope }; 1", Err("nope")),
+        ("if (false) { missing }", Err("missing")),
+    ];
+    for (input, expected) in tests {
+        let result = test_eval(input).map(|v| v.to_string());
+        let expected = expected
+            .map(str::to_string)
+            .map_err(|name| EvalError::IdentifierNotFount(name.to_string()));
+        assert_eq!(
+            result.map_err(|e| e.to_string()),
+            expected.map_err(|e| e.to_string()),
+            "{input}"
+        );
+    }
+
+    let mut interpreter = Interpreter::new();
+    interpreter.run("let a = 1;").unwrap();
+    assert_eq!(*interpreter.run("let f = fn() { a + 1 }; f()").unwrap(), Object::Int(2));
+    assert!(matches!(
+        interpreter.run("let g = fn() { later }; 1"),
+        Err(InterpreterError::Eval(EvalError::IdentifierNotFount(name), _)) if name == "later"
+    ));
+}
+
+#[test]
+fn test_optimize() {
+    let tests = vec![
+        "1 + 2 * 3",
+        "!true == !!false",
+        "-(2 - 5) * -1",
+        "\"a\" + \"b\" == \"ab\"",
+        "if (true) { 10 } else { 20 }",
+        "if (1 > 2) { 10 } else { 20 }",
+        "if (false) { 1 }",
+        "let x = 5; let f = fn(y) { x * y + (2 - 1) }; f(3)",
+        "let x = 1; let f = fn() { x }; let x = 2; f()",
+        "let f = fn() { x }; let x = 3; f()",
+        "let f = fn() { if (1 > 2) { return 1; } 5; if (false) {} }; f()",
+        "let f = fn() { if (true) { return 1; } 5 }; f()",
+        "let a = 2; if (a > 1) { let b = 3; b * a } else { 0 }",
+        "if (true) { let c = 4; } c",
+        "let g = fn(n) { if (n == 0) { 0 } else { g(n - 1) } }; g(10)",
+        "let n = 3; [n, {n: n + 1}[n + 0]]",
+        "1 + true",
+        "-true",
+        "len(1 + \"a\")",
+        "9223372036854775807 * 2 == 0",
+        "1 / 0",
+        "let zero = 0; if (false) { 1 / zero }",
+        "let r = try { g() } catch (e) { 0 }; if (true) { fn g() { 1 } } r",
+        "let r = try { f() } catch (e) { e[\"kind\"] }; let x = 5; fn f() { x } [r, f()]",
+        "f(); let x = 5; fn f() { x }",
+    ];
+    for input in tests {
+        let program = get_program(input.to_string());
+        let optimized = optimize(program.clone());
+        let expected = eval(
+            Node::Program(program),
+            &Rc::new(RefCell::new(Environment::new_with_builtin())),
+        );
+        let actual = eval(
+            Node::Program(optimized),
+            &Rc::new(RefCell::new(Environment::new_with_builtin())),
+        );
+        assert_eq!(
+            actual.map(|v| v.to_string()),
+            expected.map(|v| v.to_string()),
+            "{input}"
+        );
+    }
+
+    let optimized = optimize(get_program(
+        "let x = 1 + 2 * 3; if (x > 5) { put(x) } else { put(0) }; let y = !true; y".to_string(),
+    ));
+    assert_eq!(
+        parser::printer::print_program(&optimized, &Default::default()),
+        "let x = 7;\nput(7);\nlet y = false;\nfalse;\n"
+    );
+
+    let mut interpreter = Interpreter::new();
+    let tests = get_program(include_str!("prelude_tests.monkey").to_string());
+    interpreter.run(&parser::printer::print_program(&optimize(tests), &Default::default())).unwrap();
+    let tests = interpreter.get_global("tests").unwrap();
+    let Object::Hash(tests) = tests.as_ref() else {
+        panic!("prelude tests should be a hash, got {tests}");
+    };
+    for pair in tests.values() {
+        if let Err(e) = interpreter.call_value(pair.value.clone(), vec![]) {
+            panic!("optimized prelude test {} failed: {e}", pair.key);
+        }
+    }
+}
+
+#[test]
+fn test_native_fn_display() {
+    let tests = vec![
+        ("let f = len; f", "fn len(_)"),
+        ("push", "fn push(_, _)"),
+        ("put", "fn put(...)"),
+    ];
+    for (input, expected) in tests {
+        assert_eq!(test_eval(input).unwrap().to_string(), expected);
+    }
+    let tests: Vec<ErrorTest> = vec![ErrorTest::new(
+        "len([1], [2])",
+        EvalError::InvalidParamCount {
+            expected: 1,
+            actual: 2,
+        },
+    )];
+    ErrorTest::test(tests);
+}
+
 #[test]
 fn test_string_operations() {
     let tests: Vec<SingleValueTest> = vec![
@@ -180,13 +1288,154 @@ fn test_function_application() {
     ];
     SingleValueTest::test(tests);
 }
+#[test]
+fn test_tail_calls() {
+    let tests: Vec<SingleValueTest> = vec![
+        SingleValueTest::new(
+            "let count = fn(n, acc) { if (n == 0) { acc } else { count(n - 1, acc + 1) } }; \
+             count(10000, 0);",
+            10000,
+        ),
+        SingleValueTest::new(
+            "let count = fn(n) { if (n == 0) { return 0; } return count(n - 1); }; count(10000);",
+            0,
+        ),
+        SingleValueTest::new(
+            "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } }; \
+             let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } }; \
+             even(10001);",
+            false,
+        ),
+        SingleValueTest::new(
+            "let f = fn(n) { match n { 0 => 0, m if m > 0 => f(m - 1) } }; f(100000);",
+            0,
+        ),
+    ];
+    SingleValueTest::test(tests);
+}
+
+#[test]
+fn test_stack_overflow() {
+    let tests: Vec<ErrorTest> = vec![
+        ErrorTest::new(
+            "let f = fn(n) { 1 + f(n + 1) }; f(0);",
+            EvalError::StackOverflow(DEFAULT_MAX_CALL_DEPTH),
+        ),
+        ErrorTest::new(
+            "let f = fn() { [f()] }; f();",
+            EvalError::StackOverflow(DEFAULT_MAX_CALL_DEPTH),
+        ),
+    ];
+    ErrorTest::test(tests);
+
+    let countdown = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } };";
+    assert_eq!(test_eval(format!("{countdown} f(5000);")), Ok(Object::Int(5000).into()));
+
+    let limits = Limits {
+        max_call_depth: 10,
+        ..Default::default()
+    };
+    let result = test_eval_with_limits(format!("{countdown} f(9);"), limits.clone());
+    assert_eq!(result, Ok(Object::Int(9).into()));
+    let result = test_eval_with_limits(format!("{countdown} f(10);"), limits);
+    assert_eq!(result, Err(EvalError::StackOverflow(10)));
+}
+
+#[test]
+fn test_limits() {
+    let forever = "let f = fn() { f() }; f();";
+    let result = test_eval_with_limits(
+        forever,
+        Limits {
+            max_steps: Some(1000),
+            ..Default::default()
+        },
+    );
+    assert_eq!(result, Err(EvalError::StepLimitExceeded(1000)));
+
+    let result = test_eval_with_limits(
+        forever,
+        Limits {
+            deadline: Some(Instant::now()),
+            ..Default::default()
+        },
+    );
+    assert_eq!(result, Err(EvalError::DeadlineExceeded));
+
+    let result = test_eval_with_limits(
+        forever,
+        Limits {
+            interrupt: Some(Arc::new(AtomicBool::new(true))),
+            ..Default::default()
+        },
+    );
+    assert_eq!(result, Err(EvalError::Interrupted));
+
+    let limits = Limits {
+        max_collection_size: Some(3),
+        ..Default::default()
+    };
+    let tests = vec![
+        ("[1, 2, 3, 4]", 4),
+        ("push([1, 2, 3], 4)", 4),
+        ("{1: 1, 2: 2, 3: 3, 4: 4}", 4),
+        (r#""ab" + "cd""#, 4),
+        ("let f = fn(a) { f(push(a, 0)) }; f([]);", 4),
+    ];
+    for (input, size) in tests {
+        let result = test_eval_with_limits(input, limits.clone());
+        assert_eq!(
+            result,
+            Err(EvalError::CollectionTooLarge { size, max: 3 }),
+            "Input: {input}"
+        );
+    }
+    let result = test_eval_with_limits("len([1, 2, 3])", limits);
+    assert_eq!(result, Ok(Object::Int(3).into()));
+
+    // A panicking native function doesn't leave its budget behind on the thread.
+    let mut interpreter = Interpreter::with_limits(Limits {
+        max_steps: Some(10),
+        ..Default::default()
+    });
+    interpreter.register_fn("explode", Some(0), |_| panic!("explode"));
+    let run = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        interpreter.run("explode()")
+    }));
+    assert!(run.is_err());
+    let result = test_eval("let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) } }; f(100);");
+    assert_eq!(result, Ok(Object::Int(0).into()));
+
+    // Integer arithmetic fails rather than panicking the host.
+    let overflow = |operation: &str| EvalError::IntegerOverflow(operation.to_string());
+    let tests = vec![
+        ErrorTest::new("1 / 0", EvalError::DivisionByZero),
+        ErrorTest::new("9223372036854775807 + 1", overflow("9223372036854775807 + 1")),
+        ErrorTest::new("-9223372036854775807 - 2", overflow("-9223372036854775807 - 2")),
+        ErrorTest::new("9223372036854775807 * 2", overflow("9223372036854775807 * 2")),
+        ErrorTest::new(
+            "let min = -9223372036854775807 - 1; min / -1",
+            overflow("-9223372036854775808 / -1"),
+        ),
+        ErrorTest::new(
+            "let min = -9223372036854775807 - 1; -min",
+            overflow("-(-9223372036854775808)"),
+        ),
+    ];
+    ErrorTest::test(tests);
+    assert_eq!(
+        test_eval(r#"try { 1 / 0 } catch (e) { e["kind"] }"#).unwrap().to_string(),
+        "DivisionByZero"
+    );
+}
+
 #[test]
 fn test_function_object() {
     match test_eval("fn(x) {x +2}") {
         Ok(obj) => {
-            if let Object::Function(ident, blk, _) = obj.as_ref() {
+            if let Object::Function(ident, blk, ..) = obj.as_ref() {
                 assert_eq!(ident.len(), 1);
-                assert_eq!(ident[0], "x");
+                assert_eq!(ident[0].name, "x");
                 assert_eq!(blk.statements.len(), 1);
                 match &blk.statements[0] {
                     Statement::ExpressionStatement(Expression::InfixExpression(
@@ -194,8 +1443,9 @@ fn test_function_object() {
                         l,
                         r,
                     )) => {
-                        if let Expression::Identifier(ident) = l.as_ref() {
+                        if let Expression::Local(ident, depth, slot) = l.as_ref() {
                             assert_eq!(ident, "x");
+                            assert_eq!((*depth, *slot), (0, 0));
                         } else {
                             panic!("Expected x ident but got {l}");
                         }
@@ -326,7 +1576,13 @@ fn test_error_exp() {
 fn test_eval(input: impl Into<String>) -> Result<Rc<Object>, EvalError> {
     let program = get_program(input.into());
     let env = Environment::new_with_builtin();
-    eval(Node::Program(program), &mut Rc::new(RefCell::new(env)))
+    eval(Node::Program(program), &Rc::new(RefCell::new(env)))
+}
+
+fn test_eval_with_limits(input: impl Into<String>, limits: Limits) -> Result<Rc<Object>, EvalError> {
+    let program = get_program(input.into());
+    let env = Environment::new_with_builtin();
+    eval_with_limits(program, &Rc::new(RefCell::new(env)), limits)
 }
 
 fn get_program(input: String) -> Program {
@@ -411,3 +1667,4 @@ impl ErrorTest {
         }
     }
 }
+
@@ -6,6 +6,23 @@ pub struct Lexer<'a> {
     position: usize,
     read_position: usize,
     ch: u8,
+    comments: Vec<Comment>,
+    /// Line `ch` is on, counting from 1.
+    line: usize,
+    /// Offset of the first byte of `line`.
+    line_start: usize,
+    token_line: usize,
+    token_column: usize,
+}
+
+/// A `//` comment skipped by the lexer. `text` is everything after the slashes up to the end
+/// of the line.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Comment {
+    pub text: String,
+    /// Whether the comment started on a line of its own rather than after some code.
+    pub own_line: bool,
+    pub line: usize,
 }
 
 impl<'a> Lexer<'a> {
@@ -15,6 +32,11 @@ impl<'a> Lexer<'a> {
             read_position: 0,
             input: input.as_bytes(),
             position: 0,
+            comments: Vec::new(),
+            line: 1,
+            line_start: 0,
+            token_line: 1,
+            token_column: 1,
         };
 
         lex.read_char();
@@ -26,16 +48,35 @@ impl<'a> Lexer<'a> {
 
     pub fn next_token(&mut self) -> Token {
         self.eat_whitespace();
+        self.token_line = self.line;
+        self.token_column = self.position - self.line_start + 1;
         let token = match self.ch {
             b'=' => match self.peak_char() {
                 b'=' => {
                     self.read_char();
                     Token::Equal
                 }
+                b'>' => {
+                    self.read_char();
+                    Token::FatArrow
+                }
                 _ => Token::Assign,
             },
+            b'.' => match self.peak_char() {
+                b'.' => {
+                    self.read_char();
+                    if self.peak_char() == b'.' {
+                        self.read_char();
+                        Token::Ellipsis
+                    } else {
+                        Token::DotDot
+                    }
+                }
+                _ => Token::Illegal,
+            },
             b';' => Token::Semicolon,
             b':' => Token::Colon,
+            b'?' => Token::Question,
             b'(' => Token::LParen,
             b')' => Token::RParent,
             b',' => Token::Comma,
@@ -51,12 +92,17 @@ impl<'a> Lexer<'a> {
                 }
                 _ => Token::Bang,
             },
-            b'-' => Token::Dash,
+            b'-' => match self.peak_char() {
+                b'>' => {
+                    self.read_char();
+                    Token::Arrow
+                }
+                _ => Token::Dash,
+            },
             b'/' => Token::ForwardSlash,
             b'*' => Token::Asterisk,
-            b'"' => {
-                Token::String(self.read_string_lit())
-            }
+            // Unterminated strings aren't valid tokens.
+            b'"' => self.read_string_lit().map_or(Token::Illegal, Token::String),
 
             b'<' => match self.peak_char() {
                 b'=' => {
@@ -74,17 +120,27 @@ impl<'a> Lexer<'a> {
             },
             ch => {
                 if ch.is_ascii_digit() {
-                    return Token::Int(self.read_int());
-                } else if ch.is_ascii_alphabetic() {
+                    // Nor are literals too big for an int.
+                    return self.read_int().map_or(Token::Illegal, Token::Int);
+                } else if ch.is_ascii_alphabetic() || ch == b'_' {
                     let ident = self.read_ident();
                     return match ident.as_str() {
                         "let" => Token::Let,
+                        "const" => Token::Const,
                         "fn" => Token::Function,
                         "return" => Token::Return,
                         "true" => Token::Bool(true),
                         "false" => Token::Bool(false),
                         "if" => Token::If,
                         "else" => Token::Else,
+                        "import" => Token::Import,
+                        "as" => Token::As,
+                        "export" => Token::Export,
+                        "throw" => Token::Throw,
+                        "match" => Token::Match,
+                        "try" => Token::Try,
+                        "catch" => Token::Catch,
+                        "finally" => Token::Finally,
                         _ => Token::Ident(ident),
                     };
                 }
@@ -95,43 +151,91 @@ impl<'a> Lexer<'a> {
         token
     }
 
-    fn read_int(&mut self) -> i64 {
+    fn read_int(&mut self) -> Option<i64> {
         let position = self.position;
 
         while self.ch.is_ascii_digit() {
             self.read_char();
         }
-        return String::from_utf8_lossy(&self.input[position..self.position])
-            .to_string()
+        String::from_utf8_lossy(&self.input[position..self.position])
             .parse::<i64>()
-            .expect("expected int to parse but failed??");
+            .ok()
     }
     fn read_ident(&mut self) -> String {
         let position = self.position;
 
-        while self.ch.is_ascii_alphabetic() {
+        while self.ch.is_ascii_alphabetic() || self.ch == b'_' {
             self.read_char();
         }
 
-        return String::from_utf8_lossy(&self.input[position..self.position]).to_string();
+        String::from_utf8_lossy(&self.input[position..self.position]).to_string()
     }
 
-    fn read_string_lit(&mut self) -> String {
+    fn read_string_lit(&mut self) -> Option<String> {
         self.read_char();
         let position = self.position;
         while self.ch != b'"' {
+            if self.position >= self.input.len() {
+                return None;
+            }
             self.read_char();
         }
-        return String::from_utf8_lossy(&self.input[position..self.position]).to_string();
+        Some(String::from_utf8_lossy(&self.input[position..self.position]).to_string())
+    }
+
+    /// Line the token most recently returned by `next_token` started on, counting from 1.
+    pub fn token_line(&self) -> usize {
+        self.token_line
+    }
+
+    /// Column the token most recently returned by `next_token` started at, in bytes counting
+    /// from 1.
+    pub fn token_column(&self) -> usize {
+        self.token_column
+    }
+
+    /// Comments skipped since the last call, i.e. those in front of the token most recently
+    /// returned by `next_token`.
+    pub fn take_comments(&mut self) -> Vec<Comment> {
+        std::mem::take(&mut self.comments)
     }
 
     fn eat_whitespace(&mut self) {
-        while self.ch.is_ascii_whitespace() {
+        let mut own_line = self.position == 0;
+        loop {
+            while self.ch.is_ascii_whitespace() {
+                own_line |= self.ch == b'\n';
+                self.read_char();
+            }
+            if self.ch == b'/' && self.peak_char() == b'/' {
+                let line = self.line;
+                let text = self.read_comment();
+                self.comments.push(Comment { text, own_line, line });
+                own_line = false;
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Skips a `//` comment up to (but not including) the end of the line.
+    fn read_comment(&mut self) -> String {
+        self.read_char();
+        self.read_char();
+        let position = self.position;
+        while self.ch != b'\n' && self.position < self.input.len() {
             self.read_char();
         }
+        String::from_utf8_lossy(&self.input[position..self.position])
+            .trim_end()
+            .to_string()
     }
 
     fn read_char(&mut self) {
+        if self.ch == b'\n' {
+            self.line += 1;
+            self.line_start = self.read_position;
+        }
         if self.read_position >= self.input.len() {
             self.ch = 7
         } else {
@@ -142,6 +246,6 @@ impl<'a> Lexer<'a> {
     }
 
     fn peak_char(&mut self) -> u8 {
-        self.input[self.read_position]
+        self.input.get(self.read_position).copied().unwrap_or(0)
     }
 }
@@ -1,6 +1,9 @@
 #![allow(dead_code)]
 
-use crate::{lexer::Lexer, token::Token};
+use crate::{
+    lexer::{Comment, Lexer},
+    token::Token,
+};
 
 #[test]
 fn lexer_test() {
@@ -22,6 +25,90 @@ fn lexer_test() {
     }
 }
 
+#[test]
+fn test_comments() {
+    let input = "// leading comment
+    let x = 5; // trailing comment
+    x / 2 //";
+    let mut lex = Lexer::new(input);
+    let expected_tokens: Vec<Token> = vec![
+        Token::Let,
+        Token::new("x"),
+        Token::Assign,
+        Token::Int(5),
+        Token::Semicolon,
+        Token::new("x"),
+        Token::ForwardSlash,
+        Token::Int(2),
+        Token::Eof,
+    ];
+    for expected_token in expected_tokens {
+        assert_eq!(expected_token, lex.next_token());
+    }
+
+    let mut lex = Lexer::new("// leading\nlet x = 5; // trailing\n// own line\nx");
+    let comment = |text: &str, own_line, line| Comment {
+        text: text.to_string(),
+        own_line,
+        line,
+    };
+    assert_eq!(lex.next_token(), Token::Let);
+    assert_eq!(lex.take_comments(), vec![comment(" leading", true, 1)]);
+    for _ in 0..4 {
+        lex.next_token();
+        assert!(lex.take_comments().is_empty());
+    }
+    assert_eq!(lex.next_token(), Token::new("x"));
+    assert_eq!(
+        lex.take_comments(),
+        vec![comment(" trailing", false, 2), comment(" own line", true, 3)]
+    );
+}
+
+#[test]
+fn test_token_lines() {
+    let mut lex = Lexer::new("let x = \"a\nb\";\n\n// comment\nx");
+    let mut lines = Vec::new();
+    while lex.next_token() != Token::Eof {
+        lines.push(lex.token_line());
+    }
+    assert_eq!(lines, vec![1, 1, 1, 1, 2, 5]);
+}
+
+#[test]
+fn test_illegal_literals() {
+    let mut lex = Lexer::new("99999999999999999999 \"unterminated");
+    assert_eq!(lex.next_token(), Token::Illegal);
+    assert_eq!(lex.next_token(), Token::Illegal);
+    assert_eq!(lex.next_token(), Token::Eof);
+}
+
+#[test]
+fn test_token_columns() {
+    let mut lex = Lexer::new("let x = 10;\n  fn(a) -> a\n\"s\"");
+    let mut positions = Vec::new();
+    while lex.next_token() != Token::Eof {
+        positions.push((lex.token_line(), lex.token_column()));
+    }
+    assert_eq!(
+        positions,
+        vec![
+            (1, 1),
+            (1, 5),
+            (1, 7),
+            (1, 9),
+            (1, 11),
+            (2, 3),
+            (2, 5),
+            (2, 6),
+            (2, 7),
+            (2, 9),
+            (2, 12),
+            (3, 1),
+        ]
+    );
+}
+
 #[test]
 fn test_next_token() {
     let input = r#"
@@ -45,6 +132,14 @@ if (5 < 10) {
 "";
 [1,2];
 {"foo":"bar"};
+import "lib/math" as m;
+export let x = 1;
+fn(a: int) -> bool { a - -1 }
+try { throw e } catch (e) {} finally {}
+is_ok()?;
+match x { [a, ..] => a, _ => 0 }
+let [b, ...c] = d;
+const e = 1;
 "#;
     let mut lex = Lexer::new(input);
 
@@ -138,7 +233,80 @@ if (5 < 10) {
         Token::String("bar".to_owned()),
         Token::RBrace,
         Token::Semicolon,
-
+        Token::Import,
+        Token::String("lib/math".to_owned()),
+        Token::As,
+        Token::new("m"),
+        Token::Semicolon,
+        Token::Export,
+        Token::Let,
+        Token::new("x"),
+        Token::Assign,
+        Token::Int(1),
+        Token::Semicolon,
+        Token::Function,
+        Token::LParen,
+        Token::new("a"),
+        Token::Colon,
+        Token::new("int"),
+        Token::RParent,
+        Token::Arrow,
+        Token::new("bool"),
+        Token::LBrace,
+        Token::new("a"),
+        Token::Dash,
+        Token::Dash,
+        Token::Int(1),
+        Token::RBrace,
+        Token::Try,
+        Token::LBrace,
+        Token::Throw,
+        Token::new("e"),
+        Token::RBrace,
+        Token::Catch,
+        Token::LParen,
+        Token::new("e"),
+        Token::RParent,
+        Token::LBrace,
+        Token::RBrace,
+        Token::Finally,
+        Token::LBrace,
+        Token::RBrace,
+        Token::new("is_ok"),
+        Token::LParen,
+        Token::RParent,
+        Token::Question,
+        Token::Semicolon,
+        Token::Match,
+        Token::new("x"),
+        Token::LBrace,
+        Token::LBracket,
+        Token::new("a"),
+        Token::Comma,
+        Token::DotDot,
+        Token::RBracket,
+        Token::FatArrow,
+        Token::new("a"),
+        Token::Comma,
+        Token::new("_"),
+        Token::FatArrow,
+        Token::Int(0),
+        Token::RBrace,
+        Token::Let,
+        Token::LBracket,
+        Token::new("b"),
+        Token::Comma,
+        Token::Ellipsis,
+        Token::new("c"),
+        Token::RBracket,
+        Token::Assign,
+        Token::new("d"),
+        Token::Semicolon,
+        Token::Const,
+        Token::new("e"),
+        Token::Assign,
+        Token::Int(1),
+        Token::Semicolon,
     ];
     for expected_token in expected_stuff {
         let actual_token = lex.next_token();
@@ -21,6 +21,11 @@ pub enum Token {
     GreaterThan,
     GreaterThanEqual,
     Colon,
+    Arrow,
+    Question,
+    FatArrow,
+    DotDot,
+    Ellipsis,
 
     Plus,
     Comma,
@@ -34,11 +39,23 @@ pub enum Token {
 
     Function,
     Let,
+    Const,
 
     If,
     Else,
     Return,
     Bool(bool),
+
+    Import,
+    As,
+    Export,
+
+    Throw,
+    Try,
+    Catch,
+    Finally,
+
+    Match,
 }
 
 impl From<i64> for Token {
@@ -97,6 +114,7 @@ impl Display for Token {
             Token::RBrace => write!(f, "}}"),
             Token::Function => write!(f, "fn"),
             Token::Let => write!(f, "let"),
+            Token::Const => write!(f, "const"),
             Token::If => write!(f, "if"),
             Token::Else => write!(f, "else"),
             Token::Return => write!(f, "return"),
@@ -106,6 +124,19 @@ impl Display for Token {
             Token::LBracket => write!(f, "["),
             Token::RBracket => write!(f, "]"),
             Token::Colon => write!(f, ":"),
+            Token::Arrow => write!(f, "->"),
+            Token::Question => write!(f, "?"),
+            Token::FatArrow => write!(f, "=>"),
+            Token::DotDot => write!(f, ".."),
+            Token::Ellipsis => write!(f, "..."),
+            Token::Import => write!(f, "import"),
+            Token::As => write!(f, "as"),
+            Token::Export => write!(f, "export"),
+            Token::Throw => write!(f, "throw"),
+            Token::Try => write!(f, "try"),
+            Token::Catch => write!(f, "catch"),
+            Token::Finally => write!(f, "finally"),
+            Token::Match => write!(f, "match"),
         }
     }
 }
@@ -0,0 +1,12 @@
+//! A language server for Monkey. It speaks LSP over stdio and publishes diagnostics from the
+//! parser, linter and type checker. It also offers go to definition, hover, completion, document
+//! symbols and formatting.
+
+mod server;
+pub mod symbols;
+pub mod transport;
+
+pub use server::{serve, Server};
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,7 @@
+fn main() {
+    let code = lsp::serve(std::io::stdin().lock(), std::io::stdout().lock()).unwrap_or_else(|e| {
+        eprintln!("lsp: {e}");
+        1
+    });
+    std::process::exit(code);
+}
@@ -0,0 +1,431 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use eval::builtin::get_builtin_fns;
+use eval::check;
+use eval::lint::{self, Diagnostic, Lint, Severity};
+use eval::prelude;
+use lexer::lexer::Lexer;
+use parser::ast::{Identifier, Type};
+use parser::parser::Parser;
+use parser::printer;
+use serde_json::{json, Value};
+
+use crate::symbols::{Position, Span, Symbol, SymbolKind, Symbols};
+use crate::transport::{read_message, write_message};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Serves a client over `input` and `output` until it sends `exit` or closes the input. Returns
+/// the code the process should exit with: 0 if the client asked the server to shut down first,
+/// 1 otherwise. A malformed message gets a parse error back, and the server keeps reading.
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> io::Result<i32> {
+    let mut server = Server::new();
+    loop {
+        let message = match read_message(&mut input) {
+            Ok(Some(message)) => message,
+            Ok(None) => return Ok(1),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                let error = json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": {"code": PARSE_ERROR, "message": e.to_string()},
+                });
+                write_message(&mut output, &error)?;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        for reply in server.handle(&message) {
+            write_message(&mut output, &reply)?;
+        }
+        if let Some(code) = server.exit_code {
+            return Ok(code);
+        }
+    }
+}
+
+/// An open document, analyzed whenever it changes.
+struct Document {
+    text: String,
+    symbols: Symbols,
+    /// Types the checker infers, if the document parses.
+    types: HashMap<Identifier, Type>,
+}
+
+impl Document {
+    fn new(text: String) -> Self {
+        let symbols = Symbols::analyze(&text);
+        let mut parser = Parser::new(Lexer::new(&text));
+        let program = parser.parse_program();
+        let types = if parser.parse_errors.is_empty() {
+            check::infer(&program)
+        } else {
+            HashMap::new()
+        };
+        Document {
+            text,
+            symbols,
+            types,
+        }
+    }
+
+    /// Text of `line`, counting from 1.
+    fn line(&self, line: usize) -> &str {
+        self.text
+            .split('\n')
+            .nth(line.saturating_sub(1))
+            .unwrap_or("")
+    }
+
+    /// `position` as an LSP position, whose lines count from 0 and whose columns count UTF-16
+    /// code units.
+    fn lsp_position(&self, position: Position) -> Value {
+        let line = self.line(position.line);
+        let byte = position.column.saturating_sub(1).min(line.len());
+        let character = line
+            .get(..byte)
+            .map_or(byte, |before| before.encode_utf16().count());
+        json!({"line": position.line.saturating_sub(1), "character": character})
+    }
+
+    fn position(&self, lsp: &Value) -> Option<Position> {
+        let line = lsp["line"].as_u64()? as usize + 1;
+        let character = lsp["character"].as_u64()? as usize;
+        let text = self.line(line);
+        let mut units = 0;
+        let byte = text
+            .char_indices()
+            .find(|(_, c)| {
+                units += c.len_utf16();
+                units > character
+            })
+            .map_or(text.len(), |(byte, _)| byte);
+        Some(Position {
+            line,
+            column: byte + 1,
+        })
+    }
+
+    fn range(&self, start: Position, end: Position) -> Value {
+        json!({"start": self.lsp_position(start), "end": self.lsp_position(end)})
+    }
+
+    fn span_range(&self, span: Span) -> Value {
+        self.range(span.start, span.end())
+    }
+
+    /// The whole of `line`.
+    fn line_range(&self, line: usize) -> Value {
+        let line = line.max(1);
+        let end = Position {
+            line,
+            column: self.line(line).len() + 1,
+        };
+        self.range(Position { line, column: 1 }, end)
+    }
+
+    fn type_of(&self, symbol: &Symbol) -> Option<&Type> {
+        self.types.get(&symbol.name)
+    }
+}
+
+pub struct Server {
+    documents: HashMap<String, Document>,
+    builtins: Vec<String>,
+    /// Functions the prelude defines, with their types.
+    prelude: Vec<(String, Type)>,
+    shutdown: bool,
+    exit_code: Option<i32>,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Server {
+    pub fn new() -> Self {
+        let mut builtins: Vec<String> = get_builtin_fns().into_keys().collect();
+        builtins.sort();
+        let prelude = Document::new(prelude::SOURCE.to_string());
+        let prelude = prelude
+            .symbols
+            .symbols()
+            .iter()
+            .filter(|symbol| symbol.top_level() && symbol.kind == SymbolKind::Let)
+            .map(|symbol| {
+                let ty = prelude.type_of(symbol).cloned().unwrap_or(Type::Any);
+                (symbol.name.clone(), ty)
+            })
+            .collect();
+        Server {
+            documents: HashMap::new(),
+            builtins,
+            prelude,
+            shutdown: false,
+            exit_code: None,
+        }
+    }
+
+    /// Exit code the client asked for with `exit`, once it has.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Handles a message from the client, returning the messages to send back.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let Some(method) = message["method"].as_str() else {
+            // A response; the server never sends requests.
+            return Vec::new();
+        };
+        let params = &message["params"];
+        match message.get("id") {
+            Some(id) => {
+                let response = match self.request(method, params) {
+                    Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                    Err((code, message)) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {"code": code, "message": message},
+                    }),
+                };
+                vec![response]
+            }
+            None => self.notification(method, params),
+        }
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        if self.shutdown {
+            return Err((INVALID_REQUEST, "the server is shutting down".to_string()));
+        }
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    // Full text on every change.
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": {},
+                    "documentSymbolProvider": true,
+                    "documentFormattingProvider": true,
+                },
+                "serverInfo": {"name": "monkey-lsp"},
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => {
+                let (uri, document) = self.document(params)?;
+                let position = Server::position(document, params)?;
+                Ok(match document.symbols.at(position) {
+                    Some((_, Some(symbol))) => {
+                        json!({"uri": uri, "range": document.span_range(symbol.span)})
+                    }
+                    _ => Value::Null,
+                })
+            }
+            "textDocument/hover" => {
+                let (_, document) = self.document(params)?;
+                let position = Server::position(document, params)?;
+                Ok(match self.hover(document, position) {
+                    Some(hover) => json!({
+                        "contents": {"kind": "markdown", "value": format!("```monkey\n{hover}\n```")},
+                    }),
+                    None => Value::Null,
+                })
+            }
+            "textDocument/completion" => {
+                let (_, document) = self.document(params)?;
+                let position = Server::position(document, params)?;
+                Ok(Value::Array(self.completions(document, position)))
+            }
+            "textDocument/documentSymbol" => {
+                let (_, document) = self.document(params)?;
+                let symbols = document
+                    .symbols
+                    .symbols()
+                    .iter()
+                    .filter(|symbol| symbol.top_level())
+                    .map(|symbol| {
+                        let range = document.span_range(symbol.span);
+                        json!({
+                            "name": symbol.name,
+                            "detail": document.type_of(symbol).map(Type::to_string),
+                            "kind": match symbol.kind {
+                                SymbolKind::Import => 2,
+                                _ if symbol.function => 12,
+                                _ => 13,
+                            },
+                            "range": range,
+                            "selectionRange": range,
+                        })
+                    })
+                    .collect();
+                Ok(Value::Array(symbols))
+            }
+            "textDocument/formatting" => {
+                let (_, document) = self.document(params)?;
+                Ok(match printer::format(&document.text) {
+                    Ok(formatted) if formatted == document.text => json!([]),
+                    Ok(formatted) => {
+                        let lines = document.text.split('\n').count();
+                        let end = Position {
+                            line: lines,
+                            column: document.line(lines).len() + 1,
+                        };
+                        let range = document.range(Position { line: 1, column: 1 }, end);
+                        json!([{"range": range, "newText": formatted}])
+                    }
+                    // Documents that don't parse are left alone.
+                    Err(_) => Value::Null,
+                })
+            }
+            method => Err((METHOD_NOT_FOUND, format!("{method} is not supported"))),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let text = match method {
+            "exit" => {
+                self.exit_code = Some(if self.shutdown { 0 } else { 1 });
+                return Vec::new();
+            }
+            "textDocument/didOpen" => params["textDocument"]["text"].as_str(),
+            // Changes always carry the full text, as asked for in `initialize`.
+            "textDocument/didChange" => params["contentChanges"]
+                .as_array()
+                .and_then(|changes| changes.last())
+                .and_then(|change| change["text"].as_str()),
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![publish_diagnostics(uri, Vec::new())];
+            }
+            _ => None,
+        };
+        let Some(text) = text else {
+            return Vec::new();
+        };
+        let document = Document::new(text.to_string());
+        let diagnostics = diagnose(&document.text)
+            .iter()
+            .map(|d| {
+                json!({
+                    "range": document.line_range(d.line),
+                    "severity": match d.lint.severity() {
+                        Severity::Error => 1,
+                        Severity::Warning => 2,
+                    },
+                    "code": d.lint.code(),
+                    "source": "monkey",
+                    "message": d.lint.to_string(),
+                })
+            })
+            .collect();
+        self.documents.insert(uri.to_string(), document);
+        vec![publish_diagnostics(uri, diagnostics)]
+    }
+
+    fn document<'a>(&'a self, params: &'a Value) -> Result<(&'a str, &'a Document), (i64, String)> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match self.documents.get(uri) {
+            Some(document) => Ok((uri, document)),
+            None => Err((INVALID_PARAMS, format!("{uri} is not open"))),
+        }
+    }
+
+    fn position(document: &Document, params: &Value) -> Result<Position, (i64, String)> {
+        document
+            .position(&params["position"])
+            .ok_or_else(|| (INVALID_PARAMS, "missing position".to_string()))
+    }
+
+    /// What the name at `position` is, with its type if it is known.
+    fn hover(&self, document: &Document, position: Position) -> Option<String> {
+        let (name, symbol) = document.symbols.at(position)?;
+        let (what, ty) = match symbol {
+            Some(symbol) => {
+                let what = match symbol.kind {
+                    SymbolKind::Let => "let",
+                    SymbolKind::Param => "param",
+                    SymbolKind::Import => "import",
+                    SymbolKind::Catch => "catch",
+                };
+                (what, document.type_of(symbol).cloned())
+            }
+            None => match self.prelude.iter().find(|(n, _)| n == name) {
+                Some((_, ty)) => ("prelude", Some(ty.clone())),
+                None if self.builtins.iter().any(|n| n == name) => {
+                    ("builtin", Some(check::builtin_type(name)))
+                }
+                None => return None,
+            },
+        };
+        Some(match ty {
+            Some(ty) if symbol.is_none_or(|s| s.kind != SymbolKind::Import) => {
+                format!("{what} {name}: {ty}")
+            }
+            _ => format!("{what} {name}"),
+        })
+    }
+
+    /// Names in scope at `position`, then the prelude's and the builtins.
+    fn completions(&self, document: &Document, position: Position) -> Vec<Value> {
+        let mut labels = Vec::new();
+        let mut items = Vec::new();
+        for symbol in document.symbols.visible(position) {
+            labels.push(symbol.name.as_str());
+            items.push(json!({
+                "label": symbol.name,
+                "kind": match symbol.kind {
+                    SymbolKind::Import => 9,
+                    _ if symbol.function => 3,
+                    _ => 6,
+                },
+                "detail": document.type_of(symbol).map(Type::to_string),
+            }));
+        }
+        let globals = self
+            .prelude
+            .iter()
+            .map(|(name, ty)| (name, format!("prelude {ty}")))
+            .chain(self.builtins.iter().map(|name| {
+                let detail = match check::builtin_type(name) {
+                    Type::Any => "builtin".to_string(),
+                    ty => format!("builtin {ty}"),
+                };
+                (name, detail)
+            }));
+        for (name, detail) in globals {
+            if !labels.contains(&name.as_str()) {
+                labels.push(name);
+                items.push(json!({"label": name, "kind": 3, "detail": detail}));
+            }
+        }
+        items
+    }
+}
+
+/// What the linter finds in `text`, and the type checker if it parses.
+fn diagnose(text: &str) -> Vec<Diagnostic> {
+    let mut found = lint::lint_source(text);
+    if !found.iter().any(|d| matches!(d.lint, Lint::Parse(_))) {
+        found.extend(check::check_source(text));
+    }
+    found
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": {"uri": uri, "diagnostics": diagnostics},
+    })
+}
@@ -0,0 +1,453 @@
+//! Where names are defined and used in a document, worked out from its tokens so that it still
+//! works while the document doesn't parse.
+//!
+//! Scopes follow the evaluator: only function bodies open one, so a `let` in an `if` block
+//! belongs to the enclosing function. A use of a name refers to the innermost scope defining it,
+//! to the last definition in that scope before the use, or to the first one after it when there
+//! is none before, since functions may use names that are defined after them.
+
+use eval::modules::default_binding;
+use lexer::lexer::Lexer;
+use lexer::token::Token;
+
+/// A place in the document: line and byte column, both counting from 1, as the lexer reports
+/// them.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// The text of a token on a single line.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    pub start: Position,
+    pub len: usize,
+}
+
+impl Span {
+    /// Whether `position` is on the span or right after it, where the cursor is after typing it.
+    pub fn contains(&self, position: Position) -> bool {
+        position.line == self.start.line
+            && (self.start.column..=self.start.column + self.len).contains(&position.column)
+    }
+
+    pub fn end(&self) -> Position {
+        Position {
+            line: self.start.line,
+            column: self.start.column + self.len,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SymbolKind {
+    Let,
+    Param,
+    Import,
+    Catch,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The name in the definition, or the path of an `import` without `as`.
+    pub span: Span,
+    /// Whether the symbol is a `let` bound to a function literal, or a function declaration.
+    pub function: bool,
+    scope: usize,
+}
+
+impl Symbol {
+    /// Whether the symbol is defined outside any function.
+    pub fn top_level(&self) -> bool {
+        self.scope == 0
+    }
+}
+
+#[derive(Debug)]
+struct Scope {
+    parent: Option<usize>,
+    start: Position,
+    end: Position,
+}
+
+#[derive(Debug)]
+struct Reference {
+    name: String,
+    span: Span,
+    scope: usize,
+    symbol: Option<usize>,
+}
+
+#[derive(Debug)]
+pub struct Symbols {
+    symbols: Vec<Symbol>,
+    references: Vec<Reference>,
+    /// Function bodies, with the whole document first.
+    scopes: Vec<Scope>,
+}
+
+impl Symbols {
+    pub fn analyze(source: &str) -> Symbols {
+        let mut lexer = Lexer::new(source);
+        let mut tokens = Vec::new();
+        loop {
+            let token = lexer.next_token();
+            if token == Token::Eof {
+                break;
+            }
+            let start = Position {
+                line: lexer.token_line(),
+                column: lexer.token_column(),
+            };
+            tokens.push((token, start));
+        }
+
+        let mut analyzer = Analyzer {
+            tokens: &tokens,
+            next: 0,
+            symbols: Symbols {
+                symbols: Vec::new(),
+                references: Vec::new(),
+                scopes: vec![Scope {
+                    parent: None,
+                    start: Position { line: 1, column: 1 },
+                    end: Position {
+                        line: usize::MAX,
+                        column: usize::MAX,
+                    },
+                }],
+            },
+            scope: 0,
+            braces: Vec::new(),
+        };
+        analyzer.run();
+        let mut symbols = analyzer.symbols;
+        for i in 0..symbols.references.len() {
+            let reference = &symbols.references[i];
+            symbols.references[i].symbol =
+                symbols.resolve(&reference.name, reference.scope, reference.span.start);
+        }
+        symbols
+    }
+
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    /// The name at `position`, and the symbol it refers to if the document defines it.
+    pub fn at(&self, position: Position) -> Option<(&str, Option<&Symbol>)> {
+        if let Some(symbol) = self.symbols.iter().find(|s| s.span.contains(position)) {
+            return Some((&symbol.name, Some(symbol)));
+        }
+        self.references
+            .iter()
+            .find(|r| r.span.contains(position))
+            .map(|r| (r.name.as_str(), r.symbol.map(|i| &self.symbols[i])))
+    }
+
+    /// The symbols that can be used at `position`, innermost first, leaving out those hidden by
+    /// an inner symbol of the same name.
+    pub fn visible(&self, position: Position) -> Vec<&Symbol> {
+        let mut visible: Vec<&Symbol> = Vec::new();
+        let mut scope = Some(self.scope_at(position));
+        while let Some(s) = scope {
+            for symbol in self.symbols.iter().filter(|symbol| symbol.scope == s) {
+                if visible.iter().all(|v| v.name != symbol.name) {
+                    visible.push(symbol);
+                }
+            }
+            scope = self.scopes[s].parent;
+        }
+        visible
+    }
+
+    fn scope_at(&self, position: Position) -> usize {
+        (0..self.scopes.len())
+            .filter(|&s| self.scopes[s].start <= position && position <= self.scopes[s].end)
+            .max_by_key(|&s| self.scopes[s].start)
+            .unwrap_or(0)
+    }
+
+    fn resolve(&self, name: &str, scope: usize, position: Position) -> Option<usize> {
+        let mut scope = Some(scope);
+        while let Some(s) = scope {
+            let defined: Vec<usize> = (0..self.symbols.len())
+                .filter(|&i| self.symbols[i].scope == s && self.symbols[i].name == name)
+                .collect();
+            if let Some(&first) = defined.first() {
+                let before = defined
+                    .iter()
+                    .rev()
+                    .find(|&&i| self.symbols[i].span.start < position);
+                return Some(before.copied().unwrap_or(first));
+            }
+            scope = self.scopes[s].parent;
+        }
+        None
+    }
+}
+
+struct Analyzer<'a> {
+    tokens: &'a [(Token, Position)],
+    next: usize,
+    symbols: Symbols,
+    /// The innermost function body the analyzer is in.
+    scope: usize,
+    /// For each `{` not yet closed, the scope it opened if it is a function body.
+    braces: Vec<Option<usize>>,
+}
+
+impl Analyzer<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(token, _)| token)
+    }
+
+    fn span(&self, i: usize) -> Span {
+        let (token, start) = &self.tokens[i];
+        let len = match token {
+            Token::Ident(name) => name.len(),
+            Token::String(s) => s.len() + 2,
+            token => token.to_string().len(),
+        };
+        Span { start: *start, len }
+    }
+
+    fn define(&mut self, name: String, kind: SymbolKind, span: Span, function: bool) {
+        self.symbols.symbols.push(Symbol {
+            name,
+            kind,
+            span,
+            function,
+            scope: self.scope,
+        });
+    }
+
+    fn run(&mut self) {
+        while let Some((token, start)) = self.tokens.get(self.next) {
+            let i = self.next;
+            self.next += 1;
+            match token {
+                Token::Let | Token::Const => self.binding(),
+                Token::Import => self.import(),
+                Token::Function => self.function(),
+                Token::Catch => self.catch(),
+                Token::LBrace => self.braces.push(None),
+                Token::RBrace => {
+                    if let Some(Some(scope)) = self.braces.pop() {
+                        self.symbols.scopes[scope].end = *start;
+                        self.scope = self.symbols.scopes[scope].parent.unwrap_or(0);
+                    }
+                }
+                Token::Ident(name) => self.symbols.references.push(Reference {
+                    name: name.clone(),
+                    span: self.span(i),
+                    scope: self.scope,
+                    symbol: None,
+                }),
+                _ => {}
+            }
+        }
+    }
+
+    /// `let name [: type] = value` or `let pattern = value`, after the `let` (or `const`).
+    fn binding(&mut self) {
+        if let Some(Token::LBracket | Token::LBrace) = self.peek() {
+            self.pattern(SymbolKind::Let);
+            return;
+        }
+        let Some(Token::Ident(name)) = self.peek() else {
+            return;
+        };
+        let (name, span) = (name.clone(), self.span(self.next));
+        self.next += 1;
+        self.annotation();
+        let function = matches!(
+            self.tokens.get(self.next..self.next + 2),
+            Some([(Token::Assign, _), (Token::Function, _)])
+        );
+        self.define(name, SymbolKind::Let, span, function);
+    }
+
+    /// An array or hash pattern starting at the next token, defining the names it binds.
+    fn pattern(&mut self, kind: SymbolKind) {
+        let mut depth = 0;
+        while let Some(token) = self.peek() {
+            match token {
+                Token::LBracket | Token::LBrace => depth += 1,
+                Token::RBracket | Token::RBrace => depth -= 1,
+                Token::Ident(name) if name != "_" => {
+                    let (name, span) = (name.clone(), self.span(self.next));
+                    self.define(name, kind, span, false);
+                }
+                Token::Ident(_)
+                | Token::Comma
+                | Token::Colon
+                | Token::DotDot
+                | Token::Ellipsis
+                | Token::Dash
+                | Token::Int(_)
+                | Token::String(_)
+                | Token::Bool(_) => {}
+                _ => return,
+            }
+            self.next += 1;
+            if depth == 0 {
+                return;
+            }
+        }
+    }
+
+    /// `import "path" [as name]`, after the `import`.
+    fn import(&mut self) {
+        let Some(Token::String(path)) = self.peek() else {
+            return;
+        };
+        let (mut name, mut span) = (default_binding(path), self.span(self.next));
+        self.next += 1;
+        if let Some(Token::As) = self.peek() {
+            self.next += 1;
+            let Some(Token::Ident(alias)) = self.peek() else {
+                return;
+            };
+            (name, span) = (alias.clone(), self.span(self.next));
+            self.next += 1;
+        }
+        self.define(name, SymbolKind::Import, span, false);
+    }
+
+    /// `catch (name)`, after the `catch`. Like a `let`, the name belongs to the enclosing
+    /// function.
+    fn catch(&mut self) {
+        let Some([(Token::LParen, _), (Token::Ident(name), _)]) =
+            self.tokens.get(self.next..self.next + 2)
+        else {
+            return;
+        };
+        let (name, span) = (name.clone(), self.span(self.next + 1));
+        self.next += 2;
+        self.define(name, SymbolKind::Catch, span, false);
+    }
+
+    /// `fn [name](params) [-> type] { body }`, after the `fn`. A declaration's name is defined
+    /// in the enclosing scope, the params in the scope of the body, which lasts until its
+    /// closing brace.
+    fn function(&mut self) {
+        let (_, start) = self.tokens[self.next - 1];
+        if let Some(Token::Ident(name)) = self.peek() {
+            let (name, span) = (name.clone(), self.span(self.next));
+            self.next += 1;
+            self.define(name, SymbolKind::Let, span, true);
+        }
+        self.symbols.scopes.push(Scope {
+            parent: Some(self.scope),
+            start,
+            end: Position {
+                line: usize::MAX,
+                column: usize::MAX,
+            },
+        });
+        let enclosing = self.scope;
+        self.scope = self.symbols.scopes.len() - 1;
+
+        if let Some(Token::LParen) = self.peek() {
+            self.next += 1;
+            while let Some(token) = self.peek() {
+                match token {
+                    Token::Ident(name) => {
+                        let (name, span) = (name.clone(), self.span(self.next));
+                        self.next += 1;
+                        self.define(name, SymbolKind::Param, span, false);
+                        self.annotation();
+                    }
+                    Token::LBracket | Token::LBrace => {
+                        self.pattern(SymbolKind::Param);
+                        self.annotation();
+                    }
+                    Token::Comma | Token::Ellipsis => self.next += 1,
+                    Token::Assign => self.default_value(),
+                    _ => break,
+                }
+            }
+            if let Some(Token::RParent) = self.peek() {
+                self.next += 1;
+            }
+        }
+        if let Some(Token::Arrow) = self.peek() {
+            self.next += 1;
+            self.skip_type();
+        }
+
+        if let Some(Token::LBrace) = self.peek() {
+            self.next += 1;
+            self.braces.push(Some(self.scope));
+        } else {
+            // Without a body the params can't be used anywhere.
+            let scope = self.scope;
+            self.symbols.scopes[scope].end = self.tokens[self.next - 1].1;
+            self.scope = enclosing;
+        }
+    }
+
+    /// A param's default value, after the `=`, up to the `,` or `)` ending it. The names it
+    /// uses are looked up in the function's scope, where it's evaluated.
+    fn default_value(&mut self) {
+        self.next += 1;
+        let mut depth = 0;
+        while let Some(token) = self.peek() {
+            match token {
+                Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+                Token::RParent | Token::RBracket | Token::RBrace | Token::Comma if depth == 0 => {
+                    return
+                }
+                Token::RParent | Token::RBracket | Token::RBrace => depth -= 1,
+                Token::Ident(name) => self.symbols.references.push(Reference {
+                    name: name.clone(),
+                    span: self.span(self.next),
+                    scope: self.scope,
+                    symbol: None,
+                }),
+                _ => {}
+            }
+            self.next += 1;
+        }
+    }
+
+    /// Skips `: type` if it comes next, so type names aren't taken for uses of names.
+    fn annotation(&mut self) {
+        if let Some(Token::Colon) = self.peek() {
+            self.next += 1;
+            self.skip_type();
+        }
+    }
+
+    fn skip_type(&mut self) {
+        match self.peek() {
+            Some(Token::Ident(_)) => self.next += 1,
+            Some(Token::Function) => {
+                self.next += 1;
+                if let Some(Token::LParen) = self.peek() {
+                    self.next += 1;
+                    while let Some(token) = self.peek() {
+                        match token {
+                            Token::RParent => {
+                                self.next += 1;
+                                break;
+                            }
+                            Token::Comma => self.next += 1,
+                            Token::Ident(_) | Token::Function => self.skip_type(),
+                            _ => break,
+                        }
+                    }
+                }
+                if let Some(Token::Arrow) = self.peek() {
+                    self.next += 1;
+                    self.skip_type();
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,440 @@
+use serde_json::{json, Value};
+
+use crate::symbols::{Position, SymbolKind, Symbols};
+use crate::transport::{read_message, write_message};
+use crate::{serve, Server};
+
+const URI: &str = "file:///test.monkey";
+
+/// Drives a `Server` the way an editor would, one message at a time.
+struct Client {
+    server: Server,
+    next_id: i64,
+}
+
+impl Client {
+    fn new() -> Self {
+        let mut client = Client {
+            server: Server::new(),
+            next_id: 0,
+        };
+        client.request("initialize", json!({"capabilities": {}}));
+        client.notify("initialized", json!({}));
+        client
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let replies = self.server.handle(&json!({
+            "jsonrpc": "2.0",
+            "id": self.next_id,
+            "method": method,
+            "params": params,
+        }));
+        assert_eq!(replies.len(), 1, "{method} should get one response");
+        assert_eq!(replies[0]["id"], self.next_id);
+        replies[0].clone()
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> Vec<Value> {
+        self.server
+            .handle(&json!({"jsonrpc": "2.0", "method": method, "params": params}))
+    }
+
+    /// Opens `text` and returns the diagnostics published for it.
+    fn open(&mut self, text: &str) -> Value {
+        let published = self.notify(
+            "textDocument/didOpen",
+            json!({"textDocument": {"uri": URI, "languageId": "monkey", "version": 1, "text": text}}),
+        );
+        assert_eq!(published[0]["method"], "textDocument/publishDiagnostics");
+        published[0]["params"]["diagnostics"].clone()
+    }
+
+    /// Result of a request about the document at `line` and `character`, counting from 0.
+    fn at(&mut self, method: &str, line: usize, character: usize) -> Value {
+        self.request(
+            method,
+            json!({
+                "textDocument": {"uri": URI},
+                "position": {"line": line, "character": character},
+            }),
+        )["result"]
+            .clone()
+    }
+}
+
+fn range(start: (usize, usize), end: (usize, usize)) -> Value {
+    json!({
+        "start": {"line": start.0, "character": start.1},
+        "end": {"line": end.0, "character": end.1},
+    })
+}
+
+const SOURCE: &str = "let twice = fn(f: fn(int) -> int, x: int) -> int {
+  let once = f(x);
+  f(once)
+};
+let inc = fn(n) { n + 1 };
+let total = twice(inc, 5);
+if (total > 3) { let big = true; big }
+";
+
+#[test]
+fn test_initialize() {
+    let mut client = Client::new();
+    let capabilities = &client.request("initialize", json!({}))["result"]["capabilities"];
+    assert_eq!(capabilities["textDocumentSync"], 1);
+    assert_eq!(capabilities["definitionProvider"], true);
+    assert_eq!(capabilities["documentFormattingProvider"], true);
+    let unknown = client.request("workspace/symbol", json!({"query": ""}));
+    assert_eq!(unknown["error"]["code"], -32601);
+}
+
+#[test]
+fn test_diagnostics() {
+    let mut client = Client::new();
+    let diagnostics = client.open("let x = 5;\nlet y = ;\n");
+    assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+    assert_eq!(diagnostics[0]["range"], range((1, 0), (1, 9)));
+    assert_eq!(diagnostics[0]["severity"], 1);
+    assert_eq!(diagnostics[0]["code"], "parse-error");
+
+    // Lints and type errors once it parses.
+    let changed = client.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": {"uri": URI, "version": 2},
+            "contentChanges": [{"text": "let unused = 1;\n\"a\" - 1;\n"}],
+        }),
+    );
+    let diagnostics = &changed[0]["params"]["diagnostics"];
+    let codes: Vec<(&str, &Value)> = diagnostics
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|d| (d["code"].as_str().unwrap(), &d["range"]["start"]["line"]))
+        .collect();
+    assert_eq!(
+        codes,
+        vec![
+            ("unused-binding", &json!(0)),
+            ("invalid-operands", &json!(1))
+        ]
+    );
+    assert_eq!(diagnostics[0]["severity"], 2);
+
+    assert_eq!(client.open(SOURCE), json!([]));
+    let closed = client.notify(
+        "textDocument/didClose",
+        json!({"textDocument": {"uri": URI}}),
+    );
+    assert_eq!(closed[0]["params"]["diagnostics"], json!([]));
+    let hover = client.request(
+        "textDocument/hover",
+        json!({"textDocument": {"uri": URI}, "position": {"line": 0, "character": 0}}),
+    );
+    assert_eq!(hover["error"]["code"], -32602);
+}
+
+#[test]
+fn test_definition() {
+    let mut client = Client::new();
+    client.open(SOURCE);
+    let definition = |line, character| json!({"uri": URI, "range": range((line, character), (line, character + 1))});
+
+    // `f` in `f(once)` is the param.
+    let f = client.at("textDocument/definition", 2, 2);
+    assert_eq!(f["range"], range((0, 15), (0, 16)));
+    assert_eq!(
+        client.at("textDocument/definition", 2, 4)["range"],
+        range((1, 6), (1, 10))
+    );
+    assert_eq!(
+        client.at("textDocument/definition", 5, 21)["range"],
+        range((4, 4), (4, 7))
+    );
+    assert_eq!(
+        client.at("textDocument/definition", 4, 18),
+        definition(4, 13)
+    );
+    // Definitions lead to themselves; builtins, keywords and type names lead nowhere.
+    assert_eq!(
+        client.at("textDocument/definition", 5, 5)["range"],
+        range((5, 4), (5, 9))
+    );
+    assert_eq!(client.at("textDocument/definition", 0, 21), Value::Null);
+    assert_eq!(client.at("textDocument/definition", 0, 0), Value::Null);
+}
+
+#[test]
+fn test_hover() {
+    let mut client = Client::new();
+    client.open(SOURCE);
+    let hover = |client: &mut Client, line, character| {
+        client.at("textDocument/hover", line, character)["contents"]["value"]
+            .as_str()
+            .map(|value| {
+                value
+                    .trim_start_matches("```monkey\n")
+                    .trim_end_matches("\n```")
+                    .to_string()
+            })
+    };
+    assert_eq!(
+        hover(&mut client, 0, 5).as_deref(),
+        Some("let twice: fn(fn(int) -> int, int) -> int")
+    );
+    assert_eq!(hover(&mut client, 5, 6).as_deref(), Some("let total: int"));
+    assert_eq!(hover(&mut client, 1, 15).as_deref(), Some("param x: int"));
+    assert_eq!(hover(&mut client, 4, 19).as_deref(), Some("param n: any"));
+    assert_eq!(hover(&mut client, 6, 22).as_deref(), Some("let big: bool"));
+
+    client.open("let l = len([1]);\nlet m = map([1], fn(x) { x });\nnope");
+    assert_eq!(
+        hover(&mut client, 0, 9).as_deref(),
+        Some("builtin len: fn(any) -> int")
+    );
+    assert!(hover(&mut client, 1, 9)
+        .unwrap()
+        .starts_with("prelude map: fn("));
+    assert_eq!(hover(&mut client, 2, 1), None);
+}
+
+#[test]
+fn test_completion() {
+    let mut client = Client::new();
+    client.open(SOURCE);
+    let labels = |items: Value| -> Vec<String> {
+        items
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    // Inside `twice`: its locals and params, then the globals.
+    let inside = client.at("textDocument/completion", 2, 2);
+    let inside_labels = labels(inside.clone());
+    assert_eq!(inside_labels[..5], ["f", "x", "once", "twice", "inc"]);
+    assert!(inside_labels.contains(&"len".to_string()));
+    assert!(inside_labels.contains(&"map".to_string()));
+    assert_eq!(inside[0]["detail"], "fn(int) -> int");
+    let twice = inside
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|item| item["label"] == "twice")
+        .unwrap();
+    assert_eq!(twice["kind"], 3);
+
+    // Outside it, its locals aren't in scope.
+    let outside = labels(client.at("textDocument/completion", 6, 0));
+    assert!(!outside.contains(&"once".to_string()));
+    assert!(outside.contains(&"big".to_string()));
+    assert_eq!(outside.iter().filter(|label| *label == "push").count(), 1);
+}
+
+#[test]
+fn test_document_symbols_and_formatting() {
+    let mut client = Client::new();
+    client.open(SOURCE);
+    let symbols = client.request(
+        "textDocument/documentSymbol",
+        json!({"textDocument": {"uri": URI}}),
+    )["result"]
+        .clone();
+    let names: Vec<(&str, &Value)> = symbols
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| (s["name"].as_str().unwrap(), &s["kind"]))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            ("twice", &json!(12)),
+            ("inc", &json!(12)),
+            ("total", &json!(13)),
+            ("big", &json!(13))
+        ]
+    );
+    assert_eq!(symbols[2]["detail"], "int");
+
+    let formatting =
+        json!({"textDocument": {"uri": URI}, "options": {"tabSize": 4, "insertSpaces": true}});
+    client.open("let   x=1;\nx");
+    let edits = client.request("textDocument/formatting", formatting.clone())["result"].clone();
+    assert_eq!(edits[0]["range"], range((0, 0), (1, 1)));
+    assert_eq!(edits[0]["newText"], "let x = 1;\nx;\n");
+    client.open("let x = 1;\nx;\n");
+    assert_eq!(
+        client.request("textDocument/formatting", formatting.clone())["result"],
+        json!([])
+    );
+    client.open("let x = ;");
+    assert_eq!(
+        client.request("textDocument/formatting", formatting)["result"],
+        Value::Null
+    );
+}
+
+#[test]
+fn test_symbols() {
+    let symbols = Symbols::analyze(
+        "import \"lib/math.monkey\";\nlet f = fn(a) { a + later };\nlet later = \"√\"; later\ntry { f(1) } catch (e) { e }",
+    );
+    let kinds: Vec<(&str, SymbolKind, bool)> = symbols
+        .symbols()
+        .iter()
+        .map(|s| (s.name.as_str(), s.kind, s.top_level()))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            ("math", SymbolKind::Import, true),
+            ("f", SymbolKind::Let, true),
+            ("a", SymbolKind::Param, false),
+            ("later", SymbolKind::Let, true),
+            ("e", SymbolKind::Catch, true),
+        ]
+    );
+    // A use before the definition refers to the first one after it.
+    let (name, symbol) = symbols
+        .at(Position {
+            line: 2,
+            column: 22,
+        })
+        .unwrap();
+    assert_eq!(
+        (name, symbol.unwrap().span.start),
+        ("later", Position { line: 3, column: 5 })
+    );
+    // Columns are in bytes, so the last `later` starts after the three bytes of `√`.
+    let (_, symbol) = symbols
+        .at(Position {
+            line: 3,
+            column: 21,
+        })
+        .unwrap();
+    assert_eq!(symbol.unwrap().span.start.line, 3);
+
+    let symbols = Symbols::analyze("let [a, {\"k\": _, b}, ...c] = v;\nfn([d], e) { a }");
+    let kinds: Vec<(&str, SymbolKind)> = symbols
+        .symbols()
+        .iter()
+        .map(|s| (s.name.as_str(), s.kind))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            ("a", SymbolKind::Let),
+            ("b", SymbolKind::Let),
+            ("c", SymbolKind::Let),
+            ("d", SymbolKind::Param),
+            ("e", SymbolKind::Param),
+        ]
+    );
+
+    let symbols = Symbols::analyze("const [c] = v; const d: int = c;");
+    let names: Vec<(&str, SymbolKind)> = symbols
+        .symbols()
+        .iter()
+        .map(|s| (s.name.as_str(), s.kind))
+        .collect();
+    assert_eq!(names, vec![("c", SymbolKind::Let), ("d", SymbolKind::Let)]);
+
+    let symbols = Symbols::analyze("fn(a, b = [a], ...rest) { rest }");
+    let names: Vec<&str> = symbols.symbols().iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, vec!["a", "b", "rest"]);
+    let (_, symbol) = symbols
+        .at(Position {
+            line: 1,
+            column: 12,
+        })
+        .unwrap();
+    assert_eq!(symbol.unwrap().span.start, Position { line: 1, column: 4 });
+
+    let symbols = Symbols::analyze("fn outer(x) { inner(x) }\nfn inner(y) { y }");
+    let kinds: Vec<(&str, SymbolKind, bool, bool)> = symbols
+        .symbols()
+        .iter()
+        .map(|s| (s.name.as_str(), s.kind, s.top_level(), s.function))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            ("outer", SymbolKind::Let, true, true),
+            ("x", SymbolKind::Param, false, false),
+            ("inner", SymbolKind::Let, true, true),
+            ("y", SymbolKind::Param, false, false),
+        ]
+    );
+    // Declarations are hoisted, so the call refers to the one after it.
+    let (_, symbol) = symbols
+        .at(Position {
+            line: 1,
+            column: 16,
+        })
+        .unwrap();
+    assert_eq!(symbol.unwrap().span.start, Position { line: 2, column: 4 });
+}
+
+#[test]
+fn test_serve() {
+    let mut input = Vec::new();
+    let messages = [
+        json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}),
+        json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {"textDocument": {"uri": URI, "text": "let √ = 1;"}}}),
+        json!({"jsonrpc": "2.0", "id": 2, "method": "shutdown"}),
+        json!({"jsonrpc": "2.0", "id": 3, "method": "textDocument/hover", "params": {}}),
+        json!({"jsonrpc": "2.0", "method": "exit"}),
+        json!({"jsonrpc": "2.0", "id": 4, "method": "shutdown"}),
+    ];
+    for message in &messages {
+        write_message(&mut input, message).unwrap();
+    }
+    let mut output = Vec::new();
+    assert_eq!(serve(input.as_slice(), &mut output).unwrap(), 0);
+
+    let mut output = output.as_slice();
+    let mut replies = Vec::new();
+    while let Some(reply) = read_message(&mut output).unwrap() {
+        replies.push(reply);
+    }
+    assert_eq!(replies.len(), 4, "nothing is read after exit");
+    assert_eq!(replies[0]["result"]["serverInfo"]["name"], "monkey-lsp");
+    assert_eq!(
+        replies[1]["params"]["diagnostics"][0]["code"],
+        "parse-error"
+    );
+    assert_eq!(
+        replies[2],
+        json!({"jsonrpc": "2.0", "id": 2, "result": null})
+    );
+    assert_eq!(replies[3]["error"]["code"], -32600);
+
+    // A malformed message gets a parse error, and the messages after it are still served.
+    let mut input = b"Content-Length: 5\r\n\r\n{oops".to_vec();
+    input.extend_from_slice(b"X-Other: 1\r\n\r\n");
+    write_message(&mut input, &json!({"jsonrpc": "2.0", "id": 1, "method": "shutdown"})).unwrap();
+    write_message(&mut input, &json!({"jsonrpc": "2.0", "method": "exit"})).unwrap();
+    let mut output = Vec::new();
+    assert_eq!(serve(input.as_slice(), &mut output).unwrap(), 0);
+    let mut output = output.as_slice();
+    let mut replies = Vec::new();
+    while let Some(reply) = read_message(&mut output).unwrap() {
+        replies.push(reply);
+    }
+    assert_eq!(replies.len(), 3);
+    assert_eq!(replies[0]["error"]["code"], -32700);
+    assert_eq!(replies[0]["id"], Value::Null);
+    assert_eq!(replies[1]["error"]["code"], -32700);
+    assert_eq!(replies[2], json!({"jsonrpc": "2.0", "id": 1, "result": null}));
+
+    // Exiting without shutting down first is an error.
+    let mut input = Vec::new();
+    write_message(&mut input, &json!({"jsonrpc": "2.0", "method": "exit"})).unwrap();
+    assert_eq!(serve(input.as_slice(), Vec::new()).unwrap(), 1);
+}
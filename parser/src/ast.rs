@@ -2,19 +2,70 @@
 use itertools::Itertools;
 use lexer::token::Token;
 
+pub mod fold;
+pub mod visit;
+pub mod visit_mut;
+
+pub use fold::Fold;
+pub use visit::Visitor;
+pub use visit_mut::VisitorMut;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Statement {
-    Let(Identifier, Expression),
+    /// `let name = value;` or `let name: type = value;`.
+    Let(Identifier, Option<Type>, Expression),
     Return(Expression),
     ExpressionStatement(Expression),
+    /// `import "path"` or `import "path" as name`.
+    Import(String, Option<Identifier>),
+    /// `export let name = value;`, only meaningful at the top level of a module.
+    Export(Box<Statement>),
+    /// A `let` inside a function, which the resolver gave slot `.1` of the function's frame.
+    LetLocal(Identifier, usize, Expression),
+    /// `throw value;`.
+    Throw(Expression),
+    /// `let [a, b] = value;` or `let {a, b} = value;`, binding the names in the pattern.
+    LetPattern(Pattern, Expression),
+    /// `fn name(params) { body }`: the `let` (or `LetLocal`) binding `name` to the function,
+    /// which is run when the block it's in starts, so code before it can call it.
+    FnDeclaration(Box<Statement>),
+    /// `const name = value;` (or `const pattern = value;`): the `let`, `LetLocal` or
+    /// `LetPattern` binding names that can't be bound again in the same scope.
+    Const(Box<Statement>),
 }
 
+
 impl std::fmt::Display for Statement {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Statement::Let(i, e) => write!(f, "let {} = {};", i, e),
+            Statement::Let(i, None, e) => write!(f, "let {} = {};", i, e),
+            Statement::Let(i, Some(t), e) => write!(f, "let {}: {} = {};", i, t, e),
             Statement::Return(e) => write!(f, "{} {};", Token::Return, e),
             Statement::ExpressionStatement(e) => write!(f, "{}", e),
+            Statement::Import(path, None) => write!(f, "{} \"{}\";", Token::Import, path),
+            Statement::Import(path, Some(alias)) => {
+                write!(f, "{} \"{}\" {} {};", Token::Import, path, Token::As, alias)
+            }
+            Statement::Export(s) => write!(f, "{} {}", Token::Export, s),
+            Statement::LetLocal(i, _, e) => write!(f, "let {} = {};", i, e),
+            Statement::Throw(e) => write!(f, "{} {};", Token::Throw, e),
+            Statement::LetPattern(p, e) => write!(f, "let {} = {};", p, e),
+            Statement::FnDeclaration(s) => match s.as_ref() {
+                Statement::Let(name, _, Expression::FnExpression(params, body, _))
+                | Statement::LetLocal(name, _, Expression::FnExpression(params, body, _)) => {
+                    let body = body.statements.iter().format("");
+                    write!(f, "{} {name}({}) {body}", Token::Function, params.iter().format(", "))
+                }
+                s => write!(f, "{s}"),
+            },
+            Statement::Const(s) => match s.as_ref() {
+                Statement::Let(i, None, e) | Statement::LetLocal(i, _, e) => {
+                    write!(f, "{} {} = {};", Token::Const, i, e)
+                }
+                Statement::Let(i, Some(t), e) => write!(f, "{} {}: {} = {};", Token::Const, i, t, e),
+                Statement::LetPattern(p, e) => write!(f, "{} {} = {};", Token::Const, p, e),
+                s => write!(f, "{s}"),
+            },
         }
     }
 }
@@ -28,11 +79,29 @@ pub enum Expression {
     PrefixExpression(Token, Box<Expression>),
     InfixExpression(Token, Box<Expression>, Box<Expression>),
     IfExpression(Box<Expression>, BlockStatement, Option<BlockStatement>),
-    FnExpression(Vec<Identifier>, BlockStatement),
+    /// `fn(params) { body }`, with the params' and return type's annotations if it has any.
+    FnExpression(Vec<Param>, BlockStatement, Option<Signature>),
     CallExpression(Box<Expression>, Vec<Expression>),
     Arrary(Vec<Expression>),
     Map(Vec<(Expression,Expression)>),
     IndexExpression(Box<Expression>, Box<Expression>),
+    /// A function param or local, which the resolver found in slot `.2` of the frame `.1`
+    /// function scopes out from where it's used.
+    Local(Identifier, usize, usize),
+    /// `try { body } catch (name) { handler } finally { cleanup }`, with at least one of the
+    /// `catch` and the `finally`. The catch name is a `Pattern` so that the resolver can give it
+    /// a slot of its own, seen only by the handler.
+    TryExpression(BlockStatement, Option<(Pattern, BlockStatement)>, Option<BlockStatement>),
+    /// `value?`: the value inside an `ok`, or an early return of the `err` from the enclosing
+    /// function.
+    Propagate(Box<Expression>),
+    /// `match value { pattern => result, ... }`: the result of the first arm that matches.
+    Match(Box<Expression>, Vec<MatchArm>),
+    /// `...value` among a call's arguments: the elements of the array `value` as arguments of
+    /// their own.
+    Spread(Box<Expression>),
+    /// `name = value` among a call's arguments: `value` passed to the param called `name`.
+    KeywordArg(Identifier, Box<Expression>),
 }
 // impl Eq for Expression{
 //
@@ -90,7 +159,7 @@ impl From<Vec<(Expression,Expression)>> for Expression {
 impl std::fmt::Display for Expression {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Expression::Identifier(i) => write!(f, "{}", i),
+            Expression::Identifier(i) | Expression::Local(i, _, _) => write!(f, "{}", i),
             Expression::IntLiteral(i) => write!(f, "{}", i),
             Expression::Bool(b) => write!(f, "{}", b),
             Expression::PrefixExpression(op, e) => write!(f, "({}{})", op, e),
@@ -101,25 +170,281 @@ impl std::fmt::Display for Expression {
                 Some(e) => write!(f, "if {} {} else {}", cond, if_block, e),
                 None => write!(f, "if {} {}", cond, if_block),
             },
-            Expression::FnExpression(idents, blk) => write!(f, "fn({}) {}", idents.join(" ,"), blk),
+            Expression::FnExpression(params, blk, _) => {
+                let body = blk.statements.iter().format("");
+                write!(f, "fn({}) {}", params.iter().format(" ,"), body)
+            }
             Expression::CallExpression(func, params) => {
                 write!(f, "{}({})", func, params.iter().format(", "))
             }
             Expression::StringLiteral(s) => write!(f, "{s}"),
             Expression::Arrary(values) => write!(f, "[{}]", values.iter().format(", ")),
             Expression::IndexExpression(left, index) => write!(f, "{left}[{index}]"),
-            Expression::Map(map) => write!(f, "{:?}", map)
+            Expression::Map(map) => write!(f, "{:?}", map),
+            Expression::TryExpression(body, catch, finally) => {
+                write!(f, "{} {}", Token::Try, body)?;
+                if let Some((name, handler)) = catch {
+                    write!(f, " {} ({}) {}", Token::Catch, name, handler)?;
+                }
+                if let Some(finally) = finally {
+                    write!(f, " {} {}", Token::Finally, finally)?;
+                }
+                Ok(())
+            }
+            Expression::Propagate(value) => write!(f, "{}{}", value, Token::Question),
+            Expression::Match(value, arms) => {
+                write!(f, "{} {} {{ {} }}", Token::Match, value, arms.iter().format(", "))
+            }
+            Expression::Spread(value) => write!(f, "{}{}", Token::Ellipsis, value),
+            Expression::KeywordArg(name, value) => write!(f, "{} {} {}", name, Token::Assign, value),
         }
     }
 }
 
 pub type Identifier = String;
 
-pub type Program = BlockStatement;
+/// A type annotation, or a type the checker inferred.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Type {
+    Int,
+    String,
+    Bool,
+    Null,
+    Array,
+    Hash,
+    /// `fn(params) -> ret`.
+    Fn(Vec<Type>, Box<Type>),
+    /// Any value at all: what unannotated code gets when nothing more precise is known.
+    Any,
+}
+
+impl Type {
+    /// The type an annotation names, for the types written as a single word.
+    pub fn from_name(name: &str) -> Option<Type> {
+        Some(match name {
+            "int" => Type::Int,
+            "string" => Type::String,
+            "bool" => Type::Bool,
+            "null" => Type::Null,
+            "array" => Type::Array,
+            "hash" => Type::Hash,
+            "any" => Type::Any,
+            _ => return None,
+        })
+    }
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::String => write!(f, "string"),
+            Type::Bool => write!(f, "bool"),
+            Type::Null => write!(f, "null"),
+            Type::Array => write!(f, "array"),
+            Type::Hash => write!(f, "hash"),
+            Type::Fn(params, ret) => write!(f, "fn({}) -> {}", params.iter().format(", "), ret),
+            Type::Any => write!(f, "any"),
+        }
+    }
+}
+
+/// The annotations on a function literal: one per param, `None` where a param has none, and
+/// the return type.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Signature {
+    pub params: Vec<Option<Type>>,
+    pub ret: Option<Type>,
+}
 
+/// A param of a function literal: `name`, `name = default`, or `...name`, which can only come
+/// last and gathers the arguments after the other params into an array.
 #[derive(Debug, PartialEq, Clone)]
+pub struct Param {
+    /// Empty for a param destructured by `pattern`, as only the pattern's names are bound.
+    pub name: Identifier,
+    /// `[x, y]` or `{name}` in place of the name: the pattern the argument is destructured by.
+    pub pattern: Option<Pattern>,
+    /// The value of the param when a call leaves it out, evaluated in the call's frame after
+    /// the params before it.
+    pub default: Option<Expression>,
+    pub rest: bool,
+}
+
+impl Param {
+    /// The number of arguments every call to a function with `params` passes, if it's fixed:
+    /// no param has a default or gathers the rest.
+    pub fn fixed_arity(params: &[Param]) -> Option<usize> {
+        params
+            .iter()
+            .all(|param| param.default.is_none() && !param.rest)
+            .then_some(params.len())
+    }
+
+    /// The names the param binds: its own, or those of the pattern destructuring it.
+    pub fn names(&self) -> Vec<&Identifier> {
+        match &self.pattern {
+            Some(pattern) => pattern.names(),
+            None => vec![&self.name],
+        }
+    }
+}
+
+impl From<&str> for Param {
+    fn from(name: &str) -> Self {
+        Param {
+            name: name.into(),
+            pattern: None,
+            default: None,
+            rest: false,
+        }
+    }
+}
+
+impl std::fmt::Display for Param {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.rest {
+            write!(f, "{}", Token::Ellipsis)?;
+        }
+        match &self.pattern {
+            Some(pattern) => write!(f, "{pattern}")?,
+            None => write!(f, "{}", self.name)?,
+        }
+        if let Some(default) = &self.default {
+            write!(f, " {} {}", Token::Assign, default)?;
+        }
+        Ok(())
+    }
+}
+
+/// One arm of a `match`: `pattern => value`, or `pattern if guard => value`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expression>,
+    pub value: Expression,
+}
+
+impl std::fmt::Display for MatchArm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.pattern)?;
+        if let Some(guard) = &self.guard {
+            write!(f, " {} {}", Token::If, guard)?;
+        }
+        write!(f, " {} {}", Token::FatArrow, self.value)
+    }
+}
+
+/// The shape a `match` arm or a destructuring `let` checks its value against.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+    /// `_`, matching anything.
+    Wildcard,
+    /// `1`, `-1`, `"a"` or `true`, matching an equal value.
+    Literal(Literal),
+    /// A name, matching anything and binding it.
+    Binding(Identifier),
+    /// A name inside a function, which the resolver gave slot `.1` of the function's frame.
+    Local(Identifier, usize),
+    /// `[a, b]`, matching an array of exactly that many elements. With a `..` (or `...`) after
+    /// them it matches any array at least that long, and the remaining elements are matched
+    /// against the pattern after `..`: a name in `..rest`, `_` in a bare `..`.
+    Array(Vec<Pattern>, Option<Box<Pattern>>),
+    /// `{"key": pattern}`, matching a hash with every key whose values match. Other keys are
+    /// ignored. `{name}` is short for `{"name": name}`.
+    Hash(Vec<(Literal, Pattern)>),
+}
+
+impl Pattern {
+    /// The names the pattern binds, in order.
+    pub fn names(&self) -> Vec<&Identifier> {
+        match self {
+            Pattern::Wildcard | Pattern::Literal(_) => Vec::new(),
+            Pattern::Binding(name) | Pattern::Local(name, _) => vec![name],
+            Pattern::Array(elements, rest) => {
+                elements.iter().chain(rest.as_deref()).flat_map(Pattern::names).collect()
+            }
+            Pattern::Hash(pairs) => pairs.iter().flat_map(|(_, value)| value.names()).collect(),
+        }
+    }
+
+    /// Whether the pattern matches every value.
+    pub fn is_irrefutable(&self) -> bool {
+        matches!(
+            self,
+            Pattern::Wildcard | Pattern::Binding(_) | Pattern::Local(..)
+        )
+    }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Literal(literal) => write!(f, "{literal}"),
+            Pattern::Binding(name) | Pattern::Local(name, _) => write!(f, "{name}"),
+            Pattern::Array(elements, rest) => {
+                let rest = rest.as_deref().map(|rest| match rest {
+                    Pattern::Wildcard => Token::DotDot.to_string(),
+                    rest => format!("{}{rest}", Token::DotDot),
+                });
+                write!(f, "[{}]", elements.iter().map(Pattern::to_string).chain(rest).format(", "))
+            }
+            Pattern::Hash(pairs) => {
+                let pairs = pairs.iter().map(|(key, value)| match (key, value) {
+                    (Literal::String(key), Pattern::Binding(name) | Pattern::Local(name, _))
+                        if key == name =>
+                    {
+                        name.clone()
+                    }
+                    (key, value) => format!("{key}: {value}"),
+                });
+                write!(f, "{{{}}}", pairs.format(", "))
+            }
+        }
+    }
+}
+
+/// A value written out in a pattern.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Literal {
+    Int(i64),
+    String(String),
+    Bool(bool),
+}
+
+impl std::fmt::Display for Literal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Literal::Int(i) => write!(f, "{i}"),
+            Literal::String(s) => write!(f, "\"{s}\""),
+            Literal::Bool(b) => write!(f, "{b}"),
+        }
+    }
+}
+
+pub type Program = BlockStatement;
+
+#[derive(Debug, Clone)]
 pub struct BlockStatement {
     pub statements: Vec<Statement>,
+    /// Line each statement starts on, counting from 1. Blocks built by hand rather than parsed
+    /// may leave it empty.
+    pub lines: Vec<usize>,
+}
+
+impl BlockStatement {
+    /// Line the `i`-th statement starts on, or 0 if it isn't known.
+    pub fn line(&self, i: usize) -> usize {
+        self.lines.get(i).copied().unwrap_or(0)
+    }
+}
+
+/// Lines are left out, so the same program laid out differently compares equal.
+impl PartialEq for BlockStatement {
+    fn eq(&self, other: &Self) -> bool {
+        self.statements == other.statements
+    }
 }
 
 impl std::fmt::Display for BlockStatement {
@@ -153,7 +478,7 @@ impl Precedence {
             Token::Plus | Token::Dash => Precedence::SUM,
             Token::Asterisk | Token::ForwardSlash => Precedence::PRODUCT,
             Token::LParen => Precedence::CALL,
-            Token::LBracket => Precedence::INDEX,
+            Token::LBracket | Token::Question => Precedence::INDEX,
             _ => Precedence::LOWEST,
         }
     }
@@ -0,0 +1,249 @@
+//! Rebuilding traversal of the AST: each method takes a node by value and returns its
+//! replacement, which may be a different kind of node (an infix expression folded into a
+//! literal, say). The defaults rebuild the node from its folded children.
+
+use lexer::token::Token;
+
+use crate::ast::{
+    BlockStatement, Expression, Identifier, MatchArm, Param, Pattern, Program, Signature,
+    Statement, Type,
+};
+
+pub trait Fold {
+    fn fold_program(&mut self, program: Program) -> Program {
+        walk_block(self, program)
+    }
+
+    fn fold_block(&mut self, block: BlockStatement) -> BlockStatement {
+        walk_block(self, block)
+    }
+
+    fn fold_statement(&mut self, statement: Statement) -> Statement {
+        walk_statement(self, statement)
+    }
+
+    fn fold_let(&mut self, name: Identifier, ty: Option<Type>, value: Expression) -> Statement {
+        Statement::Let(name, ty, self.fold_expression(value))
+    }
+
+    fn fold_return(&mut self, value: Expression) -> Statement {
+        Statement::Return(self.fold_expression(value))
+    }
+
+    fn fold_expression_statement(&mut self, expression: Expression) -> Statement {
+        Statement::ExpressionStatement(self.fold_expression(expression))
+    }
+
+    fn fold_import(&mut self, path: String, alias: Option<Identifier>) -> Statement {
+        Statement::Import(path, alias)
+    }
+
+    fn fold_export(&mut self, statement: Statement) -> Statement {
+        Statement::Export(Box::new(self.fold_statement(statement)))
+    }
+
+    fn fold_let_local(&mut self, name: Identifier, slot: usize, value: Expression) -> Statement {
+        Statement::LetLocal(name, slot, self.fold_expression(value))
+    }
+
+    fn fold_throw(&mut self, value: Expression) -> Statement {
+        Statement::Throw(self.fold_expression(value))
+    }
+
+    fn fold_fn_declaration(&mut self, statement: Statement) -> Statement {
+        Statement::FnDeclaration(Box::new(self.fold_statement(statement)))
+    }
+
+    fn fold_const(&mut self, statement: Statement) -> Statement {
+        Statement::Const(Box::new(self.fold_statement(statement)))
+    }
+
+    fn fold_let_pattern(&mut self, pattern: Pattern, value: Expression) -> Statement {
+        Statement::LetPattern(pattern, self.fold_expression(value))
+    }
+
+    fn fold_expression(&mut self, expression: Expression) -> Expression {
+        walk_expression(self, expression)
+    }
+
+    fn fold_identifier(&mut self, name: Identifier) -> Expression {
+        Expression::Identifier(name)
+    }
+
+    fn fold_local(&mut self, name: Identifier, depth: usize, slot: usize) -> Expression {
+        Expression::Local(name, depth, slot)
+    }
+
+    fn fold_int_literal(&mut self, value: i64) -> Expression {
+        Expression::IntLiteral(value)
+    }
+
+    fn fold_string_literal(&mut self, value: String) -> Expression {
+        Expression::StringLiteral(value)
+    }
+
+    fn fold_bool(&mut self, value: bool) -> Expression {
+        Expression::Bool(value)
+    }
+
+    fn fold_prefix(&mut self, op: Token, right: Expression) -> Expression {
+        Expression::PrefixExpression(op, Box::new(self.fold_expression(right)))
+    }
+
+    fn fold_infix(&mut self, op: Token, left: Expression, right: Expression) -> Expression {
+        let left = self.fold_expression(left);
+        let right = self.fold_expression(right);
+        Expression::InfixExpression(op, Box::new(left), Box::new(right))
+    }
+
+    fn fold_if(
+        &mut self,
+        cond: Expression,
+        if_block: BlockStatement,
+        else_block: Option<BlockStatement>,
+    ) -> Expression {
+        let cond = self.fold_expression(cond);
+        let if_block = self.fold_block(if_block);
+        let else_block = else_block.map(|block| self.fold_block(block));
+        Expression::IfExpression(Box::new(cond), if_block, else_block)
+    }
+
+    fn fold_fn(
+        &mut self,
+        params: Vec<Param>,
+        body: BlockStatement,
+        signature: Option<Signature>,
+    ) -> Expression {
+        let params = params
+            .into_iter()
+            .map(|param| Param {
+                default: param.default.map(|default| self.fold_expression(default)),
+                ..param
+            })
+            .collect();
+        Expression::FnExpression(params, self.fold_block(body), signature)
+    }
+
+    fn fold_call(&mut self, function: Expression, args: Vec<Expression>) -> Expression {
+        let function = self.fold_expression(function);
+        let args = args
+            .into_iter()
+            .map(|arg| self.fold_expression(arg))
+            .collect();
+        Expression::CallExpression(Box::new(function), args)
+    }
+
+    fn fold_array(&mut self, values: Vec<Expression>) -> Expression {
+        Expression::Arrary(
+            values
+                .into_iter()
+                .map(|value| self.fold_expression(value))
+                .collect(),
+        )
+    }
+
+    fn fold_map(&mut self, pairs: Vec<(Expression, Expression)>) -> Expression {
+        Expression::Map(
+            pairs
+                .into_iter()
+                .map(|(key, value)| (self.fold_expression(key), self.fold_expression(value)))
+                .collect(),
+        )
+    }
+
+    fn fold_index(&mut self, left: Expression, index: Expression) -> Expression {
+        let left = self.fold_expression(left);
+        let index = self.fold_expression(index);
+        Expression::IndexExpression(Box::new(left), Box::new(index))
+    }
+
+    fn fold_propagate(&mut self, value: Expression) -> Expression {
+        Expression::Propagate(Box::new(self.fold_expression(value)))
+    }
+
+    fn fold_spread(&mut self, value: Expression) -> Expression {
+        Expression::Spread(Box::new(self.fold_expression(value)))
+    }
+
+    fn fold_keyword_arg(&mut self, name: Identifier, value: Expression) -> Expression {
+        Expression::KeywordArg(name, Box::new(self.fold_expression(value)))
+    }
+
+    fn fold_match(&mut self, value: Expression, arms: Vec<MatchArm>) -> Expression {
+        let value = self.fold_expression(value);
+        let arms = arms
+            .into_iter()
+            .map(|arm| MatchArm {
+                pattern: arm.pattern,
+                guard: arm.guard.map(|guard| self.fold_expression(guard)),
+                value: self.fold_expression(arm.value),
+            })
+            .collect();
+        Expression::Match(Box::new(value), arms)
+    }
+
+    fn fold_try(
+        &mut self,
+        body: BlockStatement,
+        catch: Option<(Pattern, BlockStatement)>,
+        finally: Option<BlockStatement>,
+    ) -> Expression {
+        let body = self.fold_block(body);
+        let catch = catch.map(|(name, handler)| (name, self.fold_block(handler)));
+        let finally = finally.map(|block| self.fold_block(block));
+        Expression::TryExpression(body, catch, finally)
+    }
+}
+
+pub fn walk_block<F: Fold + ?Sized>(folder: &mut F, block: BlockStatement) -> BlockStatement {
+    BlockStatement {
+        statements: block
+            .statements
+            .into_iter()
+            .map(|statement| folder.fold_statement(statement))
+            .collect(),
+        lines: block.lines,
+    }
+}
+
+pub fn walk_statement<F: Fold + ?Sized>(folder: &mut F, statement: Statement) -> Statement {
+    match statement {
+        Statement::Let(name, ty, value) => folder.fold_let(name, ty, value),
+        Statement::Return(value) => folder.fold_return(value),
+        Statement::ExpressionStatement(e) => folder.fold_expression_statement(e),
+        Statement::Import(path, alias) => folder.fold_import(path, alias),
+        Statement::Export(exported) => folder.fold_export(*exported),
+        Statement::LetLocal(name, slot, value) => folder.fold_let_local(name, slot, value),
+        Statement::Throw(value) => folder.fold_throw(value),
+        Statement::LetPattern(pattern, value) => folder.fold_let_pattern(pattern, value),
+        Statement::FnDeclaration(declared) => folder.fold_fn_declaration(*declared),
+        Statement::Const(declared) => folder.fold_const(*declared),
+    }
+}
+
+pub fn walk_expression<F: Fold + ?Sized>(folder: &mut F, expression: Expression) -> Expression {
+    match expression {
+        Expression::Identifier(name) => folder.fold_identifier(name),
+        Expression::IntLiteral(i) => folder.fold_int_literal(i),
+        Expression::StringLiteral(s) => folder.fold_string_literal(s),
+        Expression::Bool(b) => folder.fold_bool(b),
+        Expression::PrefixExpression(op, right) => folder.fold_prefix(op, *right),
+        Expression::InfixExpression(op, left, right) => folder.fold_infix(op, *left, *right),
+        Expression::IfExpression(cond, if_block, else_block) => {
+            folder.fold_if(*cond, if_block, else_block)
+        }
+        Expression::FnExpression(params, body, signature) => {
+            folder.fold_fn(params, body, signature)
+        }
+        Expression::CallExpression(function, args) => folder.fold_call(*function, args),
+        Expression::Arrary(values) => folder.fold_array(values),
+        Expression::Map(pairs) => folder.fold_map(pairs),
+        Expression::IndexExpression(left, index) => folder.fold_index(*left, *index),
+        Expression::Local(name, depth, slot) => folder.fold_local(name, depth, slot),
+        Expression::TryExpression(body, catch, finally) => folder.fold_try(body, catch, finally),
+        Expression::Propagate(value) => folder.fold_propagate(*value),
+        Expression::Match(value, arms) => folder.fold_match(*value, arms),
+        Expression::Spread(value) => folder.fold_spread(*value),
+        Expression::KeywordArg(name, value) => folder.fold_keyword_arg(name, *value),
+    }
+}
@@ -0,0 +1,224 @@
+//! Read-only traversal of the AST. Implement `Visitor` and override the methods for the nodes
+//! you care about; the defaults visit every child. An override that still wants the children
+//! visited calls the matching `walk_*` function (or visits them itself).
+
+use lexer::token::Token;
+
+use crate::ast::{
+    BlockStatement, Expression, Identifier, MatchArm, Param, Pattern, Program, Signature,
+    Statement, Type,
+};
+
+pub trait Visitor {
+    fn visit_program(&mut self, program: &Program) {
+        walk_block(self, program);
+    }
+
+    fn visit_block(&mut self, block: &BlockStatement) {
+        walk_block(self, block);
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) {
+        walk_statement(self, statement);
+    }
+
+    fn visit_let(&mut self, _name: &Identifier, _ty: Option<&Type>, value: &Expression) {
+        self.visit_expression(value);
+    }
+
+    fn visit_return(&mut self, value: &Expression) {
+        self.visit_expression(value);
+    }
+
+    fn visit_expression_statement(&mut self, expression: &Expression) {
+        self.visit_expression(expression);
+    }
+
+    fn visit_import(&mut self, _path: &str, _alias: Option<&Identifier>) {}
+
+    fn visit_let_local(&mut self, _name: &Identifier, _slot: usize, value: &Expression) {
+        self.visit_expression(value);
+    }
+
+    fn visit_export(&mut self, statement: &Statement) {
+        self.visit_statement(statement);
+    }
+
+    fn visit_fn_declaration(&mut self, statement: &Statement) {
+        self.visit_statement(statement);
+    }
+
+    fn visit_const(&mut self, statement: &Statement) {
+        self.visit_statement(statement);
+    }
+
+    fn visit_throw(&mut self, value: &Expression) {
+        self.visit_expression(value);
+    }
+
+    fn visit_let_pattern(&mut self, _pattern: &Pattern, value: &Expression) {
+        self.visit_expression(value);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        walk_expression(self, expression);
+    }
+
+    fn visit_identifier(&mut self, _name: &Identifier) {}
+
+    fn visit_local(&mut self, _name: &Identifier, _depth: usize, _slot: usize) {}
+
+    fn visit_int_literal(&mut self, _value: i64) {}
+
+    fn visit_string_literal(&mut self, _value: &str) {}
+
+    fn visit_bool(&mut self, _value: bool) {}
+
+    fn visit_prefix(&mut self, _op: &Token, right: &Expression) {
+        self.visit_expression(right);
+    }
+
+    fn visit_infix(&mut self, _op: &Token, left: &Expression, right: &Expression) {
+        self.visit_expression(left);
+        self.visit_expression(right);
+    }
+
+    fn visit_if(
+        &mut self,
+        cond: &Expression,
+        if_block: &BlockStatement,
+        else_block: Option<&BlockStatement>,
+    ) {
+        self.visit_expression(cond);
+        self.visit_block(if_block);
+        if let Some(else_block) = else_block {
+            self.visit_block(else_block);
+        }
+    }
+
+    fn visit_fn(
+        &mut self,
+        params: &[Param],
+        body: &BlockStatement,
+        _signature: Option<&Signature>,
+    ) {
+        for default in params.iter().filter_map(|param| param.default.as_ref()) {
+            self.visit_expression(default);
+        }
+        self.visit_block(body);
+    }
+
+    fn visit_call(&mut self, function: &Expression, args: &[Expression]) {
+        self.visit_expression(function);
+        for arg in args {
+            self.visit_expression(arg);
+        }
+    }
+
+    fn visit_array(&mut self, values: &[Expression]) {
+        for value in values {
+            self.visit_expression(value);
+        }
+    }
+
+    fn visit_map(&mut self, pairs: &[(Expression, Expression)]) {
+        for (key, value) in pairs {
+            self.visit_expression(key);
+            self.visit_expression(value);
+        }
+    }
+
+    fn visit_index(&mut self, left: &Expression, index: &Expression) {
+        self.visit_expression(left);
+        self.visit_expression(index);
+    }
+
+    fn visit_propagate(&mut self, value: &Expression) {
+        self.visit_expression(value);
+    }
+
+    fn visit_spread(&mut self, value: &Expression) {
+        self.visit_expression(value);
+    }
+
+    fn visit_keyword_arg(&mut self, _name: &Identifier, value: &Expression) {
+        self.visit_expression(value);
+    }
+
+    fn visit_match(&mut self, value: &Expression, arms: &[MatchArm]) {
+        self.visit_expression(value);
+        for arm in arms {
+            if let Some(guard) = &arm.guard {
+                self.visit_expression(guard);
+            }
+            self.visit_expression(&arm.value);
+        }
+    }
+
+    fn visit_try(
+        &mut self,
+        body: &BlockStatement,
+        catch: Option<(&Pattern, &BlockStatement)>,
+        finally: Option<&BlockStatement>,
+    ) {
+        self.visit_block(body);
+        if let Some((_, handler)) = catch {
+            self.visit_block(handler);
+        }
+        if let Some(finally) = finally {
+            self.visit_block(finally);
+        }
+    }
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, block: &BlockStatement) {
+    for statement in &block.statements {
+        visitor.visit_statement(statement);
+    }
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &Statement) {
+    match statement {
+        Statement::Let(name, ty, value) => visitor.visit_let(name, ty.as_ref(), value),
+        Statement::Return(value) => visitor.visit_return(value),
+        Statement::ExpressionStatement(e) => visitor.visit_expression_statement(e),
+        Statement::Import(path, alias) => visitor.visit_import(path, alias.as_ref()),
+        Statement::Export(exported) => visitor.visit_export(exported),
+        Statement::LetLocal(name, slot, value) => visitor.visit_let_local(name, *slot, value),
+        Statement::Throw(value) => visitor.visit_throw(value),
+        Statement::LetPattern(pattern, value) => visitor.visit_let_pattern(pattern, value),
+        Statement::FnDeclaration(declared) => visitor.visit_fn_declaration(declared),
+        Statement::Const(declared) => visitor.visit_const(declared),
+    }
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &Expression) {
+    match expression {
+        Expression::Identifier(name) => visitor.visit_identifier(name),
+        Expression::IntLiteral(i) => visitor.visit_int_literal(*i),
+        Expression::StringLiteral(s) => visitor.visit_string_literal(s),
+        Expression::Bool(b) => visitor.visit_bool(*b),
+        Expression::PrefixExpression(op, right) => visitor.visit_prefix(op, right),
+        Expression::InfixExpression(op, left, right) => visitor.visit_infix(op, left, right),
+        Expression::IfExpression(cond, if_block, else_block) => {
+            visitor.visit_if(cond, if_block, else_block.as_ref())
+        }
+        Expression::FnExpression(params, body, signature) => {
+            visitor.visit_fn(params, body, signature.as_ref())
+        }
+        Expression::CallExpression(function, args) => visitor.visit_call(function, args),
+        Expression::Arrary(values) => visitor.visit_array(values),
+        Expression::Map(pairs) => visitor.visit_map(pairs),
+        Expression::IndexExpression(left, index) => visitor.visit_index(left, index),
+        Expression::Local(name, depth, slot) => visitor.visit_local(name, *depth, *slot),
+        Expression::TryExpression(body, catch, finally) => visitor.visit_try(
+            body,
+            catch.as_ref().map(|(name, handler)| (name, handler)),
+            finally.as_ref(),
+        ),
+        Expression::Propagate(value) => visitor.visit_propagate(value),
+        Expression::Match(value, arms) => visitor.visit_match(value, arms),
+        Expression::Spread(value) => visitor.visit_spread(value),
+        Expression::KeywordArg(name, value) => visitor.visit_keyword_arg(name, value),
+    }
+}
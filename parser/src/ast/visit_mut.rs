@@ -0,0 +1,231 @@
+//! In-place traversal of the AST, for rewrites that keep the shape of the tree (renaming,
+//! desugaring a node into another of the same kind, ...). Works like `Visitor`.
+
+use lexer::token::Token;
+
+use crate::ast::{
+    BlockStatement, Expression, Identifier, MatchArm, Param, Pattern, Program, Signature,
+    Statement, Type,
+};
+
+pub trait VisitorMut {
+    fn visit_program_mut(&mut self, program: &mut Program) {
+        walk_block_mut(self, program);
+    }
+
+    fn visit_block_mut(&mut self, block: &mut BlockStatement) {
+        walk_block_mut(self, block);
+    }
+
+    fn visit_statement_mut(&mut self, statement: &mut Statement) {
+        walk_statement_mut(self, statement);
+    }
+
+    fn visit_let_mut(
+        &mut self,
+        _name: &mut Identifier,
+        _ty: &mut Option<Type>,
+        value: &mut Expression,
+    ) {
+        self.visit_expression_mut(value);
+    }
+
+    fn visit_return_mut(&mut self, value: &mut Expression) {
+        self.visit_expression_mut(value);
+    }
+
+    fn visit_expression_statement_mut(&mut self, expression: &mut Expression) {
+        self.visit_expression_mut(expression);
+    }
+
+    fn visit_import_mut(&mut self, _path: &mut String, _alias: &mut Option<Identifier>) {}
+
+    fn visit_let_local_mut(
+        &mut self,
+        _name: &mut Identifier,
+        _slot: &mut usize,
+        value: &mut Expression,
+    ) {
+        self.visit_expression_mut(value);
+    }
+
+    fn visit_export_mut(&mut self, statement: &mut Statement) {
+        self.visit_statement_mut(statement);
+    }
+
+    fn visit_throw_mut(&mut self, value: &mut Expression) {
+        self.visit_expression_mut(value);
+    }
+
+    fn visit_fn_declaration_mut(&mut self, statement: &mut Statement) {
+        self.visit_statement_mut(statement);
+    }
+
+    fn visit_const_mut(&mut self, statement: &mut Statement) {
+        self.visit_statement_mut(statement);
+    }
+
+    fn visit_let_pattern_mut(&mut self, _pattern: &mut Pattern, value: &mut Expression) {
+        self.visit_expression_mut(value);
+    }
+
+    fn visit_expression_mut(&mut self, expression: &mut Expression) {
+        walk_expression_mut(self, expression);
+    }
+
+    fn visit_identifier_mut(&mut self, _name: &mut Identifier) {}
+
+    fn visit_local_mut(&mut self, _name: &mut Identifier, _depth: &mut usize, _slot: &mut usize) {}
+
+    fn visit_int_literal_mut(&mut self, _value: &mut i64) {}
+
+    fn visit_string_literal_mut(&mut self, _value: &mut String) {}
+
+    fn visit_bool_mut(&mut self, _value: &mut bool) {}
+
+    fn visit_prefix_mut(&mut self, _op: &mut Token, right: &mut Expression) {
+        self.visit_expression_mut(right);
+    }
+
+    fn visit_infix_mut(&mut self, _op: &mut Token, left: &mut Expression, right: &mut Expression) {
+        self.visit_expression_mut(left);
+        self.visit_expression_mut(right);
+    }
+
+    fn visit_if_mut(
+        &mut self,
+        cond: &mut Expression,
+        if_block: &mut BlockStatement,
+        else_block: &mut Option<BlockStatement>,
+    ) {
+        self.visit_expression_mut(cond);
+        self.visit_block_mut(if_block);
+        if let Some(else_block) = else_block {
+            self.visit_block_mut(else_block);
+        }
+    }
+
+    fn visit_fn_mut(
+        &mut self,
+        params: &mut Vec<Param>,
+        body: &mut BlockStatement,
+        _signature: &mut Option<Signature>,
+    ) {
+        for default in params.iter_mut().filter_map(|param| param.default.as_mut()) {
+            self.visit_expression_mut(default);
+        }
+        self.visit_block_mut(body);
+    }
+
+    fn visit_call_mut(&mut self, function: &mut Expression, args: &mut Vec<Expression>) {
+        self.visit_expression_mut(function);
+        for arg in args {
+            self.visit_expression_mut(arg);
+        }
+    }
+
+    fn visit_array_mut(&mut self, values: &mut Vec<Expression>) {
+        for value in values {
+            self.visit_expression_mut(value);
+        }
+    }
+
+    fn visit_map_mut(&mut self, pairs: &mut Vec<(Expression, Expression)>) {
+        for (key, value) in pairs {
+            self.visit_expression_mut(key);
+            self.visit_expression_mut(value);
+        }
+    }
+
+    fn visit_index_mut(&mut self, left: &mut Expression, index: &mut Expression) {
+        self.visit_expression_mut(left);
+        self.visit_expression_mut(index);
+    }
+
+    fn visit_propagate_mut(&mut self, value: &mut Expression) {
+        self.visit_expression_mut(value);
+    }
+
+    fn visit_spread_mut(&mut self, value: &mut Expression) {
+        self.visit_expression_mut(value);
+    }
+
+    fn visit_keyword_arg_mut(&mut self, _name: &mut Identifier, value: &mut Expression) {
+        self.visit_expression_mut(value);
+    }
+
+    fn visit_match_mut(&mut self, value: &mut Expression, arms: &mut Vec<MatchArm>) {
+        self.visit_expression_mut(value);
+        for arm in arms {
+            if let Some(guard) = &mut arm.guard {
+                self.visit_expression_mut(guard);
+            }
+            self.visit_expression_mut(&mut arm.value);
+        }
+    }
+
+    fn visit_try_mut(
+        &mut self,
+        body: &mut BlockStatement,
+        catch: &mut Option<(Pattern, BlockStatement)>,
+        finally: &mut Option<BlockStatement>,
+    ) {
+        self.visit_block_mut(body);
+        if let Some((_, handler)) = catch {
+            self.visit_block_mut(handler);
+        }
+        if let Some(finally) = finally {
+            self.visit_block_mut(finally);
+        }
+    }
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(visitor: &mut V, block: &mut BlockStatement) {
+    for statement in &mut block.statements {
+        visitor.visit_statement_mut(statement);
+    }
+}
+
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(visitor: &mut V, statement: &mut Statement) {
+    match statement {
+        Statement::Let(name, ty, value) => visitor.visit_let_mut(name, ty, value),
+        Statement::Return(value) => visitor.visit_return_mut(value),
+        Statement::ExpressionStatement(e) => visitor.visit_expression_statement_mut(e),
+        Statement::Import(path, alias) => visitor.visit_import_mut(path, alias),
+        Statement::Export(exported) => visitor.visit_export_mut(exported),
+        Statement::LetLocal(name, slot, value) => visitor.visit_let_local_mut(name, slot, value),
+        Statement::Throw(value) => visitor.visit_throw_mut(value),
+        Statement::LetPattern(pattern, value) => visitor.visit_let_pattern_mut(pattern, value),
+        Statement::FnDeclaration(declared) => visitor.visit_fn_declaration_mut(declared),
+        Statement::Const(declared) => visitor.visit_const_mut(declared),
+    }
+}
+
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expression: &mut Expression) {
+    match expression {
+        Expression::Identifier(name) => visitor.visit_identifier_mut(name),
+        Expression::IntLiteral(i) => visitor.visit_int_literal_mut(i),
+        Expression::StringLiteral(s) => visitor.visit_string_literal_mut(s),
+        Expression::Bool(b) => visitor.visit_bool_mut(b),
+        Expression::PrefixExpression(op, right) => visitor.visit_prefix_mut(op, right),
+        Expression::InfixExpression(op, left, right) => visitor.visit_infix_mut(op, left, right),
+        Expression::IfExpression(cond, if_block, else_block) => {
+            visitor.visit_if_mut(cond, if_block, else_block)
+        }
+        Expression::FnExpression(params, body, signature) => {
+            visitor.visit_fn_mut(params, body, signature)
+        }
+        Expression::CallExpression(function, args) => visitor.visit_call_mut(function, args),
+        Expression::Arrary(values) => visitor.visit_array_mut(values),
+        Expression::Map(pairs) => visitor.visit_map_mut(pairs),
+        Expression::IndexExpression(left, index) => visitor.visit_index_mut(left, index),
+        Expression::Local(name, depth, slot) => visitor.visit_local_mut(name, depth, slot),
+        Expression::TryExpression(body, catch, finally) => {
+            visitor.visit_try_mut(body, catch, finally)
+        }
+        Expression::Propagate(value) => visitor.visit_propagate_mut(value),
+        Expression::Match(value, arms) => visitor.visit_match_mut(value, arms),
+        Expression::Spread(value) => visitor.visit_spread_mut(value),
+        Expression::KeywordArg(name, value) => visitor.visit_keyword_arg_mut(name, value),
+    }
+}
@@ -1,5 +1,6 @@
 pub mod ast;
 pub mod parse_error;
 pub mod parser;
+pub mod printer;
 #[cfg(test)]
 mod tests;
@@ -2,7 +2,7 @@ use std::fmt;
 
 use lexer::token::Token;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum ParserError {
     WrongCurrentToken {
         expected_token: TokenType,
@@ -19,6 +19,12 @@ pub enum ParserError {
     InvalidTokenToExpression(Token),
     UnexpectedStatementStart(Token),
     NoValidPrefix(TokenType),
+    UnknownType(String),
+    InvalidPattern(TokenType),
+    /// A param without a default after one with a default, which no call could leave out.
+    RequiredAfterDefault(String),
+    /// A comment on this line sits inside an expression, where the formatter can't keep it.
+    MisplacedComment(usize),
     ParserError(String),
 }
 
@@ -55,6 +61,16 @@ impl fmt::Display for ParserError {
                 write!(f, "{} is not a valid starting to a statement", token)
             }
             ParserError::NoValidPrefix(token) => write!(f, "{} is not a valid prefix token", token),
+            ParserError::UnknownType(name) => write!(f, "{} is not a type", name),
+            ParserError::InvalidPattern(token) => write!(f, "{} can't start a pattern", token),
+            ParserError::RequiredAfterDefault(name) => {
+                write!(f, "{} needs a default, as a param before it has one", name)
+            }
+            ParserError::MisplacedComment(line) => write!(
+                f,
+                "The comment on line {} is inside an expression and can't be formatted",
+                line
+            ),
             ParserError::ParserError(str) => write!(f, "{}", str),
             ParserError::InvalidTokenToExpression(t) => {
                 write!(f, "{} cannot be converted to an expression", t)
@@ -90,6 +106,7 @@ impl From<&Token> for TokenType {
             Token::RBrace => TokenType::RSquirly,
             Token::Function => TokenType::Function,
             Token::Let => TokenType::Let,
+            Token::Const => TokenType::Const,
             Token::If => TokenType::If,
             Token::Else => TokenType::Else,
             Token::Return => TokenType::Return,
@@ -98,6 +115,19 @@ impl From<&Token> for TokenType {
             Token::LBracket=> TokenType::LBracket,
             Token::RBracket => TokenType::RBracket,
             Token::Colon => TokenType::Colon,
+            Token::Arrow => TokenType::Arrow,
+            Token::Question => TokenType::Question,
+            Token::FatArrow => TokenType::FatArrow,
+            Token::DotDot => TokenType::DotDot,
+            Token::Ellipsis => TokenType::Ellipsis,
+            Token::Match => TokenType::Match,
+            Token::Import => TokenType::Import,
+            Token::As => TokenType::As,
+            Token::Export => TokenType::Export,
+            Token::Throw => TokenType::Throw,
+            Token::Try => TokenType::Try,
+            Token::Catch => TokenType::Catch,
+            Token::Finally => TokenType::Finally,
         }
     }
 }
@@ -157,10 +187,24 @@ pub enum TokenType {
     RBracket,
     Function,
     Let,
+    Const,
     If,
     Else,
     Return,
     Bool,
     String,
     Colon,
+    Arrow,
+    Question,
+    FatArrow,
+    DotDot,
+    Ellipsis,
+    Import,
+    As,
+    Export,
+    Throw,
+    Try,
+    Catch,
+    Finally,
+    Match,
 }
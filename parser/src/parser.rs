@@ -1,14 +1,34 @@
-use crate::ast::{BlockStatement, Expression, Identifier, Precedence, Program, Statement};
+use crate::ast::{
+    BlockStatement, Expression, Literal, MatchArm, Param, Pattern, Precedence, Program,
+    Signature, Statement, Type,
+};
 use crate::parse_error::{ParserError, TokenType};
+use crate::printer::Comments;
 use colored::Colorize;
-use lexer::lexer::Lexer;
+use lexer::lexer::{Comment, Lexer};
 use lexer::token::Token;
 
+/// A param as parsed: the param and its annotation.
+type FnParam = (Param, Option<Type>);
+
 pub struct Parser<'a> {
     lexer: Lexer<'a>,
     cur_token: Token,
     peek_token: Token,
+    cur_comments: Vec<Comment>,
+    peek_comments: Vec<Comment>,
+    cur_line: usize,
+    peek_line: usize,
+    statements_started: usize,
+    blocks_started: usize,
     pub parse_errors: Vec<ParserError>,
+    /// Line each of `parse_errors` was found on.
+    pub error_lines: Vec<usize>,
+    /// Line of the n-th statement the parser started, in the order a pre-order walk of the
+    /// program visits statements.
+    pub statement_lines: Vec<usize>,
+    /// Comments found in the source, for printing the program back out.
+    pub comments: Comments,
 }
 
 impl<'a> Parser<'a> {
@@ -17,7 +37,16 @@ impl<'a> Parser<'a> {
             lexer: lex,
             cur_token: Token::Eof,
             peek_token: Token::Eof,
+            cur_comments: Vec::new(),
+            peek_comments: Vec::new(),
+            cur_line: 1,
+            peek_line: 1,
+            statements_started: 0,
+            blocks_started: 0,
             parse_errors: Vec::new(),
+            error_lines: Vec::new(),
+            statement_lines: Vec::new(),
+            comments: Comments::default(),
         };
 
         parse.next_token();
@@ -27,14 +56,18 @@ impl<'a> Parser<'a> {
 
     pub fn parse_program(&mut self) -> Program {
         let mut statements: Vec<Statement> = Vec::new();
+        let mut lines = Vec::new();
         while self.cur_token != Token::Eof {
+            let line = self.cur_line;
             if let Some(p) = self.parse_statement() {
                 statements.push(p);
+                lines.push(line);
             }
             self.next_token();
         }
+        self.comments.end = std::mem::take(&mut self.cur_comments);
 
-        Program { statements }
+        Program { statements, lines }
     }
     pub fn get_program_input(&self) -> String {
         self.lexer.get_input()
@@ -42,13 +75,37 @@ impl<'a> Parser<'a> {
 
     fn next_token(&mut self) {
         self.cur_token = self.peek_token.clone();
+        // Comments that weren't in front of a statement or a closing brace (say, inside a
+        // multi-line array) move forward to the next place that takes them.
+        if let Some(comment) = self.cur_comments.first() {
+            self.comments.misplaced.get_or_insert(comment.line);
+        }
+        self.cur_comments.append(&mut self.peek_comments);
+        self.cur_line = self.peek_line;
         self.peek_token = self.lexer.next_token();
+        self.peek_comments = self.lexer.take_comments();
+        self.peek_line = self.lexer.token_line();
     }
 
     fn parse_statement(&mut self) -> Option<Statement> {
+        let id = self.statements_started;
+        self.statements_started += 1;
+        self.statement_lines.push(self.cur_line);
+        if !self.cur_comments.is_empty() {
+            let comments = std::mem::take(&mut self.cur_comments);
+            self.comments.statements.insert(id, comments);
+        }
+
         let statement = match &self.cur_token {
             Token::Let => self.parse_let_statement(),
+            Token::Const => self.parse_const_statement(),
             Token::Return => self.parse_return_statement(),
+            Token::Import => self.parse_import_statement(),
+            Token::Export => self.parse_export_statement(),
+            Token::Throw => self.parse_throw_statement(),
+            Token::Function if matches!(self.peek_token, Token::Ident(_)) => {
+                self.parse_fn_declaration()
+            }
             _ => self.parse_expression_statement(),
         };
 
@@ -58,6 +115,7 @@ impl<'a> Parser<'a> {
                 ParserError::UnexpectedStatementStart(_) => None,
                 _ => {
                     self.parse_errors.push(e);
+                    self.error_lines.push(self.cur_line);
                     None
                 }
             },
@@ -65,15 +123,78 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_let_statement(&mut self) -> Result<Statement, ParserError> {
+        if let Token::LBracket | Token::LBrace = &self.peek_token {
+            return self.parse_let_pattern_statement();
+        }
         let ident: String = self.expect_peek(TokenType::Identifier)?;
-        self.expect_peek(TokenType::Assign)?;
+        let ty = self.parse_annotation()?;
+        self.expect_peek::<()>(TokenType::Assign)?;
+        self.next_token();
+
+        let exp = self.parse_expression(Precedence::LOWEST)?;
+        if let Token::Semicolon = &self.peek_token {
+            self.next_token();
+        }
+        Ok(Statement::Let(ident, ty, exp))
+    }
+
+    /// Parses `const` followed by what could follow `let`.
+    fn parse_const_statement(&mut self) -> Result<Statement, ParserError> {
+        Ok(Statement::Const(Box::new(self.parse_let_statement()?)))
+    }
+
+    fn parse_let_pattern_statement(&mut self) -> Result<Statement, ParserError> {
+        self.next_token();
+        let pattern = self.parse_pattern()?;
+        self.expect_peek::<()>(TokenType::Assign)?;
         self.next_token();
 
         let exp = self.parse_expression(Precedence::LOWEST)?;
         if let Token::Semicolon = &self.peek_token {
             self.next_token();
         }
-        Ok(Statement::Let(ident, exp))
+        Ok(Statement::LetPattern(pattern, exp))
+    }
+
+    /// Parses `fn name(params) { body }` into the `let` binding `name` to the function.
+    fn parse_fn_declaration(&mut self) -> Result<Statement, ParserError> {
+        let name: String = self.expect_peek(TokenType::Identifier)?;
+        let function = self.parse_fn_expression()?;
+        if let Token::Semicolon = &self.peek_token {
+            self.next_token();
+        }
+        let declared = Statement::Let(name, None, function);
+        Ok(Statement::FnDeclaration(Box::new(declared)))
+    }
+
+    fn parse_import_statement(&mut self) -> Result<Statement, ParserError> {
+        let Token::String(path) = self.peek_token.clone() else {
+            return Err(self.peek_error(TokenType::String));
+        };
+        self.next_token();
+        let alias = if let Token::As = &self.peek_token {
+            self.next_token();
+            Some(self.expect_peek(TokenType::Identifier)?)
+        } else {
+            None
+        };
+        if let Token::Semicolon = &self.peek_token {
+            self.next_token();
+        }
+        Ok(Statement::Import(path, alias))
+    }
+
+    fn parse_export_statement(&mut self) -> Result<Statement, ParserError> {
+        if let Token::Function = &self.peek_token {
+            self.next_token();
+            return Ok(Statement::Export(Box::new(self.parse_fn_declaration()?)));
+        }
+        if let Token::Const = &self.peek_token {
+            self.next_token();
+            return Ok(Statement::Export(Box::new(self.parse_const_statement()?)));
+        }
+        self.expect_peek::<()>(TokenType::Let)?;
+        Ok(Statement::Export(Box::new(self.parse_let_statement()?)))
     }
 
     fn parse_return_statement(&mut self) -> Result<Statement, ParserError> {
@@ -85,6 +206,15 @@ impl<'a> Parser<'a> {
         Ok(Statement::Return(exp))
     }
 
+    fn parse_throw_statement(&mut self) -> Result<Statement, ParserError> {
+        self.next_token();
+        let exp = self.parse_expression(Precedence::LOWEST)?;
+        if let Token::Semicolon = &self.peek_token {
+            self.next_token();
+        }
+        Ok(Statement::Throw(exp))
+    }
+
     fn parse_expression_statement(&mut self) -> Result<Statement, ParserError> {
         let expression = self.parse_expression(Precedence::LOWEST)?;
 
@@ -105,7 +235,7 @@ impl<'a> Parser<'a> {
         while !matches!(&self.cur_token, Token::RBrace) {
             self.next_token();
             let key = self.parse_expression(Precedence::LOWEST)?;
-            self.expect_peek(TokenType::Colon)?;
+            self.expect_peek::<()>(TokenType::Colon)?;
             self.next_token();
             map.push((key, self.parse_expression(Precedence::LOWEST)?));
             self.next_token();
@@ -128,61 +258,145 @@ impl<'a> Parser<'a> {
             self.next_token();
             values.push(self.parse_expression(Precedence::LOWEST)?);
         }
-        self.expect_peek(TokenType::RBracket)?;
+        self.expect_peek::<()>(TokenType::RBracket)?;
         Ok(values.into())
     }
 
     fn parse_fn_expression(&mut self) -> Result<Expression, ParserError> {
-        self.expect_peek(TokenType::Lparen)?;
-        let params = self.parse_fn_params()?;
-        self.expect_peek(TokenType::LSquirly)?;
+        self.expect_peek::<()>(TokenType::Lparen)?;
+        let (params, param_types): (Vec<Param>, Vec<Option<Type>>) =
+            self.parse_fn_params()?.into_iter().unzip();
+        let ret = if let Token::Arrow = &self.peek_token {
+            self.next_token();
+            self.next_token();
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        self.expect_peek::<()>(TokenType::LSquirly)?;
 
-        Ok(Expression::FnExpression(
-            params,
-            self.parse_block_statement()?,
-        ))
+        let annotated = ret.is_some() || param_types.iter().any(Option::is_some);
+        let signature = annotated.then_some(Signature {
+            params: param_types,
+            ret,
+        });
+        let body = self.parse_block_statement()?;
+        Ok(Expression::FnExpression(params, body, signature))
     }
 
-    fn parse_fn_params(&mut self) -> Result<Vec<Identifier>, ParserError> {
-        let mut idents = Vec::<Identifier>::new();
+    /// Parses the params up to the `)`. A `...name` param has to be the last one, and once a
+    /// param has a default, every param after it but a `...name` one needs one too.
+    fn parse_fn_params(&mut self) -> Result<Vec<FnParam>, ParserError> {
+        let mut params = Vec::new();
         if matches!(&self.peek_token, Token::RParent) {
             self.next_token();
-            return Ok(idents);
+            return Ok(params);
         }
 
         self.next_token();
+        params.push(self.parse_fn_param()?);
 
-        if let Token::Ident(ident) = &self.cur_token {
-            idents.push(ident.clone());
-        } else {
-            return Err(self.cur_error(TokenType::Identifier));
+        while matches!(&self.peek_token, Token::Comma) && !params[params.len() - 1].0.rest {
+            self.next_token();
+            self.next_token();
+            let (param, ty) = self.parse_fn_param()?;
+            let defaults = params.iter().any(|(param, _)| param.default.is_some());
+            if defaults && param.default.is_none() && !param.rest {
+                return Err(ParserError::RequiredAfterDefault(param.to_string()));
+            }
+            params.push((param, ty));
         }
+        self.expect_peek::<()>(TokenType::Rparen)?;
+        Ok(params)
+    }
 
-        while matches!(&self.peek_token, Token::Comma) {
+    /// Parses `name`, `...name`, or an array or hash pattern to destructure the argument with,
+    /// then its annotation and default value if it has them.
+    fn parse_fn_param(&mut self) -> Result<FnParam, ParserError> {
+        let rest = matches!(self.cur_token, Token::Ellipsis);
+        if rest {
+            self.next_token();
+        }
+        let (name, pattern) = match self.cur_token.clone() {
+            Token::Ident(ident) => (ident, None),
+            Token::LBracket | Token::LBrace if !rest => {
+                (String::new(), Some(self.parse_pattern()?))
+            }
+            _ => return Err(self.cur_error(TokenType::Identifier)),
+        };
+        let ty = self.parse_annotation()?;
+        let default = if !rest && matches!(self.peek_token, Token::Assign) {
             self.next_token();
             self.next_token();
+            Some(self.parse_expression(Precedence::LOWEST)?)
+        } else {
+            None
+        };
+        let param = Param {
+            name,
+            pattern,
+            default,
+            rest,
+        };
+        Ok((param, ty))
+    }
 
-            if let Token::Ident(ident) = &self.cur_token {
-                idents.push(ident.clone());
-            } else {
-                return Err(self.peek_error(TokenType::Identifier));
+    /// Parses the `: type` after a name, if there is one.
+    fn parse_annotation(&mut self) -> Result<Option<Type>, ParserError> {
+        if !matches!(&self.peek_token, Token::Colon) {
+            return Ok(None);
+        }
+        self.next_token();
+        self.next_token();
+        Ok(Some(self.parse_type()?))
+    }
+
+    /// Parses the type starting at the current token: a name like `int`, or
+    /// `fn(params) -> ret`, where leaving out `-> ret` means it may return anything.
+    fn parse_type(&mut self) -> Result<Type, ParserError> {
+        match &self.cur_token {
+            Token::Ident(name) => {
+                Type::from_name(name).ok_or_else(|| ParserError::UnknownType(name.clone()))
+            }
+            Token::Function => {
+                self.expect_peek::<()>(TokenType::Lparen)?;
+                let mut params = Vec::new();
+                if matches!(&self.peek_token, Token::RParent) {
+                    self.next_token();
+                } else {
+                    self.next_token();
+                    params.push(self.parse_type()?);
+                    while matches!(&self.peek_token, Token::Comma) {
+                        self.next_token();
+                        self.next_token();
+                        params.push(self.parse_type()?);
+                    }
+                    self.expect_peek::<()>(TokenType::Rparen)?;
+                }
+                let ret = if let Token::Arrow = &self.peek_token {
+                    self.next_token();
+                    self.next_token();
+                    self.parse_type()?
+                } else {
+                    Type::Any
+                };
+                Ok(Type::Fn(params, Box::new(ret)))
             }
+            token => Err(ParserError::UnknownType(token.to_string())),
         }
-        self.expect_peek(TokenType::Rparen)?;
-        Ok(idents)
     }
 
     fn parse_if_expression(&mut self) -> Result<Expression, ParserError> {
-        self.expect_peek(TokenType::Lparen)?;
+        self.expect_peek::<()>(TokenType::Lparen)?;
         self.next_token();
         let cond = self.parse_expression(Precedence::LOWEST)?;
 
-        self.expect_peek(TokenType::Rparen)?;
-        self.expect_peek(TokenType::LSquirly)?;
+        self.expect_peek::<()>(TokenType::Rparen)?;
+        self.expect_peek::<()>(TokenType::LSquirly)?;
         let if_block = self.parse_block_statement()?;
         let else_block = if let Token::Else = &self.peek_token {
             self.next_token();
-            self.expect_peek(TokenType::LSquirly)?;
+            self.expect_peek::<()>(TokenType::LSquirly)?;
             Some(self.parse_block_statement()?)
         } else {
             None
@@ -195,17 +409,165 @@ impl<'a> Parser<'a> {
         ))
     }
 
+    fn parse_try_expression(&mut self) -> Result<Expression, ParserError> {
+        self.expect_peek::<()>(TokenType::LSquirly)?;
+        let body = self.parse_block_statement()?;
+        let catch = if let Token::Catch = &self.peek_token {
+            self.next_token();
+            self.expect_peek::<()>(TokenType::Lparen)?;
+            let name: String = self.expect_peek(TokenType::Identifier)?;
+            self.expect_peek::<()>(TokenType::Rparen)?;
+            self.expect_peek::<()>(TokenType::LSquirly)?;
+            Some((Pattern::Binding(name), self.parse_block_statement()?))
+        } else {
+            None
+        };
+        let finally = if let Token::Finally = &self.peek_token {
+            self.next_token();
+            self.expect_peek::<()>(TokenType::LSquirly)?;
+            Some(self.parse_block_statement()?)
+        } else {
+            None
+        };
+        if catch.is_none() && finally.is_none() {
+            return Err(self.peek_error(TokenType::Catch));
+        }
+        Ok(Expression::TryExpression(body, catch, finally))
+    }
+
+    fn parse_match_expression(&mut self) -> Result<Expression, ParserError> {
+        self.next_token();
+        let value = self.parse_expression(Precedence::LOWEST)?;
+        self.expect_peek::<()>(TokenType::LSquirly)?;
+        let mut arms = Vec::new();
+        while !matches!(self.peek_token, Token::RBrace) {
+            self.next_token();
+            let pattern = self.parse_pattern()?;
+            let guard = if let Token::If = self.peek_token {
+                self.next_token();
+                self.next_token();
+                Some(self.parse_expression(Precedence::LOWEST)?)
+            } else {
+                None
+            };
+            self.expect_peek::<()>(TokenType::FatArrow)?;
+            self.next_token();
+            let value = self.parse_expression(Precedence::LOWEST)?;
+            arms.push(MatchArm {
+                pattern,
+                guard,
+                value,
+            });
+            if !matches!(self.peek_token, Token::Comma) {
+                break;
+            }
+            self.next_token();
+        }
+        self.expect_peek::<()>(TokenType::RSquirly)?;
+        Ok(Expression::Match(Box::new(value), arms))
+    }
+
+    fn parse_pattern(&mut self) -> Result<Pattern, ParserError> {
+        Ok(match &self.cur_token {
+            Token::Ident(name) if name == "_" => Pattern::Wildcard,
+            Token::Ident(name) => Pattern::Binding(name.clone()),
+            Token::LBracket => self.parse_array_pattern()?,
+            Token::LBrace => self.parse_hash_pattern()?,
+            _ => Pattern::Literal(self.parse_literal()?),
+        })
+    }
+
+    fn parse_literal(&mut self) -> Result<Literal, ParserError> {
+        match &self.cur_token {
+            Token::Int(i) => Ok(Literal::Int(*i)),
+            Token::String(s) => Ok(Literal::String(s.clone())),
+            Token::Bool(b) => Ok(Literal::Bool(*b)),
+            Token::Dash => match self.peek_token {
+                Token::Int(i) => {
+                    self.next_token();
+                    Ok(Literal::Int(-i))
+                }
+                _ => Err(ParserError::InvalidPattern(TokenType::Dash)),
+            },
+            token => Err(ParserError::InvalidPattern(TokenType::from(token))),
+        }
+    }
+
+    fn parse_array_pattern(&mut self) -> Result<Pattern, ParserError> {
+        let mut elements = Vec::new();
+        let mut rest = None;
+        while !matches!(self.peek_token, Token::RBracket) {
+            self.next_token();
+            if let Token::DotDot | Token::Ellipsis = self.cur_token {
+                let pattern = match &self.peek_token {
+                    Token::Ident(name) if name != "_" => Pattern::Binding(name.clone()),
+                    _ => Pattern::Wildcard,
+                };
+                if let Token::Ident(_) = self.peek_token {
+                    self.next_token();
+                }
+                rest = Some(Box::new(pattern));
+                break;
+            }
+            elements.push(self.parse_pattern()?);
+            if !matches!(self.peek_token, Token::Comma) {
+                break;
+            }
+            self.next_token();
+        }
+        self.expect_peek::<()>(TokenType::RBracket)?;
+        Ok(Pattern::Array(elements, rest))
+    }
+
+    fn parse_hash_pattern(&mut self) -> Result<Pattern, ParserError> {
+        let mut pairs = Vec::new();
+        while !matches!(self.peek_token, Token::RBrace) {
+            self.next_token();
+            if let (Token::Ident(name), false) =
+                (&self.cur_token, matches!(self.peek_token, Token::Colon))
+            {
+                pairs.push((Literal::String(name.clone()), Pattern::Binding(name.clone())));
+            } else {
+                let key = self.parse_literal()?;
+                self.expect_peek::<()>(TokenType::Colon)?;
+                self.next_token();
+                pairs.push((key, self.parse_pattern()?));
+            }
+            if !matches!(self.peek_token, Token::Comma) {
+                break;
+            }
+            self.next_token();
+        }
+        self.expect_peek::<()>(TokenType::RSquirly)?;
+        Ok(Pattern::Hash(pairs))
+    }
+
     fn parse_block_statement(&mut self) -> Result<BlockStatement, ParserError> {
+        let id = self.blocks_started;
+        self.blocks_started += 1;
         self.next_token();
         let mut statements = Vec::<Statement>::new();
+        let mut lines = Vec::new();
         while !matches!(&self.cur_token, Token::RBrace) {
+            if self.cur_token == Token::Eof {
+                return Err(ParserError::WrongCurrentToken {
+                    expected_token: TokenType::RSquirly,
+                    actual_token: TokenType::Eof,
+                });
+            }
+            let line = self.cur_line;
             if let Some(s) = self.parse_statement() {
                 statements.push(s);
+                lines.push(line);
             }
             self.next_token();
         }
+        if !self.cur_comments.is_empty() {
+            let comments = std::mem::take(&mut self.cur_comments);
+            self.comments.block_ends.insert(id, comments);
+        }
         // self.next_token();
-        Ok(BlockStatement { statements })
+        Ok(BlockStatement { statements, lines })
     }
 
     fn parse_expression(&mut self, precedence: i8) -> Result<Expression, ParserError> {
@@ -222,6 +584,7 @@ impl<'a> Parser<'a> {
             left_exp = match &self.cur_token {
                 Token::LParen => self.parse_call_expression(left_exp)?,
                 Token::LBracket => self.parse_array_index_expression(left_exp)?,
+                Token::Question => Expression::Propagate(Box::new(left_exp)),
                 _ => self.parse_infix_expression(left_exp)?,
             };
         }
@@ -235,7 +598,7 @@ impl<'a> Parser<'a> {
     ) -> Result<Expression, ParserError> {
         self.next_token();
         let val = self.parse_expression(Precedence::LOWEST)?;
-        self.expect_peek(TokenType::RBracket)?;
+        self.expect_peek::<()>(TokenType::RBracket)?;
         Ok(Expression::IndexExpression(Box::new(left), val.into()))
     }
 
@@ -253,17 +616,36 @@ impl<'a> Parser<'a> {
 
         self.next_token();
 
-        params.push(self.parse_expression(Precedence::LOWEST)?);
+        params.push(self.parse_call_argument()?);
 
         while matches!(&self.peek_token, Token::Comma) {
             self.next_token();
             self.next_token();
-            params.push(self.parse_expression(Precedence::LOWEST)?);
+            params.push(self.parse_call_argument()?);
         }
-        self.expect_peek(TokenType::Rparen)?;
+        self.expect_peek::<()>(TokenType::Rparen)?;
         Ok(params)
     }
 
+    /// Parses an argument: a value, `...array` or `name = value`.
+    fn parse_call_argument(&mut self) -> Result<Expression, ParserError> {
+        match (&self.cur_token, &self.peek_token) {
+            (Token::Ellipsis, _) => {
+                self.next_token();
+                let value = self.parse_expression(Precedence::LOWEST)?;
+                Ok(Expression::Spread(Box::new(value)))
+            }
+            (Token::Ident(name), Token::Assign) => {
+                let name = name.clone();
+                self.next_token();
+                self.next_token();
+                let value = self.parse_expression(Precedence::LOWEST)?;
+                Ok(Expression::KeywordArg(name, Box::new(value)))
+            }
+            _ => self.parse_expression(Precedence::LOWEST),
+        }
+    }
+
     fn parse_identifier(&self, ident: &str) -> Result<Expression, ParserError> {
         Ok(Expression::Identifier(ident.to_owned()))
     }
@@ -287,6 +669,8 @@ impl<'a> Parser<'a> {
             Token::Bool(b) => return self.parse_bool(*b),
             Token::LParen => return self.parse_grouped_expression(),
             Token::If => return self.parse_if_expression(),
+            Token::Try => return self.parse_try_expression(),
+            Token::Match => return self.parse_match_expression(),
             Token::Function => return self.parse_fn_expression(),
             Token::LBracket => return self.parse_array(),
             Token::LBrace => return self.parse_hash(),
@@ -304,7 +688,7 @@ impl<'a> Parser<'a> {
     fn parse_grouped_expression(&mut self) -> Result<Expression, ParserError> {
         self.next_token();
         let exp = self.parse_expression(Precedence::LOWEST)?;
-        self.expect_peek(TokenType::Rparen)?;
+        self.expect_peek::<()>(TokenType::Rparen)?;
         Ok(exp)
     }
 
@@ -341,6 +725,8 @@ impl<'a> Parser<'a> {
                 | Token::LBrace
                 | Token::LBracket
                 | Token::If
+                | Token::Try
+                | Token::Match
                 | Token::Function
                 | Token::String(_)
         )
@@ -360,6 +746,7 @@ impl<'a> Parser<'a> {
                 | Token::ForwardSlash
                 | Token::LParen
                 | Token::LBracket
+                | Token::Question
                 | Token::Asterisk
         )
     }
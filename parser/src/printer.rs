@@ -0,0 +1,383 @@
+//! Prints a `Program` back out as canonical Monkey source: one statement per line, blocks
+//! indented by four spaces, a blank line around multi-line top-level statements, and only the
+//! parentheses the parser needs. Parsing the output gives back the same AST, and printing it
+//! again gives the same text.
+//!
+//! Comments are not part of the AST. The parser collects them separately, keyed by the order in
+//! which it started statements and blocks, and the printer walks the tree in that same order to
+//! put them back in front of the statement (or closing brace) they preceded. A comment anywhere
+//! else, such as inside a multi-line array, has no place to go back to, so `format` refuses
+//! source containing one rather than moving it.
+
+use std::collections::HashMap;
+
+use lexer::lexer::{Comment, Lexer};
+use lexer::token::Token;
+
+use crate::ast::{BlockStatement, Expression, Param, Precedence, Program, Signature, Statement};
+use crate::parse_error::ParserError;
+use crate::parser::Parser;
+
+const INDENT: &str = "    ";
+
+/// Binding strength of literals, identifiers and anything else that never needs parentheses.
+const ATOM: i8 = Precedence::INDEX + 1;
+
+/// Comments collected while parsing a program.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Comments {
+    /// Comments in front of the n-th statement the parser started.
+    pub statements: HashMap<usize, Vec<Comment>>,
+    /// Comments in front of the closing brace of the n-th block the parser started.
+    pub block_ends: HashMap<usize, Vec<Comment>>,
+    /// Comments after the last statement of the program.
+    pub end: Vec<Comment>,
+    /// Line of the first comment in front of neither a statement nor a closing brace. The
+    /// parser carries such comments on to the next place that takes them.
+    pub misplaced: Option<usize>,
+}
+
+/// Parses `input` and prints it back out in canonical form.
+pub fn format(input: &str) -> Result<String, Vec<ParserError>> {
+    let mut parser = Parser::new(Lexer::new(input));
+    let program = parser.parse_program();
+    if !parser.parse_errors.is_empty() {
+        return Err(parser.parse_errors);
+    }
+    if let Some(line) = parser.comments.misplaced {
+        return Err(vec![ParserError::MisplacedComment(line)]);
+    }
+    Ok(print_program(&program, &parser.comments))
+}
+
+/// Prints `program`, putting `comments` back where the parser found them. Pass
+/// `&Comments::default()` for a tree that didn't come from the parser.
+pub fn print_program(program: &Program, comments: &Comments) -> String {
+    let mut printer = Printer {
+        comments,
+        statements: 0,
+        blocks: 0,
+        indent: 0,
+    };
+    let mut out = String::new();
+    printer.write_statements(&program.statements, true, &mut out);
+    let (inline, own_line) = split_inline(&comments.end);
+    printer.write_comments(inline, &mut out);
+    if !out.is_empty() && !own_line.is_empty() {
+        out.push('\n');
+    }
+    printer.write_comments(own_line, &mut out);
+    out
+}
+
+struct Printer<'a> {
+    comments: &'a Comments,
+    /// Statements and blocks printed so far, matching the parser's counters.
+    statements: usize,
+    blocks: usize,
+    indent: usize,
+}
+
+impl Printer<'_> {
+    fn write_statements(&mut self, statements: &[Statement], top_level: bool, out: &mut String) {
+        let comments = self.comments;
+        let printed: Vec<(&[Comment], String)> = statements
+            .iter()
+            .map(|statement| {
+                let id = self.statements;
+                self.statements += 1;
+                let leading = comments.statements.get(&id).map_or(&[][..], Vec::as_slice);
+                (leading, self.statement(statement))
+            })
+            .collect();
+
+        for (i, (leading, text)) in printed.iter().enumerate() {
+            let (inline, own_line) = split_inline(leading);
+            self.write_comments(inline, out);
+            if top_level && i > 0 {
+                let previous = &printed[i - 1].1;
+                if previous.contains('\n') || text.contains('\n') || !own_line.is_empty() {
+                    out.push('\n');
+                }
+            }
+            self.write_comments(own_line, out);
+            let next = printed.get(i + 1).map(|(_, text)| text.as_str());
+            out.push_str(&INDENT.repeat(self.indent));
+            out.push_str(text);
+            if needs_semicolon(&statements[i], text, next, top_level) {
+                out.push(';');
+            }
+            out.push('\n');
+        }
+    }
+
+    /// Comments that started on their own line get one of their own; the rest go back on the
+    /// end of the line they followed.
+    fn write_comments(&self, comments: &[Comment], out: &mut String) {
+        for comment in comments {
+            if !comment.own_line && out.ends_with('\n') {
+                out.pop();
+                out.push_str(&format!(" //{}\n", comment.text));
+            } else {
+                out.push_str(&format!(
+                    "{}//{}\n",
+                    INDENT.repeat(self.indent),
+                    comment.text
+                ));
+            }
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) -> String {
+        match statement {
+            Statement::Let(..) | Statement::LetLocal(..) | Statement::LetPattern(..) => {
+                self.binding(Token::Let, statement)
+            }
+            Statement::Const(declared) => self.binding(Token::Const, declared),
+            Statement::Return(value) => {
+                format!(
+                    "{} {}",
+                    Token::Return,
+                    self.expression(value, Precedence::LOWEST)
+                )
+            }
+            Statement::ExpressionStatement(e) => self.expression(e, Precedence::LOWEST),
+            Statement::Import(path, None) => format!("{} \"{}\"", Token::Import, path),
+            Statement::Import(path, Some(alias)) => {
+                format!("{} \"{}\" {} {}", Token::Import, path, Token::As, alias)
+            }
+            Statement::Export(exported) => {
+                format!("{} {}", Token::Export, self.statement(exported))
+            }
+            Statement::Throw(value) => {
+                format!(
+                    "{} {}",
+                    Token::Throw,
+                    self.expression(value, Precedence::LOWEST)
+                )
+            }
+            Statement::FnDeclaration(declared) => match declared.as_ref() {
+                Statement::Let(name, _, Expression::FnExpression(params, body, signature))
+                | Statement::LetLocal(name, _, Expression::FnExpression(params, body, signature)) =>
+                {
+                    let (params, ret) = self.params(params, signature.as_ref());
+                    let body = self.block(body);
+                    format!("{} {name}({params}){ret} {body}", Token::Function)
+                }
+                declared => self.statement(declared),
+            },
+        }
+    }
+
+    /// A `let`-like statement, starting with `keyword`.
+    fn binding(&mut self, keyword: Token, statement: &Statement) -> String {
+        match statement {
+            Statement::Let(name, None, value) | Statement::LetLocal(name, _, value) => {
+                let value = self.expression(value, Precedence::LOWEST);
+                format!("{keyword} {name} = {value}")
+            }
+            Statement::Let(name, Some(ty), value) => {
+                let value = self.expression(value, Precedence::LOWEST);
+                format!("{keyword} {name}: {ty} = {value}")
+            }
+            Statement::LetPattern(pattern, value) => {
+                let value = self.expression(value, Precedence::LOWEST);
+                format!("{keyword} {pattern} = {value}")
+            }
+            statement => self.statement(statement),
+        }
+    }
+
+    fn block(&mut self, block: &BlockStatement) -> String {
+        let id = self.blocks;
+        self.blocks += 1;
+        let comments = self.comments;
+        let trailing = comments.block_ends.get(&id).map_or(&[][..], Vec::as_slice);
+
+        self.indent += 1;
+        let mut out = String::from("{\n");
+        self.write_statements(&block.statements, false, &mut out);
+        self.write_comments(trailing, &mut out);
+        self.indent -= 1;
+
+        if out == "{\n" {
+            return "{}".to_string();
+        }
+        out.push_str(&INDENT.repeat(self.indent));
+        out.push('}');
+        out
+    }
+
+    /// Prints `e`, wrapped in parentheses if it binds less tightly than `required`.
+    fn expression(&mut self, e: &Expression, required: i8) -> String {
+        let (text, precedence) = match e {
+            Expression::Identifier(name) | Expression::Local(name, _, _) => (name.clone(), ATOM),
+            Expression::IntLiteral(i) => (i.to_string(), ATOM),
+            Expression::StringLiteral(s) => (format!("\"{s}\""), ATOM),
+            Expression::Bool(b) => (b.to_string(), ATOM),
+            Expression::PrefixExpression(op, right) => (
+                format!("{op}{}", self.expression(right, Precedence::PREFIX)),
+                Precedence::PREFIX,
+            ),
+            Expression::InfixExpression(op, left, right) => {
+                let precedence = Precedence::from(op);
+                let left = self.expression(left, precedence);
+                // Operators are left-associative, so an operand of the same precedence on the
+                // right has to keep its parentheses.
+                let right = self.expression(right, precedence + 1);
+                (format!("{left} {op} {right}"), precedence)
+            }
+            Expression::IfExpression(cond, if_block, else_block) => {
+                let cond = self.expression(cond, Precedence::LOWEST);
+                let mut text = format!("{} ({cond}) {}", Token::If, self.block(if_block));
+                if let Some(else_block) = else_block {
+                    text.push_str(&format!(" {} {}", Token::Else, self.block(else_block)));
+                }
+                (text, ATOM)
+            }
+            Expression::FnExpression(params, body, signature) => {
+                let (params, ret) = self.params(params, signature.as_ref());
+                let text = format!("{}({params}){ret} {}", Token::Function, self.block(body));
+                (text, ATOM)
+            }
+            Expression::CallExpression(function, args) => {
+                let function = self.expression(function, Precedence::CALL);
+                let args = self.expressions(args);
+                (format!("{function}({args})"), Precedence::CALL)
+            }
+            Expression::Arrary(values) => (format!("[{}]", self.expressions(values)), ATOM),
+            Expression::Map(pairs) => {
+                let pairs = pairs
+                    .iter()
+                    .map(|(key, value)| {
+                        let key = self.expression(key, Precedence::LOWEST);
+                        format!("{key}: {}", self.expression(value, Precedence::LOWEST))
+                    })
+                    .collect::<Vec<_>>();
+                (format!("{{{}}}", pairs.join(", ")), ATOM)
+            }
+            Expression::IndexExpression(left, index) => {
+                let left = self.expression(left, Precedence::CALL);
+                let index = self.expression(index, Precedence::LOWEST);
+                (format!("{left}[{index}]"), Precedence::INDEX)
+            }
+            Expression::TryExpression(body, catch, finally) => {
+                let mut text = format!("{} {}", Token::Try, self.block(body));
+                if let Some((name, handler)) = catch {
+                    let handler = self.block(handler);
+                    text.push_str(&format!(" {} ({name}) {handler}", Token::Catch));
+                }
+                if let Some(finally) = finally {
+                    text.push_str(&format!(" {} {}", Token::Finally, self.block(finally)));
+                }
+                (text, ATOM)
+            }
+            Expression::Propagate(value) => {
+                let value = self.expression(value, Precedence::CALL);
+                (format!("{value}{}", Token::Question), Precedence::INDEX)
+            }
+            Expression::Match(value, arms) if arms.is_empty() => {
+                let value = self.expression(value, Precedence::LOWEST);
+                (format!("{} {value} {{}}", Token::Match), ATOM)
+            }
+            Expression::Spread(value) => {
+                let value = self.expression(value, Precedence::LOWEST);
+                (format!("{}{value}", Token::Ellipsis), ATOM)
+            }
+            Expression::KeywordArg(name, value) => {
+                let value = self.expression(value, Precedence::LOWEST);
+                (format!("{name} {} {value}", Token::Assign), ATOM)
+            }
+            Expression::Match(value, arms) => {
+                let value = self.expression(value, Precedence::LOWEST);
+                self.indent += 1;
+                let arms: String = arms
+                    .iter()
+                    .map(|arm| {
+                        let mut text = format!("{}{}", INDENT.repeat(self.indent), arm.pattern);
+                        if let Some(guard) = &arm.guard {
+                            let guard = self.expression(guard, Precedence::LOWEST);
+                            text.push_str(&format!(" {} {guard}", Token::If));
+                        }
+                        let result = self.expression(&arm.value, Precedence::LOWEST);
+                        text + &format!(" {} {result},\n", Token::FatArrow)
+                    })
+                    .collect();
+                self.indent -= 1;
+                let end = INDENT.repeat(self.indent);
+                (format!("{} {value} {{\n{arms}{end}}}", Token::Match), ATOM)
+            }
+        };
+        if precedence < required {
+            format!("({text})")
+        } else {
+            text
+        }
+    }
+
+    fn expressions(&mut self, values: &[Expression]) -> String {
+        values
+            .iter()
+            .map(|value| self.expression(value, Precedence::LOWEST))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// A function literal's params, and its return type annotation if it has one, as source.
+    fn params(&mut self, params: &[Param], signature: Option<&Signature>) -> (String, String) {
+        let params = params
+            .iter()
+            .enumerate()
+            .map(|(i, param)| {
+                let rest = if param.rest { Token::Ellipsis.to_string() } else { String::new() };
+                let mut text = match &param.pattern {
+                    Some(pattern) => format!("{rest}{pattern}"),
+                    None => format!("{rest}{}", param.name),
+                };
+                let ty = signature.and_then(|signature| signature.params.get(i)?.as_ref());
+                if let Some(ty) = ty {
+                    text.push_str(&format!(": {ty}"));
+                }
+                if let Some(default) = &param.default {
+                    let default = self.expression(default, Precedence::LOWEST);
+                    text.push_str(&format!(" {} {default}", Token::Assign));
+                }
+                text
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let ret = match signature.and_then(|signature| signature.ret.as_ref()) {
+            Some(ret) => format!(" {} {ret}", Token::Arrow),
+            None => String::new(),
+        };
+        (params, ret)
+    }
+}
+
+/// Splits off the comments that continue the line before them.
+fn split_inline(comments: &[Comment]) -> (&[Comment], &[Comment]) {
+    let own_line = comments
+        .iter()
+        .position(|c| c.own_line)
+        .unwrap_or(comments.len());
+    comments.split_at(own_line)
+}
+
+/// Every statement ends in `;` except the expression that gives a block its value, and
+/// expressions ending in a brace when the next statement can't be read as continuing them.
+fn needs_semicolon(statement: &Statement, text: &str, next: Option<&str>, top_level: bool) -> bool {
+    let declared = match statement {
+        Statement::Export(exported) => exported.as_ref(),
+        statement => statement,
+    };
+    if let Statement::FnDeclaration(_) = declared {
+        return false;
+    }
+    let Statement::ExpressionStatement(_) = statement else {
+        return true;
+    };
+    match next {
+        None => top_level,
+        Some(next) => !text.ends_with('}') || next.starts_with(['(', '[', '-']),
+    }
+}
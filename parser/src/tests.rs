@@ -1,8 +1,250 @@
 #![allow(dead_code)]
-use crate::ast::{Expression, Program, Statement};
+use crate::ast::{
+    BlockStatement, Expression, Fold, Identifier, Literal, Param, Pattern, Program, Signature,
+    Statement, Type, Visitor, VisitorMut,
+};
+use crate::parse_error::{ParserError, TokenType};
 use crate::parser::Parser;
+use crate::printer::{format, print_program, Comments};
 use lexer::lexer::Lexer;
 use lexer::token::Token;
+use proptest::prelude::*;
+
+#[test]
+fn test_format() {
+    let input = r#"// Doubles things.
+let double=fn(x){x*2}; // inline
+let pick = fn(a,b) { if (a>b) {a} else {
+  // the smaller one
+  b } };
+let xs=[1,
+  2];
+if (true) { put(1) }; -double(1-(2-3))*(4+5);
+fn() {
+    // nothing here
+}
+// the end
+"#;
+    let expected = r#"// Doubles things.
+let double = fn(x) {
+    x * 2
+}; // inline
+
+let pick = fn(a, b) {
+    if (a > b) {
+        a
+    } else {
+        // the smaller one
+        b
+    }
+};
+
+let xs = [1, 2];
+
+if (true) {
+    put(1)
+};
+
+-double(1 - (2 - 3)) * (4 + 5);
+
+fn() {
+    // nothing here
+};
+
+// the end
+"#;
+    let formatted = format(input).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(format(&formatted).unwrap(), formatted);
+    assert_eq!(parse(&formatted), parse(input));
+    assert!(format("let = 5;").is_err());
+
+    let misplaced = vec![
+        ("let xs = [1,\n  // in the middle\n  2];\nxs", 2),
+        ("if (a) {\n    b\n}\n// otherwise\nelse {\n    c\n}", 4),
+        ("let f = fn(a, // first\n  b) { a };", 1),
+        ("let x = 1 + // hi\n 2;", 1),
+    ];
+    for (input, line) in misplaced {
+        assert_eq!(
+            format(input),
+            Err(vec![ParserError::MisplacedComment(line)]),
+            "{input}"
+        );
+    }
+}
+
+fn parse(input: &str) -> Program {
+    let mut parser = Parser::new(Lexer::new(input));
+    let program = parser.parse_program();
+    assert!(parser.parse_errors.is_empty(), "{input}\n{:?}", parser.parse_errors);
+    program
+}
+
+fn identifier() -> impl Strategy<Value = String> {
+    prop::sample::select(vec!["a", "b", "foo", "bar", "len"]).prop_map(String::from)
+}
+
+fn ty() -> impl Strategy<Value = Type> {
+    let leaf = prop::sample::select(vec![
+        Type::Int,
+        Type::String,
+        Type::Bool,
+        Type::Null,
+        Type::Array,
+        Type::Hash,
+        Type::Any,
+    ]);
+    leaf.prop_recursive(2, 8, 3, |inner| {
+        (prop::collection::vec(inner.clone(), 0..3), inner)
+            .prop_map(|(params, ret)| Type::Fn(params, Box::new(ret)))
+    })
+}
+
+fn statement(expression: BoxedStrategy<Expression>) -> impl Strategy<Value = Statement> {
+    prop_oneof![
+        (identifier(), prop::option::of(ty()), expression.clone())
+            .prop_map(|(name, ty, e)| Statement::Let(name, ty, e)),
+        expression.clone().prop_map(Statement::Return),
+        expression.prop_map(Statement::ExpressionStatement),
+    ]
+}
+
+fn block(expression: BoxedStrategy<Expression>) -> impl Strategy<Value = BlockStatement> {
+    prop::collection::vec(statement(expression), 0..3)
+        .prop_map(|statements| BlockStatement {
+            statements,
+            lines: Vec::new(),
+        })
+}
+
+fn expression() -> impl Strategy<Value = Expression> {
+    let leaf = prop_oneof![
+        identifier().prop_map(Expression::Identifier),
+        (0..1000i64).prop_map(Expression::IntLiteral),
+        "[a-z ]{0,5}".prop_map(Expression::StringLiteral),
+        any::<bool>().prop_map(Expression::Bool),
+    ];
+    let prefix = prop::sample::select(vec![Token::Bang, Token::Dash]);
+    let infix = prop::sample::select(vec![
+        Token::Plus,
+        Token::Dash,
+        Token::Asterisk,
+        Token::ForwardSlash,
+        Token::Equal,
+        Token::NotEqual,
+        Token::LessThan,
+        Token::LessThanEqual,
+        Token::GreaterThan,
+        Token::GreaterThanEqual,
+    ]);
+    leaf.prop_recursive(4, 48, 4, move |inner| {
+        prop_oneof![
+            (prefix.clone(), inner.clone())
+                .prop_map(|(op, e)| Expression::PrefixExpression(op, Box::new(e))),
+            (infix.clone(), inner.clone(), inner.clone()).prop_map(|(op, l, r)| {
+                Expression::InfixExpression(op, Box::new(l), Box::new(r))
+            }),
+            (inner.clone(), block(inner.clone()), prop::option::of(block(inner.clone())))
+                .prop_map(|(cond, if_block, else_block)| {
+                    Expression::IfExpression(Box::new(cond), if_block, else_block)
+                }),
+            (
+                prop::collection::vec((identifier(), prop::option::of(ty())), 0..3),
+                block(inner.clone()),
+                prop::option::of(ty()),
+            )
+                .prop_map(|(params, body, ret)| {
+                    let (params, types): (Vec<String>, Vec<_>) = params.into_iter().unzip();
+                    let params = params.iter().map(|name| name.as_str().into()).collect();
+                    let signature = (ret.is_some() || types.iter().any(Option::is_some))
+                        .then_some(Signature { params: types, ret });
+                    Expression::FnExpression(params, body, signature)
+                }),
+            (inner.clone(), prop::collection::vec(inner.clone(), 0..3))
+                .prop_map(|(f, args)| Expression::CallExpression(Box::new(f), args)),
+            prop::collection::vec(inner.clone(), 0..3).prop_map(Expression::Arrary),
+            prop::collection::vec((inner.clone(), inner.clone()), 0..3).prop_map(Expression::Map),
+            (inner.clone(), inner)
+                .prop_map(|(l, i)| Expression::IndexExpression(Box::new(l), Box::new(i))),
+        ]
+    })
+}
+
+proptest! {
+    #[test]
+    fn test_print_round_trip(
+        statements in prop::collection::vec(statement(expression().boxed()), 0..5)
+    ) {
+        let program = Program {
+            statements,
+            lines: Vec::new(),
+        };
+        let printed = print_program(&program, &Comments::default());
+        prop_assert_eq!(parse(&printed), program);
+        prop_assert_eq!(format(&printed).unwrap(), printed);
+    }
+}
+
+#[test]
+fn test_visitors() {
+    let input = "let add = fn(a, b) { a + b }; if (add(1, 2) > a) { [add, {b: 3}] } else { -a[0] }";
+    let mut program = parse(input);
+
+    struct Identifiers(Vec<Identifier>);
+    impl Visitor for Identifiers {
+        fn visit_identifier(&mut self, name: &Identifier) {
+            self.0.push(name.clone());
+        }
+    }
+    let mut identifiers = Identifiers(Vec::new());
+    identifiers.visit_program(&program);
+    assert_eq!(
+        identifiers.0,
+        vec!["a", "b", "add", "a", "add", "b", "a"]
+    );
+
+    struct Rename;
+    impl VisitorMut for Rename {
+        fn visit_identifier_mut(&mut self, name: &mut Identifier) {
+            if name == "a" {
+                *name = "x".to_string();
+            }
+        }
+
+        fn visit_fn_mut(
+            &mut self,
+            params: &mut Vec<Param>,
+            body: &mut BlockStatement,
+            _signature: &mut Option<Signature>,
+        ) {
+            for param in params.iter_mut() {
+                self.visit_identifier_mut(&mut param.name);
+            }
+            self.visit_block_mut(body);
+        }
+    }
+    Rename.visit_program_mut(&mut program);
+    assert_eq!(
+        program,
+        parse("let add = fn(x, b) { x + b }; if (add(1, 2) > x) { [add, {b: 3}] } else { -x[0] }")
+    );
+
+    /// Replaces `n + m` on two literals with its sum, bottom-up.
+    struct AddLiterals;
+    impl Fold for AddLiterals {
+        fn fold_infix(&mut self, op: Token, left: Expression, right: Expression) -> Expression {
+            match (op, self.fold_expression(left), self.fold_expression(right)) {
+                (Token::Plus, Expression::IntLiteral(l), Expression::IntLiteral(r)) => {
+                    Expression::IntLiteral(l + r)
+                }
+                (op, left, right) => Expression::InfixExpression(op, left.into(), right.into()),
+            }
+        }
+    }
+    let folded = AddLiterals.fold_program(parse("let f = fn() { 1 + 2 + x + (3 + 4) }; [5 + 6]"));
+    assert_eq!(folded, parse("let f = fn() { 3 + x + 7 }; [11]"));
+}
 
 #[test]
 fn test_array_index_parse() {
@@ -102,10 +344,10 @@ fn test_fn_expressions() {
     assert_eq!(program.statements.len(), 1);
     let statement = &program.statements[0];
     if let Statement::ExpressionStatement(i) = statement {
-        if let Expression::FnExpression(params, block) = i {
+        if let Expression::FnExpression(params, block, None) = i {
             assert_eq!(params.len(), 2);
-            assert_eq!(String::from("x"), params[0]);
-            assert_eq!(String::from("y"), params[1]);
+            assert_eq!(Param::from("x"), params[0]);
+            assert_eq!(Param::from("y"), params[1]);
             if let Statement::ExpressionStatement(e) = &block.statements[0] {
                 test_infix_exp(e, Token::Plus, "x", "y");
             } else {
@@ -117,6 +359,53 @@ fn test_fn_expressions() {
     }
 }
 
+#[test]
+fn test_type_annotations() {
+    let program =
+        parse("let x: int = 5; let f = fn(a: string, b) -> fn(int) -> bool { b }; fn(g: fn()) {}");
+    let fn_type = |params, ret| Type::Fn(params, Box::new(ret));
+    assert_eq!(
+        program.statements[0],
+        Statement::Let("x".to_string(), Some(Type::Int), 5.into())
+    );
+    let Statement::Let(_, None, Expression::FnExpression(params, _, signature)) =
+        &program.statements[1]
+    else {
+        panic!("expected an unannotated let of a fn, got {}", program.statements[1]);
+    };
+    assert_eq!(params, &vec![Param::from("a"), Param::from("b")]);
+    assert_eq!(
+        signature,
+        &Some(Signature {
+            params: vec![Some(Type::String), None],
+            ret: Some(fn_type(vec![Type::Int], Type::Bool)),
+        })
+    );
+    let Statement::ExpressionStatement(Expression::FnExpression(_, _, signature)) =
+        &program.statements[2]
+    else {
+        panic!("expected a fn, got {}", program.statements[2]);
+    };
+    assert_eq!(
+        signature,
+        &Some(Signature {
+            params: vec![Some(fn_type(vec![], Type::Any))],
+            ret: None,
+        })
+    );
+    assert_eq!(
+        format("let f = fn(a:int,b)->string{a};let y:array=[];").unwrap(),
+        "let f = fn(a: int, b) -> string {\n    a\n};\n\nlet y: array = [];\n"
+    );
+
+    let mut p = Parser::new(Lexer::new("let x: integer = 5;"));
+    p.parse_program();
+    assert_eq!(
+        p.parse_errors[0],
+        ParserError::UnknownType("integer".to_string())
+    );
+}
+
 #[test]
 fn test_if_expressions() {
     let input = "if (x < y) { x }";
@@ -447,11 +736,283 @@ fn test_let_statements() {
     }
     for (i, value_name) in expected_statements.iter().enumerate() {
         let statement = &program.statements[i];
-        let test_result = test_let_statement(statement, value_name);
-        if test_result.is_err() {
-            panic!("{}", test_result.unwrap_err());
+        if let Err(e) = test_let_statement(statement, value_name) {
+            panic!("{}", e);
+        }
+    }
+}
+
+#[test]
+fn test_import_export_statements() {
+    let statements = get_statements(
+        r#"import "lib/math.monkey";
+        import "strings" as s;
+        export let x = 5;"#,
+    );
+    assert_eq!(
+        statements,
+        vec![
+            Statement::Import("lib/math.monkey".to_string(), None),
+            Statement::Import("strings".to_string(), Some("s".to_string())),
+            Statement::Export(Box::new(Statement::Let("x".to_string(), None, 5.into()))),
+        ]
+    );
+
+    let mut p = Parser::new(Lexer::new("import math; export 5;"));
+    p.parse_program();
+    assert_eq!(p.parse_errors.len(), 2);
+}
+
+#[test]
+fn test_try_throw() {
+    let block = |statements| BlockStatement {
+        statements,
+        lines: Vec::new(),
+    };
+    let statements = get_statements("try { f() } catch (e) { throw e; } finally { 1 }; throw 5;");
+    assert_eq!(
+        statements,
+        vec![
+            Statement::ExpressionStatement(Expression::TryExpression(
+                block(vec![Statement::ExpressionStatement(
+                    Expression::CallExpression(Box::new(Expression::Identifier("f".into())), vec![])
+                )]),
+                Some((
+                    Pattern::Binding("e".into()),
+                    block(vec![Statement::Throw(Expression::Identifier("e".into()))])
+                )),
+                Some(block(vec![Statement::ExpressionStatement(1.into())])),
+            )),
+            Statement::Throw(5.into()),
+        ]
+    );
+
+    let input = "let r = try {\n    f(1)\n} catch (e) {\n    throw e;\n} finally {\n    put(1)\n};\n";
+    assert_eq!(format(input).unwrap(), input);
+
+    assert_eq!(
+        get_statements("-f(x)?[0];"),
+        vec![Statement::ExpressionStatement(Expression::PrefixExpression(
+            Token::Dash,
+            Box::new(Expression::IndexExpression(
+                Box::new(Expression::Propagate(Box::new(Expression::CallExpression(
+                    Box::new(Expression::Identifier("f".into())),
+                    vec![Expression::Identifier("x".into())],
+                )))),
+                Box::new(0.into()),
+            )),
+        ))]
+    );
+    assert_eq!(format("(a + b)?;").unwrap(), "(a + b)?;\n");
+
+    let mut p = Parser::new(Lexer::new("try { 1 };"));
+    p.parse_program();
+    assert_eq!(
+        p.parse_errors[0],
+        ParserError::WrongPeekToken {
+            expected_token: TokenType::Catch,
+            actual_token: TokenType::Semicolon,
         }
+    );
+}
+
+#[test]
+fn test_match() {
+    let statements = get_statements("match x { [a, ..rest] if a > 0 => a, {\"k\": -1} => 0, _ => x };");
+    let Statement::ExpressionStatement(Expression::Match(value, arms)) = &statements[0] else {
+        panic!("expected a match, got {statements:?}");
+    };
+    assert_eq!(**value, Expression::Identifier("x".into()));
+    assert_eq!(
+        arms.iter().map(|arm| arm.pattern.clone()).collect::<Vec<_>>(),
+        vec![
+            Pattern::Array(
+                vec![Pattern::Binding("a".into())],
+                Some(Box::new(Pattern::Binding("rest".into())))
+            ),
+            Pattern::Hash(vec![(
+                Literal::String("k".into()),
+                Pattern::Literal(Literal::Int(-1))
+            )]),
+            Pattern::Wildcard,
+        ]
+    );
+    assert!(arms[0].guard.is_some() && arms[1].guard.is_none());
+
+    let input = "let m = match v {\n    0 => \"zero\",\n    [a, ..] if a > 0 => a,\n    _ => v,\n};\n";
+    assert_eq!(format(input).unwrap(), input);
+
+    let mut p = Parser::new(Lexer::new("match x { + => 1 };"));
+    p.parse_program();
+    assert_eq!(
+        p.parse_errors[0],
+        ParserError::InvalidPattern(TokenType::Plus)
+    );
+}
+
+#[test]
+fn test_destructuring() {
+    let rest = |name: &str| Some(Box::new(Pattern::Binding(name.into())));
+    assert_eq!(
+        get_statements("let [a, ...rest] = v; let {name, \"n\": [b, ..]} = h;"),
+        vec![
+            Statement::LetPattern(
+                Pattern::Array(vec![Pattern::Binding("a".into())], rest("rest")),
+                Expression::Identifier("v".into()),
+            ),
+            Statement::LetPattern(
+                Pattern::Hash(vec![
+                    (
+                        Literal::String("name".into()),
+                        Pattern::Binding("name".into())
+                    ),
+                    (
+                        Literal::String("n".into()),
+                        Pattern::Array(
+                            vec![Pattern::Binding("b".into())],
+                            Some(Box::new(Pattern::Wildcard))
+                        )
+                    ),
+                ]),
+                Expression::Identifier("h".into()),
+            ),
+        ]
+    );
+
+    // A destructured param keeps its pattern, and the body only what the source has.
+    let statements = get_statements("fn([x, y], z) { x };");
+    let Statement::ExpressionStatement(Expression::FnExpression(params, body, None)) =
+        &statements[0]
+    else {
+        panic!("expected a fn, got {statements:?}");
+    };
+    let destructured = Param {
+        name: String::new(),
+        pattern: Some(Pattern::Array(
+            vec![Pattern::Binding("x".into()), Pattern::Binding("y".into())],
+            None,
+        )),
+        ..Param::from("")
+    };
+    assert_eq!(params, &vec![destructured, Param::from("z")]);
+    assert_eq!(body.statements.len(), 1);
+
+    let input = "let [a, ..rest] = f(v);\n\nlet g = fn({name}, [x, _]: array) {\n    name + x\n};\n";
+    assert_eq!(format(input).unwrap(), input);
+    assert_eq!(format("let [a, ...b] = c;").unwrap(), "let [a, ..b] = c;\n");
+}
+
+#[test]
+fn test_params() {
+    let statements = get_statements("fn(a, b = 10, ...rest) { a }; f(...xs, k = 1);");
+    let Statement::ExpressionStatement(Expression::FnExpression(params, _, None)) = &statements[0]
+    else {
+        panic!("expected a fn, got {statements:?}");
+    };
+    let default = Param {
+        default: Some(Expression::IntLiteral(10)),
+        ..Param::from("b")
+    };
+    let rest = Param {
+        rest: true,
+        ..Param::from("rest")
+    };
+    assert_eq!(params, &vec![Param::from("a"), default, rest]);
+    assert_eq!(
+        statements[1],
+        Statement::ExpressionStatement(Expression::CallExpression(
+            Box::new(Expression::Identifier("f".into())),
+            vec![
+                Expression::Spread(Box::new(Expression::Identifier("xs".into()))),
+                Expression::KeywordArg("k".into(), Box::new(Expression::IntLiteral(1))),
+            ]
+        ))
+    );
+
+    // A rest param has to come last.
+    let mut p = Parser::new(Lexer::new("fn(...a, b) { a }"));
+    p.parse_program();
+    assert!(!p.parse_errors.is_empty());
+    // So does a param without a default after one with a default.
+    let mut p = Parser::new(Lexer::new("fn(a = 1, b) { b }"));
+    p.parse_program();
+    assert_eq!(p.parse_errors[0], ParserError::RequiredAfterDefault("b".to_string()));
+    get_statements("fn(a = 1, ...b) { b }");
+
+    let input = "let f = fn(a: int, b = a + 1, ...rest: array) {\n    a\n};\n\nf(...xs, b = 2);\n";
+    assert_eq!(format(input).unwrap(), input);
+}
+
+#[test]
+fn test_fn_declarations() {
+    let statements = get_statements("fn add(a, b) { a + b } add(1, 2);");
+    let Statement::FnDeclaration(declared) = &statements[0] else {
+        panic!("expected a declaration, got {statements:?}");
+    };
+    let Statement::Let(name, None, Expression::FnExpression(params, _, None)) = declared.as_ref()
+    else {
+        panic!("expected a let binding the function, got {declared}");
+    };
+    assert_eq!(name, "add");
+    assert_eq!(params, &vec![Param::from("a"), Param::from("b")]);
+    assert_eq!(statements.len(), 2);
+    assert_eq!(statements[0].to_string(), "fn add(a, b) (a + b)");
+
+    // Without a name, `fn` still starts an expression.
+    let statements = get_statements("fn(x) { x }(1);");
+    assert!(matches!(statements[0], Statement::ExpressionStatement(_)));
+
+    let input = "fn add(a: int, b = 1) -> int {\n    a + b\n}\n\nexport fn twice(x) {\n    add(x, x)\n}\n\nadd(1);\n";
+    assert_eq!(format(input).unwrap(), input);
+    assert_eq!(format("fn f() { 1 };").unwrap(), "fn f() {\n    1\n}\n");
+}
+
+#[test]
+fn test_constants() {
+    let statements = get_statements("const x: int = 1; const [a] = v; export const y = 2;");
+    assert_eq!(
+        statements,
+        vec![
+            Statement::Const(Box::new(Statement::Let(
+                "x".into(),
+                Some(Type::Int),
+                Expression::IntLiteral(1)
+            ))),
+            Statement::Const(Box::new(Statement::LetPattern(
+                Pattern::Array(vec![Pattern::Binding("a".into())], None),
+                Expression::Identifier("v".into())
+            ))),
+            Statement::Export(Box::new(Statement::Const(Box::new(Statement::Let(
+                "y".into(),
+                None,
+                Expression::IntLiteral(2)
+            ))))),
+        ]
+    );
+    assert_eq!(statements[0].to_string(), "const x: int = 1;");
+
+    let input = "const x: int = 1;\nconst [a, ..b] = v;\nexport const y = x;\n";
+    assert_eq!(format(input).unwrap(), input);
+}
+
+#[test]
+fn test_incomplete_input() {
+    // Editors parse the program after every keystroke, so every prefix must parse to an end.
+    let input = r#"let f = fn(a: int, b) -> int { if (a < b) { return [a, {"b": b}][0]; } f(b, a) };"#;
+    for end in 0..input.len() {
+        let mut p = Parser::new(Lexer::new(&input[..end]));
+        p.parse_program();
     }
+
+    let mut p = Parser::new(Lexer::new("let f = fn() { 1"));
+    p.parse_program();
+    assert_eq!(
+        p.parse_errors[0],
+        ParserError::WrongCurrentToken {
+            expected_token: TokenType::RSquirly,
+            actual_token: TokenType::Eof,
+        }
+    );
 }
 
 #[test]
@@ -464,11 +1025,9 @@ fn test_return_statements() {
 
     let statements = get_statements(input);
     assert_eq!(statements.len(), expected_count);
-    for i in 0..expected_count {
-        let statement = &statements[i];
-        let test_result = test_return_statement(statement);
-        if test_result.is_err() {
-            panic!("{}", test_result.unwrap_err());
+    for statement in &statements {
+        if let Err(e) = test_return_statement(statement) {
+            panic!("{}", e);
         }
     }
 }
@@ -0,0 +1,202 @@
+//! `debug script.monkey`: runs a script under a debugger driven by commands on stdin.
+
+use colored::Colorize;
+use eval::debug::{Debugger, Frame, Pause, PauseReason, Step};
+use eval::environment::Environment;
+use eval::object::Object;
+use eval::sync::Rc;
+use std::collections::{BTreeSet, HashSet};
+use std::io::Write;
+
+const HELP: &str = "\
+b N      set a breakpoint on line N        d N   delete the breakpoint on line N
+c        continue to the next breakpoint   s     step into the next statement
+n        step over calls                   o     step out of the current function
+bt       show the call stack               f N   select frame N of the call stack
+l        list the source around the line   p     print the selected frame's variables
+p NAME   print a variable                  env   print every scope the frame can see
+q        stop the program                  h     show this help";
+
+pub struct ReplDebugger {
+    source: Vec<String>,
+    breakpoints: BTreeSet<usize>,
+    /// Builtins and prelude functions, left out when printing the globals.
+    predefined: HashSet<String>,
+}
+
+impl ReplDebugger {
+    pub fn new(source: &str) -> Self {
+        ReplDebugger {
+            source: source.lines().map(String::from).collect(),
+            breakpoints: BTreeSet::new(),
+            predefined: Environment::new_with_builtin()
+                .bindings()
+                .map(|(name, _)| name.clone())
+                .collect(),
+        }
+    }
+
+    fn list(&self, line: usize) {
+        if line == 0 {
+            println!("{}", "this frame hasn't run a statement yet".red());
+            return;
+        }
+        let first = line.saturating_sub(3).max(1);
+        let last = (line + 3).min(self.source.len());
+        for n in first..=last {
+            let marker = match (n == line, self.breakpoints.contains(&n)) {
+                (true, _) => "->",
+                (false, true) => " *",
+                (false, false) => "  ",
+            };
+            println!("{marker}{n:>4}  {}", self.source[n - 1]);
+        }
+    }
+
+    fn backtrace(frames: &[Frame], selected: usize) {
+        for (i, frame) in frames.iter().enumerate().rev() {
+            let marker = if i == selected { "->" } else { "  " };
+            println!("{marker}#{i} {} (line {})", frame.function, frame.line);
+        }
+    }
+
+    fn variables(&self, frame: &Frame) -> Vec<(String, Rc<Object>)> {
+        frame
+            .variables()
+            .into_iter()
+            .filter(|(name, _)| !self.predefined.contains(name))
+            .collect()
+    }
+
+    fn print(&self, frame: &Frame, name: Option<&str>) {
+        match name {
+            None => {
+                for (name, value) in self.variables(frame) {
+                    println!("{name} = {value}");
+                }
+            }
+            Some(name) => {
+                let value = frame
+                    .variables()
+                    .into_iter()
+                    .find(|(n, _)| n == name)
+                    .map(|(_, value)| value)
+                    .or_else(|| frame.env.as_ref()?.borrow().get(name));
+                match value {
+                    Some(value) => println!("{name} = {value}"),
+                    None => println!("{}", format!("{name} is not defined here").red()),
+                }
+            }
+        }
+    }
+
+    /// The frame's own variables, then each enclosing scope's. The locals of enclosing
+    /// functions are shown by slot, since only the running function's slots have names here.
+    fn env(&self, frame: &Frame) {
+        for (depth, scope) in frame.scopes().iter().enumerate() {
+            println!("{}", format!("scope {depth}:").bold());
+            let scope = scope.borrow();
+            if depth == 0 {
+                for (name, value) in self.variables(frame) {
+                    println!("  {name} = {value}");
+                }
+                continue;
+            }
+            for (slot, value) in scope.slots().iter().enumerate() {
+                if let Some(value) = value {
+                    println!("  #{slot} = {value}");
+                }
+            }
+            let mut bindings: Vec<_> = scope
+                .bindings()
+                .filter(|(name, _)| !self.predefined.contains(*name))
+                .collect();
+            bindings.sort_by_key(|(name, _)| *name);
+            for (name, value) in bindings {
+                println!("  {name} = {value}");
+            }
+        }
+    }
+}
+
+impl Debugger for ReplDebugger {
+    fn breaks_at(&self, line: usize) -> bool {
+        self.breakpoints.contains(&line)
+    }
+
+    fn stop_on_entry(&self) -> bool {
+        true
+    }
+
+    fn pause(&mut self, pause: &Pause) -> Step {
+        let reason = match pause.reason {
+            PauseReason::Entry => "entry",
+            PauseReason::Breakpoint => "breakpoint",
+            PauseReason::Step => "step",
+        };
+        let current = pause
+            .frames
+            .last()
+            .expect("the program is always on the stack");
+        println!(
+            "{}",
+            format!(
+                "stopped at {reason} in {} (line {})",
+                current.function, pause.line
+            )
+            .yellow()
+        );
+        if let Some(text) = self.source.get(pause.line - 1) {
+            println!("{:>6}  {text}", pause.line);
+        }
+
+        let mut selected = pause.frames.len() - 1;
+        loop {
+            print!("{}", "(debug) ".green());
+            let _ = std::io::stdout().flush();
+            let mut line = String::new();
+            if std::io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+                return Step::Stop;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let frame = &pause.frames[selected];
+            match words.as_slice() {
+                [] => {}
+                ["c"] => return Step::Continue,
+                ["s"] => return Step::In,
+                ["n"] => return Step::Over,
+                ["o"] => return Step::Out,
+                ["q"] => return Step::Stop,
+                ["b"] => {
+                    for breakpoint in &self.breakpoints {
+                        println!("breakpoint on line {breakpoint}");
+                    }
+                }
+                ["b", n] | ["d", n] => match n.parse::<usize>() {
+                    Ok(n) if (1..=self.source.len()).contains(&n) => {
+                        if words[0] == "b" {
+                            self.breakpoints.insert(n);
+                        } else {
+                            self.breakpoints.remove(&n);
+                        }
+                    }
+                    _ => println!("{}", format!("no line {n}").red()),
+                },
+                ["bt"] => ReplDebugger::backtrace(pause.frames, selected),
+                ["f", n] => match n.parse::<usize>() {
+                    Ok(n) if n < pause.frames.len() => {
+                        selected = n;
+                        ReplDebugger::backtrace(pause.frames, selected);
+                    }
+                    _ => println!("{}", format!("no frame {n}").red()),
+                },
+                ["l"] => self.list(frame.line),
+                ["p"] => self.print(frame, None),
+                ["p", name] => self.print(frame, Some(name)),
+                ["env"] => self.env(frame),
+                ["h"] => println!("{HELP}"),
+                _ => println!("{}", "unknown command, h for help".red()),
+            }
+        }
+    }
+}
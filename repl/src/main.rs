@@ -1,17 +1,155 @@
+mod debugger;
+
 use colored::Colorize;
+use debugger::ReplDebugger;
+use eval::check;
+use eval::interpreter::{Interpreter, InterpreterError};
+use eval::lint::{self, Diagnostic, Severity};
 use eval::object::Object;
-use eval::{environment::Environment, eval::eval};
-use lexer::lexer::Lexer;
-use parser::parser::Parser;
-use std::{cell::RefCell, io::Write, rc::Rc};
+use eval::sync::Rc;
+use parser::printer;
+use std::io::{Read, Write};
 
 fn main() {
-    Repl::start();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.as_slice() {
+        [command, rest @ ..] if command == "fmt" => Repl::fmt(rest),
+        [command, rest @ ..] if command == "lint" => Repl::diagnose(rest, lint::lint_source),
+        [command, rest @ ..] if command == "check" => Repl::diagnose(rest, check::check_source),
+        [command, path] if command == "debug" => Repl::debug(path),
+        [path, ..] => Repl::run_file(path),
+        [] => Repl::start(),
+    }
 }
 
 struct Repl {}
 
 impl Repl {
+    fn run_file(path: &str) {
+        let mut interpreter = Interpreter::new();
+        let result = interpreter.run_file(path);
+        Repl::print_result(result);
+    }
+
+    /// `debug file`: runs the file under the debugger, stopping before its first statement.
+    fn debug(path: &str) {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                println!("{}", format!("{path}: {e}").red());
+                std::process::exit(1);
+            }
+        };
+        println!("Debugging {path}, h for help");
+        let mut interpreter = Interpreter::new();
+        interpreter.set_debugger(ReplDebugger::new(&source));
+        Repl::print_result(interpreter.run_file(path));
+    }
+
+    /// `fmt [--write] [files...]`: prints each file (or stdin) in canonical form, or rewrites
+    /// the files in place with `--write`.
+    fn fmt(args: &[String]) {
+        let write = args.iter().any(|a| a == "--write");
+        let paths: Vec<&String> = args.iter().filter(|a| *a != "--write").collect();
+        let mut failed = false;
+        if paths.is_empty() {
+            let mut source = String::new();
+            std::io::stdin().read_to_string(&mut source).unwrap();
+            failed |= !Repl::fmt_source("<stdin>", &source, None);
+        }
+        for path in paths {
+            match std::fs::read_to_string(path) {
+                Ok(source) => failed |= !Repl::fmt_source(path, &source, write.then_some(path)),
+                Err(e) => {
+                    println!("{}", format!("{path}: {e}").red());
+                    failed = true;
+                }
+            }
+        }
+        if failed {
+            std::process::exit(1);
+        }
+    }
+
+    /// `lint [--json] files...` and `check [--json] files...`: reports the problems `find`
+    /// finds in each file, as text or as a JSON array of `{file, line, severity, code, message}`
+    /// objects.
+    fn diagnose(args: &[String], find: fn(&str) -> Vec<Diagnostic>) {
+        let json = args.iter().any(|a| a == "--json");
+        let mut found = Vec::new();
+        for path in args.iter().filter(|a| *a != "--json") {
+            match std::fs::read_to_string(path) {
+                Ok(source) => found.extend(
+                    find(&source)
+                        .into_iter()
+                        .map(|diagnostic| (path, diagnostic)),
+                ),
+                Err(e) => {
+                    println!("{}", format!("{path}: {e}").red());
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        if json {
+            let diagnostics: Vec<_> = found
+                .iter()
+                .map(|(path, d)| {
+                    serde_json::json!({
+                        "file": path,
+                        "line": d.line,
+                        "severity": match d.lint.severity() {
+                            Severity::Warning => "warning",
+                            Severity::Error => "error",
+                        },
+                        "code": d.lint.code(),
+                        "message": d.lint.to_string(),
+                    })
+                })
+                .collect();
+            println!("{}", serde_json::Value::Array(diagnostics));
+        } else {
+            for (path, d) in &found {
+                let line = format!("{path}:{d}");
+                match d.lint.severity() {
+                    Severity::Warning => println!("{}", line.yellow()),
+                    Severity::Error => println!("{}", line.red()),
+                }
+            }
+        }
+        if found
+            .iter()
+            .any(|(_, d)| d.lint.severity() == Severity::Error)
+        {
+            std::process::exit(1);
+        }
+    }
+
+    fn fmt_source(name: &str, source: &str, write_to: Option<&String>) -> bool {
+        match printer::format(source) {
+            Ok(formatted) => match write_to {
+                Some(path) => match std::fs::write(path, formatted) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        println!("{}", format!("{path}: {e}").red());
+                        false
+                    }
+                },
+                None => {
+                    print!("{formatted}");
+                    true
+                }
+            },
+            Err(errors) => {
+                println!("Ruh Roh, {name} has errors and can't be formatted");
+                for e in errors {
+                    println!("{}", e);
+                }
+                false
+            }
+        }
+    }
+
     fn start() {
         // let parser = Parser {};
         println!(
@@ -21,25 +159,41 @@ impl Repl {
         let prompt = ">>>".green();
 
         println!("Feel free to type in commands");
-        let env = Rc::new(RefCell::new(Environment::new_with_builtin()));
+        let mut interpreter = Interpreter::new();
         loop {
             let mut line = String::new();
             print!("{prompt}");
             let _ = std::io::stdout().flush();
-            std::io::stdin().read_line(&mut line).unwrap();
-            let mut parser = Parser::new(Lexer::new(&line));
-            let program = parser.parse_program();
-            if parser.parse_errors.is_empty() {
-                match eval(program, &env).unwrap().as_ref() {
-                    Object::Null => (),
-                    out => println!("{out}"),
-                };
-            } else {
+            if std::io::stdin().read_line(&mut line).unwrap() == 0 {
+                break;
+            }
+            Repl::print_result(interpreter.run(&line));
+        }
+    }
+
+    fn print_result(result: Result<Rc<Object>, InterpreterError>) {
+        match result {
+            Ok(out) => match out.as_ref() {
+                Object::Null => (),
+                out => println!("{out}"),
+            },
+            Err(InterpreterError::Parse(errors)) => {
                 println!("Ruh Roh, looks like we ran into some errors while parsing");
-                for e in parser.parse_errors {
+                for e in errors {
                     println!("{}", e);
                 }
             }
+            Err(InterpreterError::Check(diagnostics)) => {
+                for d in diagnostics {
+                    println!("{}", d.to_string().red());
+                }
+            }
+            Err(InterpreterError::Eval(e, backtrace)) => {
+                println!("{}", e.to_string().red());
+                for call in backtrace.to_string().lines() {
+                    println!("    {call}");
+                }
+            }
         }
     }
 }